@@ -5,6 +5,7 @@
 #include <iox2/service_type.hpp>
 #include <iox2/service_name.hpp>
 #include <iox2/publisher.hpp>
+#include <iox2/subscriber.hpp>
 #include <iostream>
 #include <optional>
 
@@ -19,6 +20,11 @@ constexpr auto SERVICE_NAME_ABS_LINE_GRADIENT = "nsm/abs_line_gradient";
 constexpr auto SERVICE_NAME_CORNER_DETECTED = "nsm/corner_detected";
 constexpr auto SERVICE_NAME_CORNER_DIRECTION = "nsm/corner_direction";
 constexpr auto SERVICE_NAME_CORNER_POINT = "nsm/corner_point";
+constexpr auto SERVICE_NAME_PAN_ANGLE = "nsm/pan_angle";
+
+// Horizontal field of view of the camera. A pan of hfov/2 moves the scene by half a frame, which is
+// 1.0 of heading error. Only approximate with the birds-eye warp on.
+constexpr float CAMERA_HFOV_DEG = 62.2f;
 
 std::optional<Node<ServiceType::Ipc>> g_node;
 std::optional<Publisher<ServiceType::Ipc, HeadingErrorMsg, void>> g_pub_heading_error;
@@ -26,6 +32,8 @@ std::optional<Publisher<ServiceType::Ipc, AbsLineGradientMsg, void>> g_pub_abs_l
 std::optional<Publisher<ServiceType::Ipc, CornerDetectedMsg, void>> g_pub_corner_detected;
 std::optional<Publisher<ServiceType::Ipc, CornerDirectionMsg, void>> g_pub_corner_direction;
 std::optional<Publisher<ServiceType::Ipc, CornerPointMsg, void>> g_pub_corner_point;
+std::optional<Subscriber<ServiceType::Ipc, PanAngleMsg, void>> g_sub_pan_angle;
+std::optional<float> g_pan_angle_deg;
 
 bool g_initialized = false;
 
@@ -153,6 +161,28 @@ bool init_publishers() {
         g_pub_corner_point.emplace(std::move(pub.value()));
     }
 
+    // Pan angle subscriber
+    {
+        auto service_name = ServiceName::create(SERVICE_NAME_PAN_ANGLE);
+        if (!service_name.has_value()) {
+            std::cerr << "Failed to create service name" << std::endl;
+            return false;
+        }
+        auto service = g_node->service_builder(service_name.value())
+            .publish_subscribe<PanAngleMsg>()
+            .open_or_create();
+        if (!service.has_value()) {
+            std::cerr << "Failed to create pan_angle service" << std::endl;
+            return false;
+        }
+        auto sub = service.value().subscriber_builder().create();
+        if (!sub.has_value()) {
+            std::cerr << "Failed to create pan_angle subscriber" << std::endl;
+            return false;
+        }
+        g_sub_pan_angle.emplace(std::move(sub.value()));
+    }
+
     g_initialized = true;
     std::cout << "iceoryx2 publishers initialized" << std::endl;
     return true;
@@ -164,14 +194,43 @@ void shutdown_publishers() {
     g_pub_corner_detected.reset();
     g_pub_corner_direction.reset();
     g_pub_corner_point.reset();
+    g_sub_pan_angle.reset();
+    g_pan_angle_deg = std::nullopt;
     g_node.reset();
     g_initialized = false;
 }
 
+// Drains the pan angle subscriber, keeps the newest sample
+static void receive_pan_angle() {
+    if (!g_sub_pan_angle.has_value()) {
+        return;
+    }
+
+    while (true) {
+        auto receive_result = g_sub_pan_angle->receive();
+        if (!receive_result.has_value()) {
+            std::cerr << "Failed to receive pan_angle" << std::endl;
+            return;
+        }
+        auto& sample_opt = receive_result.value();
+        if (!sample_opt.has_value()) {
+            return;
+        }
+        g_pan_angle_deg = sample_opt.value().payload().angle_deg;
+    }
+}
+
 void process(const FrameResult& frame_result, int frame_width, int frame_height, BridgeResult& out) {
     out.reset();
+    receive_pan_angle();
+    out.pan_angle_deg = g_pan_angle_deg;
 
     out.heading_error = calculate_heading_error(frame_result.center_line, frame_width);
+    // panning left moves the lane right in the frame, take that back out so heading error stays
+    // relative to the robot and not the camera
+    if (out.heading_error.has_value() && out.pan_angle_deg.has_value()) {
+        *out.heading_error -= *out.pan_angle_deg / (CAMERA_HFOV_DEG / 2.0f);
+    }
     out.abs_line_gradient = calculate_abs_line_gradient(frame_result.center_line);
     out.corner_detected = frame_result.corner.detected;
     out.corner_direction = frame_result.corner.horizontal_direction;
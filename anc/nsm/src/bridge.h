@@ -14,6 +14,8 @@ struct BridgeResult {
     bool corner_detected = false;
     cv::Point2f corner_direction;
     cv::Point2f corner_point;
+    // latest camera pan angle from copper, positive is left. Kept across frames.
+    std::optional<float> pan_angle_deg;
 
     void reset() {
         heading_error = std::nullopt;
@@ -54,7 +56,14 @@ struct CornerPointMsg {
     float y;
 };
 
-// Initialize iceoryx2 publishers. Call once at startup.
+// copper -> NSM
+struct PanAngleMsg {
+    static constexpr const char* IOX2_TYPE_NAME = "PanAngleMsg";
+    float angle_deg;
+    uint8_t settled;
+};
+
+// Initialize iceoryx2 publishers and the pan angle subscriber. Call once at startup.
 bool init_publishers();
 
 // Cleanup iceoryx2 publishers. Call at shutdown.
@@ -13,7 +13,11 @@ use cu_irencoder::IrEncoderPayload;
 pub struct AncPubPayload {
     pub e_stop_trig_fdbk: bool,
     pub loop_mode_fdbk: LoopState,
-    pub distance: Option<f64>
+    pub distance: Option<f64>,
+    pub speed_cap: f32,
+    pub bstn_e_stop_reason: u8,
+    pub itp_e_stop_reason: u8,
+    pub obstacle_policy_state: u8,
 }
 
 #[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Encode, Decode)]
@@ -28,6 +32,26 @@ pub struct Distance(pub f64);
 #[derive(Reflect)]
 pub struct LmtrSpeed(pub f32);
 
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Encode, Decode)]
+#[derive(Reflect)]
+pub struct SpeedCap(pub f32);
+
+/// 0 - Idle, 1 - Waiting, 2 - Bypassing, 3 - Reacquiring, 4 - Gave up
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Encode, Decode)]
+#[derive(Reflect)]
+pub struct ObstaclePolicyStatus(pub u8);
+
+/// Echo of every e-stop source. Reason codes are the ones received on bstn/estop and itp/estop,
+/// 0 when that source is clear.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, Encode, Decode)]
+#[derive(Reflect)]
+#[reflect(no_field_bounds, from_reflect = false)]
+pub struct EStopStatus {
+    pub obstacle_latched: bool,
+    pub bstn_reason: u8,
+    pub itp_reason: u8,
+}
+
 #[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Encode, Decode)]
 #[derive(Reflect)]
 pub struct RmtrSpeed(pub f32);
@@ -43,7 +67,7 @@ impl CuTask for AncPub {
     // u8 - anc_obstacle
     // f64 - anc_distance
 
-    type Output<'m> = output_msg!(ObstacleDetected, Distance, LmtrSpeed, RmtrSpeed);
+    type Output<'m> = output_msg!(ObstacleDetected, Distance, LmtrSpeed, RmtrSpeed, SpeedCap, EStopStatus, ObstaclePolicyStatus);
     type Resources<'r> = ();
 
     fn new(_config: Option<&ComponentConfig>, _resources: Self::Resources<'_>) -> CuResult<Self>
@@ -65,6 +89,13 @@ impl CuTask for AncPub {
             if let Some(d) = anc_pub.distance {
                 output.1.set_payload(Distance(d));
             }
+            output.4.set_payload(SpeedCap(anc_pub.speed_cap));
+            output.5.set_payload(EStopStatus {
+                obstacle_latched: anc_pub.e_stop_trig_fdbk,
+                bstn_reason: anc_pub.bstn_e_stop_reason,
+                itp_reason: anc_pub.itp_e_stop_reason,
+            });
+            output.6.set_payload(ObstaclePolicyStatus(anc_pub.obstacle_policy_state));
         }
 
         if let Some(rpm) = input.1.payload() {
@@ -0,0 +1,167 @@
+use cu29::prelude::*;
+use cu_propulsion::PropulsionPayload;
+use battery_model::{BatteryLevel, BatteryPayload};
+
+pub const DEFAULT_LOW_BATTERY_SPEED_CAP: f32 = 0.5;
+pub const DEFAULT_CRITICAL_STOP_RAMP_MS: u64 = 1500;
+
+/// Warning: every wheel capped at warning_speed_cap. Critical: the cap ramps from warning_speed_cap
+/// down to 0 over stop_ramp_ms and the robot stays stopped until the battery is back to Ok (charged or swapped).
+/// An unavailable battery estimate leaves the speed alone.
+#[derive(Debug)]
+pub struct LowBatteryPolicy {
+    pub warning_speed_cap: f32,
+    pub stop_ramp_ms: u64,
+    /// Some while stopping or stopped for a critical battery
+    critical_since: Option<CuTime>,
+}
+
+impl Default for LowBatteryPolicy {
+    fn default() -> Self {
+        Self {
+            warning_speed_cap: DEFAULT_LOW_BATTERY_SPEED_CAP,
+            stop_ramp_ms: DEFAULT_CRITICAL_STOP_RAMP_MS,
+            critical_since: None,
+        }
+    }
+}
+
+impl LowBatteryPolicy {
+    /// True once the ramp has reached zero
+    pub fn stopped(&self, now: CuTime) -> bool {
+        self.critical_since.is_some_and(|since| self.ramp(now, since) <= 0.0)
+    }
+
+    fn ramp(&self, now: CuTime, since: CuTime) -> f32 {
+        if self.stop_ramp_ms == 0 {
+            return 0.0;
+        }
+        let elapsed_ms = now.as_nanos().saturating_sub(since.as_nanos()) / 1_000_000;
+        self.warning_speed_cap * (1.0 - elapsed_ms as f32 / self.stop_ramp_ms as f32).max(0.0)
+    }
+
+    /// Speed cap for this cycle, 1.0 - uncapped. `now` is the robot clock.
+    pub fn update(&mut self, now: CuTime, battery: Option<&BatteryPayload>) -> f32 {
+        let level = battery.filter(|b| b.available()).map(|b| b.level);
+
+        match (level, self.critical_since) {
+            (Some(BatteryLevel::Critical), None) => {
+                eprintln!("BATTERY: critical, stopping over {}ms", self.stop_ramp_ms);
+                self.critical_since = Some(now);
+            },
+            (Some(BatteryLevel::Ok), Some(_)) => {
+                eprintln!("BATTERY: back to ok, critical stop released");
+                self.critical_since = None;
+            },
+            _ => (),
+        }
+
+        if let Some(since) = self.critical_since {
+            return self.ramp(now, since);
+        }
+        match level {
+            Some(BatteryLevel::Warning) => self.warning_speed_cap,
+            _ => 1.0,
+        }
+    }
+}
+
+/// Unlike the time-to-collision cap this one covers reverse and pivots too
+pub fn apply_battery_cap(payload: &mut PropulsionPayload, cap: f32) {
+    let fastest = payload.left_speed.abs().max(payload.right_speed.abs());
+    if fastest > cap && fastest > 0.0 {
+        let scale = cap / fastest;
+        payload.left_speed *= scale;
+        payload.right_speed *= scale;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use cu_health::Availability;
+
+    fn battery(level: BatteryLevel) -> BatteryPayload {
+        BatteryPayload { level, ..Default::default() }
+    }
+
+    #[test]
+    fn warning_caps_the_speed() {
+        let (clock, mock) = RobotClock::mock();
+        let mut p = LowBatteryPolicy::default();
+
+        assert_eq!(p.update(clock.now(), Some(&battery(BatteryLevel::Ok))), 1.0);
+        for _ in 0..100 {
+            assert_eq!(p.update(clock.now(), Some(&battery(BatteryLevel::Warning))), p.warning_speed_cap);
+            assert!(!p.stopped(clock.now()));
+            mock.increment(Duration::from_millis(18));
+        }
+
+        let mut payload = PropulsionPayload { left_speed: 0.8, right_speed: 0.4, ..Default::default() };
+        apply_battery_cap(&mut payload, p.warning_speed_cap);
+        assert!((payload.left_speed - 0.5).abs() < 1e-6 && (payload.right_speed - 0.25).abs() < 1e-6);
+
+        // already slower than the cap, untouched
+        let mut payload = PropulsionPayload { left_speed: 0.3, right_speed: 0.2, ..Default::default() };
+        apply_battery_cap(&mut payload, p.warning_speed_cap);
+        assert_eq!((payload.left_speed, payload.right_speed), (0.3, 0.2));
+    }
+
+    #[test]
+    fn critical_ramps_to_a_stop() {
+        let (clock, mock) = RobotClock::mock();
+        let mut p = LowBatteryPolicy::default();
+        let critical = battery(BatteryLevel::Critical);
+
+        assert_eq!(p.update(clock.now(), Some(&critical)), p.warning_speed_cap);
+
+        mock.increment(Duration::from_millis(p.stop_ramp_ms / 2));
+        assert!((p.update(clock.now(), Some(&critical)) - p.warning_speed_cap / 2.0).abs() < 1e-6);
+        assert!(!p.stopped(clock.now()));
+
+        mock.increment(Duration::from_millis(p.stop_ramp_ms / 2));
+        assert_eq!(p.update(clock.now(), Some(&critical)), 0.0);
+        assert!(p.stopped(clock.now()));
+
+        // no ramp at all
+        let mut p = LowBatteryPolicy { stop_ramp_ms: 0, ..Default::default() };
+        assert_eq!(p.update(clock.now(), Some(&critical)), 0.0);
+        assert!(p.stopped(clock.now()));
+    }
+
+    #[test]
+    fn stays_stopped_until_ok() {
+        let (clock, mock) = RobotClock::mock();
+        let mut p = LowBatteryPolicy::default();
+
+        p.update(clock.now(), Some(&battery(BatteryLevel::Critical)));
+        mock.increment(Duration::from_millis(p.stop_ramp_ms));
+        assert!(p.stopped(clock.now()));
+
+        // the voltage recovering a bit under no load isn't enough, nor is losing the estimate
+        let unavailable = BatteryPayload { availability: Availability::Unavailable, ..battery(BatteryLevel::Ok) };
+        for b in [Some(battery(BatteryLevel::Warning)), Some(unavailable), None, Some(battery(BatteryLevel::Critical))] {
+            mock.increment(Duration::from_secs(10));
+            assert_eq!(p.update(clock.now(), b.as_ref()), 0.0);
+            assert!(p.stopped(clock.now()));
+        }
+
+        assert_eq!(p.update(clock.now(), Some(&battery(BatteryLevel::Ok))), 1.0);
+        assert!(!p.stopped(clock.now()));
+
+        // a new critical starts a new ramp
+        assert_eq!(p.update(clock.now(), Some(&battery(BatteryLevel::Critical))), p.warning_speed_cap);
+    }
+
+    #[test]
+    fn no_cap_without_an_estimate() {
+        let (clock, _) = RobotClock::mock();
+        let mut p = LowBatteryPolicy::default();
+
+        let unavailable = BatteryPayload { availability: Availability::Unavailable, ..battery(BatteryLevel::Critical) };
+        assert_eq!(p.update(clock.now(), Some(&unavailable)), 1.0);
+        assert_eq!(p.update(clock.now(), None), 1.0);
+        assert!(!p.stopped(clock.now()));
+    }
+}
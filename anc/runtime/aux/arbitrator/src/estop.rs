@@ -0,0 +1,284 @@
+extern crate cu_bincode as bincode;
+use cu29::prelude::*;
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+/// Auto-resume only: distance that has to be seen before the hold timer starts. Keep it above
+/// e_stop_threshold_cm of the propulsion adapter, the gap is the hysteresis.
+pub const DEFAULT_ESTOP_RESUME_DISTANCE_CM: f64 = 30.0;
+pub const DEFAULT_ESTOP_RESUME_HOLD_MS: u64 = 1000;
+
+/// IMPORTANT: #[serde(transparent)] so that cu-zenoh-src decodes the raw u8 from the wire, same as zsrc-merger
+/// 1 - reset, 0 - idle. Only the rising edge resets.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Encode, Decode)]
+#[serde(transparent)]
+#[derive(Reflect)]
+pub struct BstnEStopReset(pub u8);
+
+/// Remote software e-stops, consumed directly by the arbitrator rather than through zsrc-merger.
+/// IMPORTANT: #[serde(transparent)], raw u8 on the wire.
+/// 0 - clear, anything else - stop, the value is the reason code (defined by the publisher) and is
+/// echoed back on palanuk/anc/estop
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Encode, Decode)]
+#[serde(transparent)]
+#[derive(Reflect)]
+pub struct BstnEStop(pub u8);
+
+/// Same as BstnEStop, from the vision service
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Encode, Decode)]
+#[serde(transparent)]
+#[derive(Reflect)]
+pub struct ItpEStop(pub u8);
+
+/// Holds the last reason code from one remote e-stop topic. A stop stays in force until an explicit
+/// 0 arrives, a stale or silent topic keeps the last value.
+#[derive(Debug, Default)]
+pub struct RemoteEStop {
+    pub reason: u8,
+}
+
+impl RemoteEStop {
+    pub fn update(&mut self, source: &str, msg: Option<u8>) -> bool {
+        if let Some(reason) = msg && reason != self.reason {
+            if reason == 0 {
+                eprintln!("ESTOP: {source} cleared (was reason {})", self.reason);
+            } else {
+                eprintln!("ESTOP: {source} stop, reason {reason}");
+            }
+            self.reason = reason;
+        }
+        self.active()
+    }
+
+    pub fn active(&self) -> bool {
+        self.reason != 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EStopMode {
+    /// Stays stopped until palanuk/bstn/estop_reset, and only if the trigger has cleared
+    #[default]
+    Latch,
+    /// Resumes on its own once the obstacle is past the resume distance for the hold time
+    AutoResume,
+}
+
+impl EStopMode {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "latch" => Ok(EStopMode::Latch),
+            "auto_resume" => Ok(EStopMode::AutoResume),
+            _ => Err(format!("Invalid e_stop_mode value: \"{s}\". Valid values: \"latch\", \"auto_resume\"")),
+        }
+    }
+}
+
+/// Holds the timers and edge detection, the latched flag itself lives in the arbitrator
+/// (e_stop_trig_fdbk) so it goes through Freezable.
+#[derive(Debug)]
+pub struct EStopLatch {
+    pub mode: EStopMode,
+    pub resume_distance_cm: f64,
+    pub resume_hold_ms: u64,
+    clear_since: Option<CuTime>,
+    last_reset: bool,
+}
+
+impl Default for EStopLatch {
+    fn default() -> Self {
+        Self {
+            mode: EStopMode::default(),
+            resume_distance_cm: DEFAULT_ESTOP_RESUME_DISTANCE_CM,
+            resume_hold_ms: DEFAULT_ESTOP_RESUME_HOLD_MS,
+            clear_since: None,
+            last_reset: false,
+        }
+    }
+}
+
+impl EStopLatch {
+    /// Returns the new latched state.
+    /// `triggered` is the confirmed e-stop condition from the propulsion adapter this cycle.
+    /// No distance reading never counts as clear. `now` is the robot clock, the auto-resume hold runs on it.
+    pub fn update(&mut self, now: CuTime, latched: bool, triggered: bool, distance_cm: Option<f64>, reset_cmd: bool) -> bool {
+        let reset_edge = reset_cmd && !self.last_reset;
+        self.last_reset = reset_cmd;
+
+        if triggered {
+            self.clear_since = None;
+            if !latched {
+                eprintln!("ESTOP: latched ({:?})", self.mode);
+            }
+            return true;
+        }
+
+        if !latched {
+            return false;
+        }
+
+        match self.mode {
+            EStopMode::Latch => {
+                if reset_edge {
+                    eprintln!("ESTOP: reset by base station");
+                    return false;
+                }
+                true
+            },
+            EStopMode::AutoResume => {
+                let clear = distance_cm.is_some_and(|d| d >= self.resume_distance_cm);
+                if !clear {
+                    self.clear_since = None;
+                    return true;
+                }
+
+                let since = *self.clear_since.get_or_insert(now);
+                let elapsed_ns = now.as_nanos()
+                    .checked_sub(since.as_nanos())
+                    .unwrap_or(0);
+                if CuDuration::from_nanos(elapsed_ns) >= CuDuration::from_millis(self.resume_hold_ms) {
+                    self.clear_since = None;
+                    eprintln!("ESTOP: auto-resumed after {}ms clear", self.resume_hold_ms);
+                    return false;
+                }
+                true
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// The arbitrator feeds the latched state back in every cycle
+    struct Fed {
+        latch: EStopLatch,
+        latched: bool,
+    }
+
+    impl Fed {
+        fn new(latch: EStopLatch) -> Self {
+            Self { latch, latched: false }
+        }
+
+        fn update(&mut self, now: CuTime, triggered: bool, distance_cm: Option<f64>, reset_cmd: bool) -> bool {
+            self.latched = self.latch.update(now, self.latched, triggered, distance_cm, reset_cmd);
+            self.latched
+        }
+    }
+
+    #[test]
+    fn latch_holds_until_a_reset_edge() {
+        let (clock, mock) = RobotClock::mock();
+        let cycle = Duration::from_millis(10);
+        let mut latch = Fed::new(EStopLatch::default());
+
+        assert!(!latch.update(clock.now(), false, Some(100.0), false));
+        mock.increment(cycle);
+        assert!(!latch.update(clock.now(), false, Some(5.0), false));
+        mock.increment(cycle);
+        assert!(latch.update(clock.now(), true, Some(5.0), false));
+
+        // clear for a long while, no reset: stays latched
+        assert!(latch.update(clock.now(), true, Some(5.0), false));
+        mock.increment(Duration::from_secs(10));
+        assert!(latch.update(clock.now(), false, Some(100.0), false));
+
+        // a reset while the obstacle is still triggering does nothing, and holding it isn't an edge
+        mock.increment(cycle);
+        assert!(latch.update(clock.now(), true, Some(5.0), true));
+        mock.increment(cycle);
+        assert!(latch.update(clock.now(), false, Some(100.0), true));
+
+        // a fresh rising edge once clear releases it
+        mock.increment(cycle);
+        assert!(latch.update(clock.now(), false, Some(100.0), false));
+        mock.increment(cycle);
+        assert!(!latch.update(clock.now(), false, Some(100.0), true));
+        mock.increment(cycle);
+        assert!(!latch.update(clock.now(), false, Some(100.0), true));
+    }
+
+    #[test]
+    fn auto_resume_needs_the_whole_hold_clear() {
+        let (clock, mock) = RobotClock::mock();
+        let mut latch = Fed::new(EStopLatch { mode: EStopMode::AutoResume, ..Default::default() });
+        let hold = Duration::from_millis(latch.latch.resume_hold_ms);
+        let ms = Duration::from_millis(1);
+        let clear = Some(latch.latch.resume_distance_cm);
+        let close = Some(latch.latch.resume_distance_cm - 1.0);
+
+        assert!(latch.update(clock.now(), true, Some(5.0), false));
+
+        // between the e-stop threshold and the resume distance isn't clear
+        mock.increment(Duration::from_millis(100));
+        assert!(latch.update(clock.now(), false, close, false));
+        mock.increment(2 * hold);
+        assert!(latch.update(clock.now(), false, close, false));
+
+        // clear from here, a dip restarts the hold
+        mock.increment(Duration::from_secs(1));
+        assert!(latch.update(clock.now(), false, clear, false));
+        mock.increment(hold - ms);
+        assert!(latch.update(clock.now(), false, clear, false));
+        assert!(latch.update(clock.now(), false, close, false));
+        mock.increment(ms);
+        assert!(latch.update(clock.now(), false, clear, false));
+
+        // no reading doesn't count as clear either
+        mock.increment(10 * ms);
+        assert!(latch.update(clock.now(), false, None, false));
+
+        mock.increment(10 * ms);
+        assert!(latch.update(clock.now(), false, clear, false));
+        mock.increment(hold - ms);
+        assert!(latch.update(clock.now(), false, clear, false));
+        mock.increment(ms);
+        assert!(!latch.update(clock.now(), false, clear, false));
+        // stays released
+        mock.increment(10 * ms);
+        assert!(!latch.update(clock.now(), false, close, false));
+    }
+
+    #[test]
+    fn auto_resume_ignores_the_base_station_reset() {
+        let (clock, mock) = RobotClock::mock();
+        let mut latch = Fed::new(EStopLatch { mode: EStopMode::AutoResume, ..Default::default() });
+        assert!(latch.update(clock.now(), true, Some(5.0), false));
+        mock.increment(Duration::from_millis(10));
+        assert!(latch.update(clock.now(), false, Some(5.0), true));
+    }
+
+    #[test]
+    fn retrigger_during_the_hold_restarts_it() {
+        let (clock, mock) = RobotClock::mock();
+        let mut latch = Fed::new(EStopLatch { mode: EStopMode::AutoResume, ..Default::default() });
+        let hold = Duration::from_millis(latch.latch.resume_hold_ms);
+        let clear = Some(latch.latch.resume_distance_cm + 10.0);
+
+        assert!(latch.update(clock.now(), true, Some(5.0), false));
+        mock.increment(Duration::from_millis(100));
+        assert!(latch.update(clock.now(), false, clear, false));
+        mock.increment(hold / 2);
+        assert!(latch.update(clock.now(), true, clear, false));
+        mock.increment(hold / 2);
+        assert!(latch.update(clock.now(), false, clear, false));
+        mock.increment(hold - Duration::from_millis(1));
+        assert!(latch.update(clock.now(), false, clear, false));
+        mock.increment(Duration::from_millis(1));
+        assert!(!latch.update(clock.now(), false, clear, false));
+    }
+
+    #[test]
+    fn remote_e_stop_holds_until_an_explicit_clear() {
+        let mut remote = RemoteEStop::default();
+        assert!(!remote.update("bstn", None));
+        assert!(remote.update("bstn", Some(3)));
+        // silent topic keeps the stop
+        assert!(remote.update("bstn", None));
+        assert_eq!(remote.reason, 3);
+        assert!(!remote.update("bstn", Some(0)));
+    }
+}
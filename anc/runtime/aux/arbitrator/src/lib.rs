@@ -2,10 +2,17 @@
 /// This task consolidates the arbitration of permissives/interlocks from different inputs
 /// This is where it decides that an e-stop condition is correct, and also ultimately decides
 /// if the loop mode can change. This task is stateful; it has feedback values for e-stop trigger
-/// and loop mode.
+/// and loop mode. The e-stop latches (see estop.rs), e_stop_trig_fdbk is the latched state.
 
 extern crate cu_bincode as bincode;
 
+mod estop;
+mod obstacle;
+mod battery;
+pub use estop::*;
+pub use obstacle::*;
+pub use battery::*;
+
 use cu_pid::PIDControlOutputPayload;
 use cu29::prelude::*;
 use bincode::{Decode, Encode};
@@ -16,7 +23,10 @@ use opencv_splitter::NsmPayload;
 use opencv_iox2::{CornerDirection};
 use cu_irencoder::IrEncoderPayload;
 use itp_merger::ItpTopicsOutputPayload;
+use speed_ctrlrs::WheelVelocitySetpointPayload;
+use battery_model::BatteryPayload;
 use core::default::*;
+use serde::{Deserialize, Serialize};
 
 pub const R_WIND_COMP_LMTR: f32 = 1.0; // 1.17
 pub const R_WIND_COMP_RMTR: f32 = 1.0; // 0.85
@@ -51,7 +61,18 @@ pub const DEFAULT_MAX_RPM: f32 = 600.0;
 #[derive(Reflect)]
 #[reflect(no_field_bounds, from_reflect = false)]
 pub struct Arbitrator {
+    /// latched e-stop, persisted through Freezable
     e_stop_trig_fdbk: bool,
+    #[reflect(ignore)]
+    e_stop_latch: EStopLatch,
+    #[reflect(ignore)]
+    bstn_e_stop: RemoteEStop,
+    #[reflect(ignore)]
+    itp_e_stop: RemoteEStop,
+    #[reflect(ignore)]
+    obstacle: ObstacleHandler,
+    #[reflect(ignore)]
+    low_battery: LowBatteryPolicy,
     target_speed: Option<f32>,
     /// Applied to left motor
     r_wind_comp_lmtr: f32,
@@ -211,6 +232,26 @@ pub enum AlignmentState {
     Aligned,
 }
 
+/// Corner maneuver phase as seen from outside the arbitrator, drives the look-into-the-corner panning
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+#[derive(Reflect)]
+pub enum ManeuverPhase {
+    #[default]
+    None,
+    CornerPending,
+    Cornering,
+    Cooldown,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Encode, Decode, Serialize, Deserialize)]
+#[derive(Reflect)]
+#[reflect(no_field_bounds, from_reflect = false)]
+pub struct ManeuverPayload {
+    pub phase: ManeuverPhase,
+    /// Only meaningful when phase isn't None
+    pub direction: CornerDirection,
+}
+
 #[derive(Default, Debug, PartialEq, Eq)]
 pub enum SteererState {
     WaitingToSteer,
@@ -225,6 +266,11 @@ impl Default for Arbitrator {
     fn default() -> Self {
         Self {
             e_stop_trig_fdbk: false,
+            e_stop_latch: EStopLatch::default(),
+            bstn_e_stop: RemoteEStop::default(),
+            itp_e_stop: RemoteEStop::default(),
+            obstacle: ObstacleHandler::default(),
+            low_battery: LowBatteryPolicy::default(),
             target_speed: None,
             r_wind_comp_lmtr: 0.0,
             r_wind_comp_rmtr: 0.0,
@@ -280,8 +326,8 @@ impl Freezable for Arbitrator {
 }
 
 impl CuTask for Arbitrator {
-    type Input<'m> = input_msg!('m, PropulsionAdapterOutputPayload, PIDControlOutputPayload, NsmPayload, IrEncoderPayload, ItpTopicsOutputPayload);
-    type Output<'m> = output_msg!(PropulsionPayload, AncPubPayload);
+    type Input<'m> = input_msg!('m, PropulsionAdapterOutputPayload, PIDControlOutputPayload, NsmPayload, IrEncoderPayload, ItpTopicsOutputPayload, BstnEStopReset, BstnEStop, ItpEStop, BatteryPayload);
+    type Output<'m> = output_msg!(PropulsionPayload, AncPubPayload, WheelVelocitySetpointPayload, ManeuverPayload);
     type Resources<'r> = ();
 
     fn new(config: Option<&ComponentConfig>, _resources: Self::Resources<'_>) -> CuResult<Self>
@@ -399,6 +445,88 @@ impl CuTask for Arbitrator {
             .map(|v| { let f: f64 = v.clone().into(); f as u64 })
             .unwrap_or(DEFAULT_ACCELERATE_DURATION_MS);
 
+        let e_stop_mode = match kv.get("e_stop_mode") {
+            Some(v) => {
+                let s: String = v.clone().into();
+                EStopMode::parse(&s).map_err(|e| CuError::from(e.as_str()))?
+            },
+            None => EStopMode::default(),
+        };
+
+        let e_stop_resume_distance_cm: f64 = kv.get("e_stop_resume_distance_cm")
+            .map(|v| v.clone().into())
+            .unwrap_or(DEFAULT_ESTOP_RESUME_DISTANCE_CM);
+
+        let e_stop_resume_hold_ms: u64 = kv.get("e_stop_resume_hold_ms")
+            .map(|v| { let f: f64 = v.clone().into(); f as u64 })
+            .unwrap_or(DEFAULT_ESTOP_RESUME_HOLD_MS);
+
+        let obstacle_policy = match kv.get("obstacle_policy") {
+            Some(v) => {
+                let s: String = v.clone().into();
+                ObstaclePolicy::parse(&s).map_err(|e| CuError::from(e.as_str()))?
+            },
+            None => ObstaclePolicy::default(),
+        };
+
+        // wait and bypass release the e-stop themselves, a latch that only bstn/estop_reset may clear
+        // can't hold under them
+        if obstacle_policy != ObstaclePolicy::Stop && e_stop_mode == EStopMode::Latch {
+            return Err(CuError::from("Arbitrator: obstacle_policy \"wait\" and \"bypass\" resume on their own, they need e_stop_mode \"auto_resume\""));
+        }
+
+        let bypass_left = match kv.get("bypass_side") {
+            Some(v) => {
+                let s: String = v.clone().into();
+                match s.as_str() {
+                    "left" => true,
+                    "right" => false,
+                    _ => return Err(CuError::from(format!("Invalid bypass_side value: \"{s}\". Valid values: \"left\", \"right\""))),
+                }
+            },
+            None => true,
+        };
+
+        let obstacle_wait_ms: u64 = kv.get("obstacle_wait_ms")
+            .map(|v| { let f: f64 = v.clone().into(); f as u64 })
+            .unwrap_or(DEFAULT_OBSTACLE_WAIT_MS);
+
+        let bypass_angle_degrees: f32 = kv.get("bypass_angle_degrees")
+            .map(|v| { let f: f64 = v.clone().into(); f as f32 })
+            .unwrap_or(DEFAULT_BYPASS_ANGLE_DEGREES);
+
+        let bypass_offset_cm: f32 = kv.get("bypass_offset_cm")
+            .map(|v| { let f: f64 = v.clone().into(); f as f32 })
+            .unwrap_or(DEFAULT_BYPASS_OFFSET_CM);
+
+        let bypass_pass_cm: f32 = kv.get("bypass_pass_cm")
+            .map(|v| { let f: f64 = v.clone().into(); f as f32 })
+            .unwrap_or(DEFAULT_BYPASS_PASS_CM);
+
+        let bypass_speed: f32 = kv.get("bypass_speed")
+            .map(|v| { let f: f64 = v.clone().into(); f as f32 })
+            .unwrap_or(DEFAULT_BYPASS_SPEED);
+
+        let bypass_pivot_speed: f32 = kv.get("bypass_pivot_speed")
+            .map(|v| { let f: f64 = v.clone().into(); f as f32 })
+            .unwrap_or(DEFAULT_BYPASS_PIVOT_SPEED);
+
+        let bypass_leg_max_ms: u64 = kv.get("bypass_leg_max_ms")
+            .map(|v| { let f: f64 = v.clone().into(); f as u64 })
+            .unwrap_or(DEFAULT_BYPASS_LEG_MAX_MS);
+
+        let reacquire_timeout_ms: u64 = kv.get("reacquire_timeout_ms")
+            .map(|v| { let f: f64 = v.clone().into(); f as u64 })
+            .unwrap_or(DEFAULT_REACQUIRE_TIMEOUT_MS);
+
+        let low_battery_speed_cap: f32 = kv.get("low_battery_speed_cap")
+            .map(|v| { let f: f64 = v.clone().into(); f as f32 })
+            .unwrap_or(DEFAULT_LOW_BATTERY_SPEED_CAP);
+
+        let critical_stop_ramp_ms: u64 = kv.get("critical_stop_ramp_ms")
+            .map(|v| { let f: f64 = v.clone().into(); f as u64 })
+            .unwrap_or(DEFAULT_CRITICAL_STOP_RAMP_MS);
+
         let mut inst = Self::default();
         inst.r_wind_comp_lmtr = r_wind_comp_lmtr as f32;
         inst.r_wind_comp_rmtr = r_wind_comp_rmtr as f32;
@@ -423,21 +551,62 @@ impl CuTask for Arbitrator {
         inst.alignment_deadband = alignment_deadband;
         inst.alignment_pulse_ms = alignment_pulse_ms;
         inst.alignment_cooldown_ms = alignment_cooldown_ms;
+        inst.obstacle.policy = obstacle_policy;
+        inst.obstacle.wait_ms = obstacle_wait_ms;
+        inst.obstacle.legs = bypass_legs(bypass_left, bypass_angle_degrees, bypass_offset_cm, bypass_pass_cm);
+        inst.obstacle.bypass_speed = bypass_speed;
+        inst.obstacle.bypass_pivot_speed = bypass_pivot_speed;
+        inst.obstacle.leg_max_ms = bypass_leg_max_ms;
+        inst.obstacle.reacquire_timeout_ms = reacquire_timeout_ms;
+        inst.e_stop_latch.mode = e_stop_mode;
+        inst.e_stop_latch.resume_distance_cm = e_stop_resume_distance_cm;
+        inst.e_stop_latch.resume_hold_ms = e_stop_resume_hold_ms;
         inst.accelerate_speed = accelerate_speed;
         inst.accelerate_duration_ms = accelerate_duration_ms;
+        inst.low_battery.warning_speed_cap = low_battery_speed_cap.clamp(0.0, 1.0);
+        inst.low_battery.stop_ramp_ms = critical_stop_ramp_ms;
         Ok(inst)
     }
 
-    fn process(&mut self, _clock: &RobotClock, input: &Self::Input<'_>, output: &mut Self::Output<'_>)
+    fn process(&mut self, clock: &RobotClock, input: &Self::Input<'_>, output: &mut Self::Output<'_>)
     -> CuResult<()>
     {
-        let (prop_adap, mtr_pid, nsm, encoder, itp) = *input;
+        let (prop_adap, mtr_pid, nsm, encoder, itp, e_stop_reset, bstn_e_stop, itp_e_stop, battery) = *input;
+
+        // remote e-stops don't depend on anything else being there
+        let remote_e_stop = self.bstn_e_stop.update("bstn", bstn_e_stop.payload().map(|m| m.0))
+            | self.itp_e_stop.update("itp", itp_e_stop.payload().map(|m| m.0));
 
         // PropulsionAdapterOutputPayload is required - can't do anything without it
         let Some(prop_adap_pload) = prop_adap.payload() else {
+            if remote_e_stop {
+                output.0.set_payload(PropulsionPayload::default());
+                output.2.set_payload(WheelVelocitySetpointPayload::default());
+            }
             return Ok(());
         };
 
+        // from here on the latched state is what counts, not the adapter's instantaneous trigger
+        self.e_stop_trig_fdbk = self.e_stop_latch.update(
+            clock.now(),
+            self.e_stop_trig_fdbk,
+            prop_adap_pload.is_e_stop_triggered,
+            prop_adap_pload.distance,
+            e_stop_reset.payload().map_or(false, |r| r.0 == 1),
+        );
+
+        // obstacle policy only runs in closed loop, it may take the e-stop latch over
+        let obstacle_override = match prop_adap_pload.loop_state {
+            LoopState::Closed => self.obstacle_handler(clock.now(), prop_adap_pload, encoder.payload(), nsm.payload()),
+            LoopState::Open => {
+                if self.obstacle.state != ObstaclePolicyState::Idle {
+                    self.obstacle.enter(ObstaclePolicyState::Idle, clock.now());
+                }
+                None
+            },
+        };
+        let obstacle_idle = self.obstacle.state == ObstaclePolicyState::Idle;
+
         self.target_speed = Some(prop_adap_pload.propulsion_payload.left_speed.clamp(0.0, 1.0));
 
         let mut closed_loop_prop_payload: PropulsionPayload = PropulsionPayload::default();
@@ -462,7 +631,7 @@ impl CuTask for Arbitrator {
                     let corner_close_enough = m.corner_coords.1 >= self.corner_y_coord_steering_trig && m.corner_detected;
 
                     // only trigger from NotSteering (cooldown must expire first)
-                    if corner_close_enough && self.steerer_state == SteererState::NotSteering {
+                    if corner_close_enough && self.steerer_state == SteererState::NotSteering && obstacle_idle {
                         self.steerer_state = SteererState::WaitingToSteer;
                         self.steering_triggered = CuInstant::now();
                         self.steering_direction = m.corner_direction;
@@ -529,7 +698,8 @@ impl CuTask for Arbitrator {
                 // Activates when stopped in closed-loop with valid lane vision and not steering
                 let is_stopped = self.target_speed.unwrap_or(0.0) < 0.01;
                 let not_steering = self.steerer_state == SteererState::NotSteering;
-                if is_stopped && not_steering {
+                // the obstacle handler drives the alignment handler itself while re-acquiring
+                if is_stopped && not_steering && obstacle_idle {
                     if let Some(m) = nsm.payload() {
                         if m.vertical_line_valid {
                             self.alignment_handler(m.heading_error, &mut closed_loop_prop_payload);
@@ -537,7 +707,7 @@ impl CuTask for Arbitrator {
                             self.alignment_state = AlignmentState::Inactive;
                         }
                     }
-                } else {
+                } else if obstacle_idle {
                     self.alignment_state = AlignmentState::Inactive;
                 }
             }
@@ -546,13 +716,13 @@ impl CuTask for Arbitrator {
 
         // Accelerate: rising edge from ITP triggers timed full-speed override
         // Only e-stop (obstacle) cancels acceleration
-        if prop_adap_pload.is_e_stop_triggered && self.accelerating {
+        if (self.e_stop_trig_fdbk || remote_e_stop) && self.accelerating {
             self.accelerating = false;
             eprintln!("ACCEL: cancelled by e-stop");
         }
 
         if let Some(itp_pload) = itp.payload() {
-            if itp_pload.accelerate_cmd && !self.accelerating && !prop_adap_pload.is_e_stop_triggered {
+            if itp_pload.accelerate_cmd && !self.accelerating && !self.e_stop_trig_fdbk && !remote_e_stop && obstacle_idle {
                 self.accelerating = true;
                 self.accelerate_started = CuInstant::now();
                 eprintln!("ACCEL: started ({}ms @ {:.2})", self.accelerate_duration_ms, self.accelerate_speed);
@@ -569,7 +739,7 @@ impl CuTask for Arbitrator {
             }
         }
 
-        let prop_payload: PropulsionPayload = if self.accelerating {
+        let mut prop_payload: PropulsionPayload = if self.accelerating {
             PropulsionPayload {
                 left_enable: true,
                 right_enable: true,
@@ -589,19 +759,177 @@ impl CuTask for Arbitrator {
             }
         };
 
+        if let Some(p) = obstacle_override {
+            prop_payload = p;
+        }
+
+        // time-to-collision cap goes on last so it covers the accelerate burst and the steering boost too
+        apply_speed_cap(&mut prop_payload, prop_adap_pload.speed_cap);
+
+        // same for the battery, a critical battery ramps down to a stop
+        apply_battery_cap(&mut prop_payload, self.low_battery.update(clock.now(), battery.payload()));
+        if self.low_battery.stopped(clock.now()) {
+            prop_payload = PropulsionPayload::default();
+        }
+
+        // remote e-stop overrides every mode
+        if remote_e_stop {
+            prop_payload = PropulsionPayload::default();
+        }
+
         let anc_pub_pload = AncPubPayload {
-            e_stop_trig_fdbk: prop_adap_pload.is_e_stop_triggered,
+            e_stop_trig_fdbk: self.e_stop_trig_fdbk,
             loop_mode_fdbk: prop_adap_pload.loop_state,
-            distance: prop_adap_pload.distance
+            distance: prop_adap_pload.distance,
+            speed_cap: prop_adap_pload.speed_cap,
+            bstn_e_stop_reason: self.bstn_e_stop.reason,
+            itp_e_stop_reason: self.itp_e_stop.reason,
+            obstacle_policy_state: self.obstacle.state.to_u8(),
         };
 
+        // normalized speed maps linearly from 0 rpm to max_rpm, see docs/speed_setpoint.md
+        let setpoint_pload = WheelVelocitySetpointPayload {
+            lmtr_rpm: self.wheel_rpm_setpoint(prop_payload.left_enable, prop_payload.left_speed, prop_payload.left_direction),
+            rmtr_rpm: self.wheel_rpm_setpoint(prop_payload.right_enable, prop_payload.right_speed, prop_payload.right_direction),
+        };
+
+        // Done only lasts within a tick, it goes to Cooldown before we get here
+        let phase = match (loop_state, &self.steerer_state) {
+            (LoopState::Closed, SteererState::WaitingToSteer) => ManeuverPhase::CornerPending,
+            (LoopState::Closed, SteererState::Steering) => ManeuverPhase::Cornering,
+            (LoopState::Closed, SteererState::Done | SteererState::Cooldown) => ManeuverPhase::Cooldown,
+            _ => ManeuverPhase::None,
+        };
+        let maneuver_pload = ManeuverPayload { phase, direction: self.steering_direction };
+
         output.0.set_payload(prop_payload);
         output.1.set_payload(anc_pub_pload);
+        output.2.set_payload(setpoint_pload);
+        output.3.set_payload(maneuver_pload);
         Ok(())
     }
 }
 
+/// Scales both wheels by the same factor so the fastest forward wheel is at most `cap`, which keeps
+/// the left/right ratio (and so the steering) intact. Reversing wheels are left alone, they move
+/// away from the obstacle or pivot in place.
+fn apply_speed_cap(payload: &mut PropulsionPayload, cap: f32) {
+    let fwd_speed = |enable: bool, speed: f32, dir: WheelDirection| {
+        if enable && dir == WheelDirection::Forward { speed } else { 0.0 }
+    };
+    let fastest = fwd_speed(payload.left_enable, payload.left_speed, payload.left_direction)
+        .max(fwd_speed(payload.right_enable, payload.right_speed, payload.right_direction));
+
+    if fastest > cap && fastest > 0.0 {
+        let scale = cap / fastest;
+        payload.left_speed *= scale;
+        payload.right_speed *= scale;
+    }
+}
+
 impl Arbitrator {
+    /// Returns a payload when the obstacle policy is driving the robot, None to leave it to the
+    /// normal closed loop (which is stopped anyway while the e-stop is latched).
+    fn obstacle_handler(&mut self, now: CuTime, prop_adap_pload: &PropulsionAdapterOutputPayload,
+        encoder: Option<&IrEncoderPayload>, nsm: Option<&NsmPayload>) -> Option<PropulsionPayload>
+    {
+        if self.obstacle.policy == ObstaclePolicy::Stop {
+            return None;
+        }
+
+        match self.obstacle.state {
+            ObstaclePolicyState::Idle => {
+                if self.e_stop_trig_fdbk {
+                    self.obstacle.enter(ObstaclePolicyState::Waiting, now);
+                }
+                None
+            },
+            ObstaclePolicyState::Waiting => {
+                let clear = !prop_adap_pload.is_e_stop_triggered
+                    && prop_adap_pload.distance.is_some_and(|d| d >= self.e_stop_latch.resume_distance_cm);
+                // only reachable with e_stop_mode auto_resume (checked in new), the policy resumes on its own
+                if clear || !self.e_stop_trig_fdbk {
+                    self.e_stop_trig_fdbk = false;
+                    self.obstacle.enter(ObstaclePolicyState::Idle, now);
+                    return None;
+                }
+
+                if self.obstacle.elapsed_in_state(now) >= CuDuration::from_millis(self.obstacle.wait_ms) {
+                    match self.obstacle.policy {
+                        ObstaclePolicy::Bypass => self.obstacle.enter(ObstaclePolicyState::Bypassing, now),
+                        _ => self.obstacle.enter(ObstaclePolicyState::GaveUp, now),
+                    }
+                }
+                None
+            },
+            ObstaclePolicyState::Bypassing => {
+                // pivots turn away from the obstacle, straights must not run into something
+                if let Some(BypassLeg::Straight(_)) = self.obstacle.current_leg() && prop_adap_pload.is_e_stop_triggered {
+                    eprintln!("OBSTACLE: blocked during bypass");
+                    self.e_stop_trig_fdbk = true;
+                    self.obstacle.enter(ObstaclePolicyState::GaveUp, now);
+                    return None;
+                }
+
+                let odom = Odometry {
+                    wheel_radius_cm: self.wheel_radius_cm,
+                    wheelbase_cm: self.wheelbase_cm,
+                    max_rpm: self.max_rpm,
+                };
+                match self.obstacle.drive_bypass(now, encoder, &odom) {
+                    Ok(Some(p)) => Some(p),
+                    Ok(None) => {
+                        self.e_stop_trig_fdbk = false;
+                        self.alignment_state = AlignmentState::Inactive;
+                        self.obstacle.enter(ObstaclePolicyState::Reacquiring, now);
+                        Some(PropulsionPayload::default())
+                    },
+                    Err(_) => {
+                        self.e_stop_trig_fdbk = true;
+                        self.obstacle.enter(ObstaclePolicyState::GaveUp, now);
+                        None
+                    },
+                }
+            },
+            ObstaclePolicyState::Reacquiring => {
+                let mut res = PropulsionPayload::default();
+                if let Some(m) = nsm && m.vertical_line_valid {
+                    self.alignment_handler(m.heading_error, &mut res);
+                    if self.alignment_state == AlignmentState::Aligned {
+                        self.obstacle.enter(ObstaclePolicyState::Idle, now);
+                        return None;
+                    }
+                }
+
+                if self.obstacle.elapsed_in_state(now) >= CuDuration::from_millis(self.obstacle.reacquire_timeout_ms) {
+                    eprintln!("OBSTACLE: lane not re-acquired within {}ms", self.obstacle.reacquire_timeout_ms);
+                    self.e_stop_trig_fdbk = true;
+                    self.obstacle.enter(ObstaclePolicyState::GaveUp, now);
+                    return None;
+                }
+                Some(res)
+            },
+            ObstaclePolicyState::GaveUp => {
+                // released when the e-stop auto-resumes
+                if !self.e_stop_trig_fdbk {
+                    self.obstacle.enter(ObstaclePolicyState::Idle, now);
+                }
+                None
+            },
+        }
+    }
+
+    fn wheel_rpm_setpoint(&self, enable: bool, speed: f32, direction: WheelDirection) -> f32 {
+        if !enable {
+            return 0.0;
+        }
+        match direction {
+            WheelDirection::Forward => speed * self.max_rpm,
+            WheelDirection::Reverse => -speed * self.max_rpm,
+            WheelDirection::Stop => 0.0,
+        }
+    }
+
     fn open_loop_handler(&mut self, prop_adap_pload: &PropulsionAdapterOutputPayload) -> CuResult<PropulsionPayload> {
         // initialize to safe conditions
         let left_enable: bool = false;
@@ -620,7 +948,7 @@ impl Arbitrator {
                 right_direction
             };
 
-        if prop_adap_pload.is_e_stop_triggered {
+        if self.e_stop_trig_fdbk {
             return Ok(ret)
         }
         else {
@@ -646,7 +974,7 @@ impl Arbitrator {
     }
 
     fn closed_loop_handler(&self, pid_output: f32, prop_adap_pload: &PropulsionAdapterOutputPayload) -> CuResult<PropulsionPayload> {
-        if prop_adap_pload.is_e_stop_triggered {
+        if self.e_stop_trig_fdbk {
             return Ok(PropulsionPayload::default());
         }
 
@@ -672,6 +1000,7 @@ impl Arbitrator {
 
     /// Uses latched steering_direction so it runs every tick without depending on nsm.
     /// Integrates encoder-derived differential wheel velocity to estimate cumulative yaw.
+    /// Encoder readings are signed, so a wheel turning backwards during a pivot adds to the yaw rate.
     /// Exit conditions:
     ///   1. max hold exceeded (safety timeout)
     ///   2. accumulated_yaw >= target_yaw_radians (encoder-based dead reckoning)
@@ -0,0 +1,361 @@
+use cu29::prelude::*;
+use cu_propulsion::{PropulsionPayload, WheelDirection};
+use cu_irencoder::IrEncoderPayload;
+
+pub const DEFAULT_OBSTACLE_WAIT_MS: u64 = 3000;
+pub const DEFAULT_BYPASS_ANGLE_DEGREES: f32 = 45.0;
+pub const DEFAULT_BYPASS_OFFSET_CM: f32 = 25.0;
+pub const DEFAULT_BYPASS_PASS_CM: f32 = 30.0;
+pub const DEFAULT_BYPASS_SPEED: f32 = 0.4;
+pub const DEFAULT_BYPASS_PIVOT_SPEED: f32 = 0.4;
+pub const DEFAULT_BYPASS_LEG_MAX_MS: u64 = 3000;
+pub const DEFAULT_REACQUIRE_TIMEOUT_MS: u64 = 4000;
+
+/// What closed loop does when the obstacle e-stop latches
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ObstaclePolicy {
+    /// Old behaviour, the e-stop latch alone decides
+    #[default]
+    Stop,
+    /// Wait up to obstacle_wait_ms for the obstacle to go away and resume, otherwise give up
+    Wait,
+    /// Wait, then drive around the obstacle on bypass_side and re-acquire the lane
+    Bypass,
+}
+
+impl ObstaclePolicy {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "stop" => Ok(ObstaclePolicy::Stop),
+            "wait" => Ok(ObstaclePolicy::Wait),
+            "bypass" => Ok(ObstaclePolicy::Bypass),
+            _ => Err(format!("Invalid obstacle_policy value: \"{s}\". Valid values: \"stop\", \"wait\", \"bypass\"")),
+        }
+    }
+}
+
+/// Published on palanuk/anc/obstacle_policy as u8
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ObstaclePolicyState {
+    #[default]
+    Idle,
+    Waiting,
+    Bypassing,
+    Reacquiring,
+    /// Stopped with the e-stop latched until it auto-resumes
+    GaveUp,
+}
+
+impl ObstaclePolicyState {
+    pub fn to_u8(self) -> u8 {
+        match self {
+            ObstaclePolicyState::Idle => 0,
+            ObstaclePolicyState::Waiting => 1,
+            ObstaclePolicyState::Bypassing => 2,
+            ObstaclePolicyState::Reacquiring => 3,
+            ObstaclePolicyState::GaveUp => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BypassLeg {
+    /// radians, positive is counter-clockwise (left)
+    Pivot(f32),
+    /// cm
+    Straight(f32),
+}
+
+/// Wheel geometry for odometry, same values the steering handler uses
+#[derive(Debug, Clone, Copy)]
+pub struct Odometry {
+    pub wheel_radius_cm: f32,
+    pub wheelbase_cm: f32,
+    pub max_rpm: f32,
+}
+
+#[derive(Debug)]
+pub struct ObstacleHandler {
+    pub policy: ObstaclePolicy,
+    pub wait_ms: u64,
+    pub bypass_speed: f32,
+    pub bypass_pivot_speed: f32,
+    pub leg_max_ms: u64,
+    pub reacquire_timeout_ms: u64,
+    pub legs: Vec<BypassLeg>,
+    pub state: ObstaclePolicyState,
+    state_started: CuTime,
+    leg_idx: usize,
+    leg_started: CuTime,
+    leg_progress: f32,
+    last_tick: CuTime,
+}
+
+impl Default for ObstacleHandler {
+    fn default() -> Self {
+        Self {
+            policy: ObstaclePolicy::default(),
+            wait_ms: DEFAULT_OBSTACLE_WAIT_MS,
+            bypass_speed: DEFAULT_BYPASS_SPEED,
+            bypass_pivot_speed: DEFAULT_BYPASS_PIVOT_SPEED,
+            leg_max_ms: DEFAULT_BYPASS_LEG_MAX_MS,
+            reacquire_timeout_ms: DEFAULT_REACQUIRE_TIMEOUT_MS,
+            legs: bypass_legs(true, DEFAULT_BYPASS_ANGLE_DEGREES, DEFAULT_BYPASS_OFFSET_CM, DEFAULT_BYPASS_PASS_CM),
+            state: ObstaclePolicyState::default(),
+            state_started: CuDuration::from_nanos(0),
+            leg_idx: 0,
+            leg_started: CuDuration::from_nanos(0),
+            leg_progress: 0.0,
+            last_tick: CuDuration::from_nanos(0),
+        }
+    }
+}
+
+/// Out at `angle_degrees` until `offset_cm` to the side, straighten, pass, come back in, straighten.
+pub fn bypass_legs(left: bool, angle_degrees: f32, offset_cm: f32, pass_cm: f32) -> Vec<BypassLeg> {
+    let theta = angle_degrees.to_radians() * if left { 1.0 } else { -1.0 };
+    let out_cm = offset_cm / angle_degrees.to_radians().sin().max(0.1);
+    vec![
+        BypassLeg::Pivot(theta),
+        BypassLeg::Straight(out_cm),
+        BypassLeg::Pivot(-theta),
+        BypassLeg::Straight(pass_cm),
+        BypassLeg::Pivot(-theta),
+        BypassLeg::Straight(out_cm),
+        BypassLeg::Pivot(theta),
+    ]
+}
+
+fn elapsed_since(now: CuTime, since: CuTime) -> CuDuration {
+    CuDuration::from_nanos(now.as_nanos().saturating_sub(since.as_nanos()))
+}
+
+impl ObstacleHandler {
+    pub fn enter(&mut self, state: ObstaclePolicyState, now: CuTime) {
+        eprintln!("OBSTACLE: {:?} -> {:?}", self.state, state);
+        self.state = state;
+        self.state_started = now;
+        if state == ObstaclePolicyState::Bypassing {
+            self.leg_idx = 0;
+            self.start_leg(now);
+        }
+    }
+
+    pub fn elapsed_in_state(&self, now: CuTime) -> CuDuration {
+        elapsed_since(now, self.state_started)
+    }
+
+    pub fn current_leg(&self) -> Option<BypassLeg> {
+        self.legs.get(self.leg_idx).copied()
+    }
+
+    fn start_leg(&mut self, now: CuTime) {
+        self.leg_started = now;
+        self.last_tick = now;
+        self.leg_progress = 0.0;
+        if let Some(leg) = self.current_leg() {
+            eprintln!("OBSTACLE: bypass leg {}/{} {:?}", self.leg_idx + 1, self.legs.len(), leg);
+        }
+    }
+
+    /// Ok(Some(payload)) while a leg is running, Ok(None) once all legs are done,
+    /// Err on a leg timeout (wheels slipping or stalled, odometry can't be trusted)
+    pub fn drive_bypass(&mut self, now: CuTime, encoder: Option<&IrEncoderPayload>, odom: &Odometry) -> Result<Option<PropulsionPayload>, ()> {
+        let Some(leg) = self.current_leg() else {
+            return Ok(None);
+        };
+
+        if elapsed_since(now, self.leg_started) >= CuDuration::from_millis(self.leg_max_ms) {
+            eprintln!("OBSTACLE: bypass leg {} timed out after {}ms", self.leg_idx + 1, self.leg_max_ms);
+            return Err(());
+        }
+
+        // integrate odometry, same model as the steering handler
+        if let Some(enc) = encoder {
+            let to_omega = |norm: f32| norm * odom.max_rpm * 2.0 * std::f32::consts::PI / 60.0;
+            let omega_l = to_omega(enc.lmtr_normalized_rpm.unwrap_or(0.0));
+            let omega_r = to_omega(enc.rmtr_normalized_rpm.unwrap_or(0.0));
+
+            let dt_s = now.as_nanos().saturating_sub(self.last_tick.as_nanos()) as f32 / 1_000_000_000.0;
+            self.last_tick = now;
+
+            self.leg_progress += match leg {
+                BypassLeg::Pivot(_) => odom.wheel_radius_cm * (omega_r - omega_l) / odom.wheelbase_cm * dt_s,
+                BypassLeg::Straight(_) => odom.wheel_radius_cm * (omega_l + omega_r) / 2.0 * dt_s,
+            };
+        }
+
+        let done = match leg {
+            BypassLeg::Pivot(target) => self.leg_progress * target.signum() >= target.abs(),
+            BypassLeg::Straight(target) => self.leg_progress >= target,
+        };
+        if done {
+            self.leg_idx += 1;
+            self.start_leg(now);
+            // one stopped tick between legs
+            return Ok(Some(PropulsionPayload::default()));
+        }
+
+        let mut res = PropulsionPayload {
+            left_enable: true,
+            right_enable: true,
+            ..Default::default()
+        };
+        match leg {
+            BypassLeg::Pivot(target) => {
+                res.left_speed = self.bypass_pivot_speed;
+                res.right_speed = self.bypass_pivot_speed;
+                if target > 0.0 {
+                    res.left_direction = WheelDirection::Reverse;
+                    res.right_direction = WheelDirection::Forward;
+                } else {
+                    res.left_direction = WheelDirection::Forward;
+                    res.right_direction = WheelDirection::Reverse;
+                }
+            },
+            BypassLeg::Straight(_) => {
+                res.left_speed = self.bypass_speed;
+                res.right_speed = self.bypass_speed;
+                res.left_direction = WheelDirection::Forward;
+                res.right_direction = WheelDirection::Forward;
+            },
+        }
+        Ok(Some(res))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+    use std::time::Duration;
+
+    const CYCLE: Duration = Duration::from_millis(18);
+    const ODOM: Odometry = Odometry { wheel_radius_cm: 3.0, wheelbase_cm: 15.0, max_rpm: 600.0 };
+
+    fn encoder(l: f32, r: f32) -> IrEncoderPayload {
+        IrEncoderPayload { lmtr_normalized_rpm: Some(l), rmtr_normalized_rpm: Some(r), ..Default::default() }
+    }
+
+    /// Drives the current leg a cycle at a time until it ends, returns the time it took and how
+    fn drive_leg(h: &mut ObstacleHandler, clock: &(RobotClock, RobotClockMock), enc: Option<IrEncoderPayload>)
+        -> (u64, Result<Option<PropulsionPayload>, ()>)
+    {
+        let (clock, mock) = clock;
+        let leg = h.leg_idx;
+        let start = clock.now().as_nanos();
+        loop {
+            mock.increment(CYCLE);
+            let res = h.drive_bypass(clock.now(), enc.as_ref(), &ODOM);
+            if res.is_err() || h.leg_idx != leg {
+                return ((clock.now().as_nanos() - start) / 1_000_000, res);
+            }
+        }
+    }
+
+    /// Walks the legs from the origin heading along x, returns (x, y, heading)
+    fn walk(legs: &[BypassLeg]) -> (f32, f32, f32) {
+        legs.iter().fold((0.0, 0.0, 0.0), |(x, y, h), leg| match leg {
+            BypassLeg::Pivot(a) => (x, y, h + a),
+            BypassLeg::Straight(d) => (x + d * h.cos(), y + d * h.sin(), h),
+        })
+    }
+
+    #[test]
+    fn bypass_legs_come_back_to_the_lane() {
+        for left in [true, false] {
+            let legs = bypass_legs(left, 45.0, 25.0, 30.0);
+            let (x, y, heading) = walk(&legs);
+            assert!(heading.abs() < 1e-5, "heading {heading}");
+            assert!(y.abs() < 1e-3, "y {y}");
+            // out and back at 45 degrees covers the offset forward twice
+            assert!((x - (2.0 * 25.0 + 30.0)).abs() < 1e-3, "x {x}");
+
+            // halfway along the pass the robot is offset_cm out, on the chosen side
+            let (_, y, heading) = walk(&legs[..3]);
+            assert!(heading.abs() < 1e-5);
+            assert!((y - if left { 25.0 } else { -25.0 }).abs() < 1e-3, "y {y}");
+        }
+    }
+
+    #[test]
+    fn pivot_leg_completes_on_odometry() {
+        let clock = RobotClock::mock();
+        let mut h = ObstacleHandler { legs: vec![BypassLeg::Pivot(PI / 2.0)], ..Default::default() };
+        h.enter(ObstaclePolicyState::Bypassing, clock.0.now());
+
+        // counter-clockwise, left wheel back, right forward
+        let first = h.drive_bypass(clock.0.now(), None, &ODOM).unwrap().unwrap();
+        assert_eq!((first.left_direction, first.right_direction), (WheelDirection::Reverse, WheelDirection::Forward));
+        assert_eq!(first.left_speed, h.bypass_pivot_speed);
+
+        // 0.1 normalized is 60 rpm, r * (wr - wl) / wheelbase
+        let omega = 0.1 * 600.0 * 2.0 * PI / 60.0;
+        let yaw_rate = 3.0 * 2.0 * omega / 15.0;
+        let expected_ms = (PI / 2.0 / yaw_rate * 1000.0) as u64;
+
+        let (ms, res) = drive_leg(&mut h, &clock, Some(encoder(-0.1, 0.1)));
+        assert!(ms >= expected_ms && ms < expected_ms + 18, "took {ms}ms vs {expected_ms}ms");
+        // a stopped tick between legs, then nothing left
+        assert_eq!(res, Ok(Some(PropulsionPayload::default())));
+        assert_eq!(h.drive_bypass(clock.0.now(), None, &ODOM), Ok(None));
+    }
+
+    #[test]
+    fn clockwise_pivot_turns_right() {
+        let clock = RobotClock::mock();
+        let mut h = ObstacleHandler { legs: vec![BypassLeg::Pivot(-PI / 4.0)], ..Default::default() };
+        h.enter(ObstaclePolicyState::Bypassing, clock.0.now());
+
+        let first = h.drive_bypass(clock.0.now(), None, &ODOM).unwrap().unwrap();
+        assert_eq!((first.left_direction, first.right_direction), (WheelDirection::Forward, WheelDirection::Reverse));
+
+        // turning the wrong way never completes it
+        let (ms, res) = drive_leg(&mut h, &clock, Some(encoder(-0.1, 0.1)));
+        assert_eq!(res, Err(()));
+        assert!(ms >= h.leg_max_ms, "took {ms}ms");
+    }
+
+    #[test]
+    fn straight_leg_completes_on_odometry() {
+        let clock = RobotClock::mock();
+        let mut h = ObstacleHandler { legs: vec![BypassLeg::Straight(30.0), BypassLeg::Pivot(1.0)], ..Default::default() };
+        h.enter(ObstaclePolicyState::Bypassing, clock.0.now());
+
+        let first = h.drive_bypass(clock.0.now(), None, &ODOM).unwrap().unwrap();
+        assert_eq!((first.left_direction, first.right_direction), (WheelDirection::Forward, WheelDirection::Forward));
+        assert_eq!(first.left_speed, h.bypass_speed);
+
+        let speed_cm_s = 3.0 * 0.2 * 600.0 * 2.0 * PI / 60.0;
+        let expected_ms = (30.0 / speed_cm_s * 1000.0) as u64;
+        let (ms, res) = drive_leg(&mut h, &clock, Some(encoder(0.2, 0.2)));
+        assert!(ms >= expected_ms && ms < expected_ms + 18, "took {ms}ms vs {expected_ms}ms");
+        assert_eq!(res, Ok(Some(PropulsionPayload::default())));
+
+        // the next leg starts from scratch
+        assert_eq!(h.current_leg(), Some(BypassLeg::Pivot(1.0)));
+        assert_eq!(h.leg_progress, 0.0);
+    }
+
+    #[test]
+    fn leg_times_out_without_odometry() {
+        let clock = RobotClock::mock();
+        let mut h = ObstacleHandler { leg_max_ms: 1000, ..Default::default() };
+        h.enter(ObstaclePolicyState::Bypassing, clock.0.now());
+
+        let (ms, res) = drive_leg(&mut h, &clock, None);
+        assert_eq!(res, Err(()));
+        assert!(ms >= 1000 && ms < 1018, "timed out after {ms}ms");
+    }
+
+    #[test]
+    fn state_time_is_from_entering_it() {
+        let (clock, mock) = RobotClock::mock();
+        let mut h = ObstacleHandler::default();
+
+        mock.increment(Duration::from_secs(5));
+        h.enter(ObstaclePolicyState::Waiting, clock.now());
+        mock.increment(Duration::from_millis(1200));
+        assert_eq!(h.elapsed_in_state(clock.now()), CuDuration::from_millis(1200));
+    }
+}
@@ -0,0 +1,62 @@
+/// Resting cell voltage (mV) to state of charge, ascending. Typical discharge curves at room
+/// temperature, good to a few percent away from the knees, which is what the coulomb counter is for.
+const LI_ION_CURVE: [(f64, f32); 10] = [
+    (3000.0, 0.00), (3300.0, 0.05), (3500.0, 0.10), (3600.0, 0.20), (3700.0, 0.40),
+    (3800.0, 0.60), (3900.0, 0.75), (4000.0, 0.85), (4100.0, 0.95), (4200.0, 1.00),
+];
+const LIFEPO4_CURVE: [(f64, f32); 8] = [
+    (2500.0, 0.00), (3000.0, 0.10), (3200.0, 0.20), (3250.0, 0.40),
+    (3300.0, 0.70), (3350.0, 0.90), (3400.0, 0.97), (3600.0, 1.00),
+];
+const NIMH_CURVE: [(f64, f32); 6] = [
+    (1000.0, 0.00), (1100.0, 0.10), (1200.0, 0.40), (1250.0, 0.70), (1300.0, 0.90), (1400.0, 1.00),
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Chemistry {
+    /// Li-ion and LiPo
+    #[default]
+    LiIon,
+    LiFePo4,
+    NiMh,
+}
+
+impl Chemistry {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "liion" | "lipo" => Ok(Chemistry::LiIon),
+            "lifepo4" => Ok(Chemistry::LiFePo4),
+            "nimh" => Ok(Chemistry::NiMh),
+            _ => Err(format!("Invalid chemistry value: \"{s}\". Valid values: \"liion\", \"lipo\", \"lifepo4\", \"nimh\"")),
+        }
+    }
+
+    fn curve(&self) -> &'static [(f64, f32)] {
+        match self {
+            Chemistry::LiIon => &LI_ION_CURVE,
+            Chemistry::LiFePo4 => &LIFEPO4_CURVE,
+            Chemistry::NiMh => &NIMH_CURVE,
+        }
+    }
+
+    /// Cell voltage at 0% SoC, below this the cell is being damaged
+    pub fn empty_cell_mv(&self) -> f64 {
+        self.curve()[0].0
+    }
+
+    /// Linear interpolation on the curve, clamped to 0..1
+    pub fn soc_from_cell_mv(&self, cell_mv: f64) -> f32 {
+        let curve = self.curve();
+        if cell_mv <= curve[0].0 {
+            return 0.0;
+        }
+        for pair in curve.windows(2) {
+            let ((v0, s0), (v1, s1)) = (pair[0], pair[1]);
+            if cell_mv <= v1 {
+                let t = ((cell_mv - v0) / (v1 - v0)) as f32;
+                return s0 + t * (s1 - s0);
+            }
+        }
+        1.0
+    }
+}
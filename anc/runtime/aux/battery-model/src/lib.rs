@@ -0,0 +1,381 @@
+/// Battery state of charge from the power monitors, and the warning/critical levels the arbitrator
+/// acts on
+
+extern crate cu_bincode as bincode;
+
+mod chemistry;
+pub use chemistry::*;
+
+use cu29::prelude::*;
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use cu_powermon::Ina219Payload;
+use cu_health::Availability;
+
+pub const MAX_BATTERY_LOADS: usize = 3;
+pub const DEFAULT_CELLS: u32 = 2;
+pub const DEFAULT_CAPACITY_MAH: f64 = 2000.0;
+pub const DEFAULT_INTERNAL_RESISTANCE_MOHMS: f64 = 150.0;
+/// Per second, how hard the voltage estimate pulls the coulomb count. Small: the count carries the
+/// short term, the voltage only removes its drift.
+pub const DEFAULT_VOLTAGE_GAIN: f32 = 0.002;
+pub const DEFAULT_WARNING_SOC: f32 = 0.20;
+pub const DEFAULT_CRITICAL_SOC: f32 = 0.08;
+/// A level only clears once the SoC is this much above its threshold, so it doesn't flap under load
+pub const DEFAULT_LEVEL_HYSTERESIS: f32 = 0.03;
+pub const DEFAULT_CURRENT_TAU_S: f32 = 30.0;
+/// Same reasoning as ec-energy, one stalled cycle doesn't drain the count
+const MAX_STEP_S: f32 = 0.5;
+/// Below this the remaining runtime is meaningless
+const MIN_CURRENT_MA: f64 = 1.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Encode, Decode, Serialize, Deserialize)]
+#[derive(Reflect)]
+pub enum BatteryLevel {
+    #[default]
+    Ok,
+    Warning,
+    Critical,
+}
+
+/// `voltage_mv` is measured under load, `ocv_mv` is that plus the internal resistance drop.
+/// `current_ma` is the estimated draw from the battery, `remaining_min` is at the smoothed draw.
+/// Unavailable when no voltage source is available, the rest is then the last estimate.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Encode, Decode, Serialize, Deserialize)]
+#[derive(Reflect)]
+#[reflect(no_field_bounds, from_reflect = false)]
+pub struct BatteryPayload {
+    pub soc: f32,
+    pub voltage_mv: f64,
+    pub ocv_mv: f64,
+    pub current_ma: f64,
+    pub remaining_min: Option<f32>,
+    pub level: BatteryLevel,
+    pub availability: Availability,
+}
+
+impl BatteryPayload {
+    pub fn available(&self) -> bool {
+        self.availability == Availability::Available
+    }
+}
+
+/// Inputs are the power monitors of everything the battery feeds, in cnx order. The first
+/// "voltage_sources" of them sit directly on the battery and their bus voltage is the pack voltage
+/// (first available one wins). Battery current is the sum of their power over the pack voltage, each
+/// divided by its "efficiencyN" (e.g. the 5V regulator). SoC starts from the load compensated voltage,
+/// then is coulomb counted and pulled towards the voltage estimate by "voltage_gain".
+/// Config: "chemistry", "cells", "capacity_mah", "internal_resistance_mohms", "voltage_gain",
+/// "voltage_sources", "efficiency0".."efficiency2", "warning_soc", "critical_soc", "level_hysteresis",
+/// "current_tau_s"
+#[derive(Reflect)]
+#[reflect(no_field_bounds, from_reflect = false)]
+pub struct BatteryModel {
+    #[reflect(ignore)]
+    chemistry: Chemistry,
+    cells: u32,
+    capacity_mah: f64,
+    internal_resistance_mohms: f64,
+    voltage_gain: f32,
+    voltage_sources: usize,
+    #[reflect(ignore)]
+    efficiency: [f64; MAX_BATTERY_LOADS],
+    warning_soc: f32,
+    critical_soc: f32,
+    level_hysteresis: f32,
+    current_tau_s: f32,
+    soc: Option<f32>,
+    avg_current_ma: f64,
+    #[reflect(ignore)]
+    level: BatteryLevel,
+    #[reflect(ignore)]
+    last_sample: Option<CuTime>,
+    #[reflect(ignore)]
+    last: BatteryPayload,
+}
+
+impl Freezable for BatteryModel {
+    fn freeze<E: bincode::enc::Encoder>(&self, encoder: &mut E) -> Result<(), bincode::error::EncodeError> {
+        Encode::encode(&self.soc, encoder)?;
+        Encode::encode(&self.level, encoder)?;
+        Ok(())
+    }
+
+    fn thaw<D: bincode::de::Decoder>(&mut self, decoder: &mut D) -> Result<(), bincode::error::DecodeError> {
+        self.soc = Decode::decode(decoder)?;
+        self.level = Decode::decode(decoder)?;
+        Ok(())
+    }
+}
+
+impl BatteryModel {
+    /// Levels only go up (worse) on the threshold, and down once the SoC clears it by the hysteresis
+    fn update_level(&mut self, soc: f32, cell_mv: f64) {
+        let mut level = if soc <= self.critical_soc {
+            BatteryLevel::Critical
+        } else if soc <= self.warning_soc {
+            BatteryLevel::Warning
+        } else {
+            BatteryLevel::Ok
+        };
+        // loaded voltage under the empty point is critical whatever the count says
+        if cell_mv < self.chemistry.empty_cell_mv() {
+            level = BatteryLevel::Critical;
+        }
+
+        let clears = |threshold: f32| soc > threshold + self.level_hysteresis;
+        let level = match (self.level, level) {
+            (prev, new) if new >= prev => new,
+            (BatteryLevel::Critical, _) if !clears(self.critical_soc) => BatteryLevel::Critical,
+            (BatteryLevel::Warning, _) | (BatteryLevel::Critical, _) if !clears(self.warning_soc) => BatteryLevel::Warning,
+            (_, new) => new,
+        };
+
+        if level != self.level {
+            eprintln!("BATTERY: {:?} -> {:?} at soc={:.2} cell={:.0}mV", self.level, level, soc, cell_mv);
+            self.level = level;
+        }
+    }
+}
+
+impl CuTask for BatteryModel {
+    type Input<'m> = input_msg!('m, Ina219Payload, Ina219Payload, Ina219Payload);
+    type Output<'m> = output_msg!(BatteryPayload);
+    type Resources<'r> = ();
+
+    fn new(config: Option<&ComponentConfig>, _resources: Self::Resources<'_>) -> CuResult<Self>
+    where Self: Sized
+    {
+        let ComponentConfig(kv) =
+            config.ok_or("No ComponentConfig specified for BatteryModel in RON")?;
+        let get_f64 = |key: &str| kv.get(key).map(|v| -> f64 { v.clone().into() });
+
+        let chemistry = match kv.get("chemistry") {
+            Some(v) => {
+                let s: String = v.clone().into();
+                Chemistry::parse(&s).map_err(|e| CuError::from(e.as_str()))?
+            },
+            None => Chemistry::default(),
+        };
+
+        let mut efficiency = [1.0; MAX_BATTERY_LOADS];
+        for (i, e) in efficiency.iter_mut().enumerate() {
+            if let Some(f) = get_f64(&format!("efficiency{i}")) {
+                if !(f > 0.0 && f <= 1.0) {
+                    return Err(CuError::from(format!("BatteryModel: efficiency{i} must be in (0, 1], got {f}")));
+                }
+                *e = f;
+            }
+        }
+
+        let inst = Self {
+            chemistry,
+            cells: get_f64("cells").map_or(DEFAULT_CELLS, |f| f as u32),
+            capacity_mah: get_f64("capacity_mah").unwrap_or(DEFAULT_CAPACITY_MAH),
+            internal_resistance_mohms: get_f64("internal_resistance_mohms").unwrap_or(DEFAULT_INTERNAL_RESISTANCE_MOHMS),
+            voltage_gain: get_f64("voltage_gain").map_or(DEFAULT_VOLTAGE_GAIN, |f| f as f32),
+            voltage_sources: get_f64("voltage_sources").map_or(1, |f| f as usize),
+            efficiency,
+            warning_soc: get_f64("warning_soc").map_or(DEFAULT_WARNING_SOC, |f| f as f32),
+            critical_soc: get_f64("critical_soc").map_or(DEFAULT_CRITICAL_SOC, |f| f as f32),
+            level_hysteresis: get_f64("level_hysteresis").map_or(DEFAULT_LEVEL_HYSTERESIS, |f| f as f32),
+            current_tau_s: get_f64("current_tau_s").map_or(DEFAULT_CURRENT_TAU_S, |f| f as f32),
+            soc: None,
+            avg_current_ma: 0.0,
+            level: BatteryLevel::default(),
+            last_sample: None,
+            last: BatteryPayload { availability: Availability::Unavailable, ..Default::default() },
+        };
+
+        if inst.cells == 0 || inst.capacity_mah <= 0.0 {
+            return Err(CuError::from("BatteryModel: cells and capacity_mah must be positive"));
+        }
+        if inst.voltage_sources == 0 || inst.voltage_sources > MAX_BATTERY_LOADS {
+            return Err(CuError::from(format!("BatteryModel: voltage_sources must be 1..={MAX_BATTERY_LOADS}")));
+        }
+        if inst.critical_soc >= inst.warning_soc {
+            return Err(CuError::from("BatteryModel: need critical_soc < warning_soc"));
+        }
+        Ok(inst)
+    }
+
+    fn process(&mut self, clock: &RobotClock, input: &Self::Input<'_>, output: &mut Self::Output<'_>)
+    -> CuResult<()>
+    {
+        let loads = [input.0.payload(), input.1.payload(), input.2.payload()]
+            .map(|p| p.filter(|p| p.available()));
+
+        let Some(voltage_mv) = loads[..self.voltage_sources].iter().flatten().map(|p| p.bus_voltage_mv).next()
+            .filter(|v| *v > 0.0) else {
+            // keep the estimate, resume the count on the next valid reading
+            self.last_sample = None;
+            self.last.availability = Availability::Unavailable;
+            output.set_payload(self.last);
+            return Ok(());
+        };
+
+        let power_mw: f64 = loads.iter().zip(self.efficiency)
+            .filter_map(|(p, eff)| p.map(|p| p.power_mw / eff))
+            .sum();
+        let current_ma = power_mw / voltage_mv * 1000.0;
+        let ocv_mv = voltage_mv + current_ma * self.internal_resistance_mohms / 1000.0;
+        let cell_mv = voltage_mv / self.cells as f64;
+        let soc_v = self.chemistry.soc_from_cell_mv(ocv_mv / self.cells as f64);
+
+        let now = clock.now();
+        let dt_s = self.last_sample
+            .map_or(0.0, |last| now.as_nanos().saturating_sub(last.as_nanos()) as f32 / 1_000_000_000.0)
+            .min(MAX_STEP_S);
+        self.last_sample = Some(now);
+
+        let soc = match self.soc {
+            None => {
+                eprintln!("BATTERY: starting at soc={:.2} from {:.0}mV open circuit", soc_v, ocv_mv);
+                self.avg_current_ma = current_ma;
+                soc_v
+            },
+            Some(soc) => {
+                let counted = soc - (current_ma * dt_s as f64 / 3600.0 / self.capacity_mah) as f32;
+                counted + (self.voltage_gain * dt_s).min(1.0) * (soc_v - counted)
+            },
+        }.clamp(0.0, 1.0);
+        self.soc = Some(soc);
+
+        let alpha = if self.current_tau_s > 0.0 { (dt_s / self.current_tau_s).min(1.0) as f64 } else { 1.0 };
+        self.avg_current_ma += alpha * (current_ma - self.avg_current_ma);
+
+        self.update_level(soc, cell_mv);
+
+        self.last = BatteryPayload {
+            soc,
+            voltage_mv,
+            ocv_mv,
+            current_ma,
+            remaining_min: (self.avg_current_ma >= MIN_CURRENT_MA)
+                .then(|| (soc as f64 * self.capacity_mah / self.avg_current_ma * 60.0) as f32),
+            level: self.level,
+            availability: Availability::Available,
+        };
+        output.set_payload(self.last);
+        output.metadata.set_status(format!("{:.0}% {:?}", soc * 100.0, self.level));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A resting Li-ion cell well above empty, so only the SoC decides
+    const CELL_MV: f64 = 3700.0;
+
+    fn model() -> BatteryModel {
+        BatteryModel::new(Some(&ComponentConfig::new()), ()).unwrap()
+    }
+
+    fn assert_soc(chemistry: Chemistry, cell_mv: f64, expected: f32) {
+        let soc = chemistry.soc_from_cell_mv(cell_mv);
+        assert!((soc - expected).abs() < 1e-5, "{chemistry:?} at {cell_mv}mV: {soc}, expected {expected}");
+    }
+
+    #[test]
+    fn soc_is_clamped_outside_the_curve() {
+        for chemistry in [Chemistry::LiIon, Chemistry::LiFePo4, Chemistry::NiMh] {
+            let empty = chemistry.empty_cell_mv();
+            assert_soc(chemistry, empty - 500.0, 0.0);
+            assert_soc(chemistry, empty, 0.0);
+            assert_soc(chemistry, 5000.0, 1.0);
+        }
+        assert_soc(Chemistry::LiIon, 4200.0, 1.0);
+    }
+
+    #[test]
+    fn soc_interpolates_between_curve_points() {
+        assert_soc(Chemistry::LiIon, 3700.0, 0.40);
+        assert_soc(Chemistry::LiIon, 3750.0, 0.50);
+        assert_soc(Chemistry::LiIon, 3150.0, 0.025);
+        assert_soc(Chemistry::LiFePo4, 3275.0, 0.55);
+        assert_soc(Chemistry::NiMh, 1225.0, 0.55);
+    }
+
+    #[test]
+    fn soc_rises_with_voltage() {
+        for chemistry in [Chemistry::LiIon, Chemistry::LiFePo4, Chemistry::NiMh] {
+            let mut last = 0.0;
+            let mut cell_mv = chemistry.empty_cell_mv() - 100.0;
+            while cell_mv < 4500.0 {
+                let soc = chemistry.soc_from_cell_mv(cell_mv);
+                assert!(soc >= last, "{chemistry:?} drops to {soc} at {cell_mv}mV");
+                last = soc;
+                cell_mv += 5.0;
+            }
+        }
+    }
+
+    #[test]
+    fn chemistry_names() {
+        assert_eq!(Chemistry::parse("lipo"), Ok(Chemistry::LiIon));
+        assert_eq!(Chemistry::parse("liion"), Ok(Chemistry::LiIon));
+        assert_eq!(Chemistry::parse("lifepo4"), Ok(Chemistry::LiFePo4));
+        assert_eq!(Chemistry::parse("nimh"), Ok(Chemistry::NiMh));
+        assert!(Chemistry::parse("lead").is_err());
+    }
+
+    #[test]
+    fn level_gets_worse_on_the_thresholds() {
+        let mut m = model();
+        m.update_level(0.5, CELL_MV);
+        assert_eq!(m.level, BatteryLevel::Ok);
+        m.update_level(DEFAULT_WARNING_SOC, CELL_MV);
+        assert_eq!(m.level, BatteryLevel::Warning);
+        m.update_level(DEFAULT_CRITICAL_SOC, CELL_MV);
+        assert_eq!(m.level, BatteryLevel::Critical);
+    }
+
+    #[test]
+    fn warning_clears_past_the_hysteresis() {
+        let mut m = model();
+        m.update_level(0.19, CELL_MV);
+        assert_eq!(m.level, BatteryLevel::Warning);
+
+        // coulomb count noise around the threshold doesn't flap
+        m.update_level(0.21, CELL_MV);
+        assert_eq!(m.level, BatteryLevel::Warning);
+        m.update_level(DEFAULT_WARNING_SOC + DEFAULT_LEVEL_HYSTERESIS - 0.001, CELL_MV);
+        assert_eq!(m.level, BatteryLevel::Warning);
+
+        m.update_level(DEFAULT_WARNING_SOC + DEFAULT_LEVEL_HYSTERESIS + 0.001, CELL_MV);
+        assert_eq!(m.level, BatteryLevel::Ok);
+    }
+
+    #[test]
+    fn critical_steps_down_through_warning() {
+        let mut m = model();
+        m.update_level(0.05, CELL_MV);
+        assert_eq!(m.level, BatteryLevel::Critical);
+
+        m.update_level(DEFAULT_CRITICAL_SOC + DEFAULT_LEVEL_HYSTERESIS - 0.001, CELL_MV);
+        assert_eq!(m.level, BatteryLevel::Critical);
+
+        // past critical's hysteresis but inside warning's
+        m.update_level(DEFAULT_CRITICAL_SOC + DEFAULT_LEVEL_HYSTERESIS + 0.001, CELL_MV);
+        assert_eq!(m.level, BatteryLevel::Warning);
+
+        m.update_level(DEFAULT_WARNING_SOC + DEFAULT_LEVEL_HYSTERESIS + 0.001, CELL_MV);
+        assert_eq!(m.level, BatteryLevel::Ok);
+
+        // a big jump (e.g. a fresh pack) goes straight back to Ok
+        m.update_level(0.05, CELL_MV);
+        m.update_level(0.9, CELL_MV);
+        assert_eq!(m.level, BatteryLevel::Ok);
+    }
+
+    #[test]
+    fn cell_under_empty_is_critical_whatever_the_soc() {
+        let mut m = model();
+        m.update_level(0.9, Chemistry::LiIon.empty_cell_mv() - 1.0);
+        assert_eq!(m.level, BatteryLevel::Critical);
+        m.update_level(0.9, CELL_MV);
+        assert_eq!(m.level, BatteryLevel::Ok);
+    }
+}
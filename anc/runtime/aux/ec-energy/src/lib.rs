@@ -0,0 +1,185 @@
+/// Energy accounting for one power rail, fed by its INA219
+
+extern crate cu_bincode as bincode;
+use cu29::prelude::*;
+use cu_powermon::Ina219Payload;
+use cu_irencoder::IrEncoderPayload;
+use bincode::{Encode, Decode};
+use serde::{Serialize, Deserialize};
+
+/// A stalled cycle or a monitor coming back from unavailable doesn't get integrated as one huge step
+pub const DEFAULT_MAX_GAP_MS: u64 = 500;
+/// Below this the per-metre figure is mostly noise
+const MIN_DISTANCE_M: f64 = 0.1;
+const NS_PER_HOUR: f64 = 3_600_000_000_000.0;
+
+/// IMPORTANT: #[serde(transparent)] so that cu-zenoh-src decodes the raw u8 from the wire, same as zsrc-merger
+/// 1 - reset, 0 - idle. Only the rising edge resets the run counters, the since-boot ones never reset.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Encode, Decode)]
+#[serde(transparent)]
+#[derive(Reflect)]
+pub struct BstnEnergyReset(pub u8);
+
+/// Since boot
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, Encode, Decode)]
+#[derive(Reflect)]
+pub struct EnergyBootPayload {
+    pub mwh: f64,
+    pub mah: f64,
+}
+
+/// Since the last BstnEnergyReset (or boot). `mwh_per_m` is None until the wheels have covered
+/// some distance, or when there's no encoder.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, Encode, Decode)]
+#[derive(Reflect)]
+pub struct EnergyRunPayload {
+    pub mwh: f64,
+    pub mah: f64,
+    pub peak_mwatts: f64,
+    pub avg_mwatts: f64,
+    pub elapsed_s: f64,
+    pub distance_m: f64,
+    pub mwh_per_m: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Encode, Decode)]
+struct Accumulator {
+    mwh: f64,
+    mah: f64,
+    peak_mwatts: f64,
+    elapsed_ns: u64,
+    distance_m: f64,
+}
+
+impl Accumulator {
+    fn add(&mut self, reading: &Ina219Payload, dt_ns: u64, distance_m: f64) {
+        let hours = dt_ns as f64 / NS_PER_HOUR;
+        self.mwh += reading.power_mw * hours;
+        self.mah += reading.load_current_ma * hours;
+        self.peak_mwatts = self.peak_mwatts.max(reading.power_mw);
+        self.elapsed_ns += dt_ns;
+        self.distance_m += distance_m;
+    }
+}
+
+/// Integrates power and current over the robot clock into mWh and mAh, since boot and since the
+/// last base station reset. Unavailable readings are skipped, not integrated as zero.
+/// The encoder input is optional, without it the distance stays 0. Wheel geometry comes from the
+/// task config ("wheel_radius_cm", "max_rpm", same as the arbitrator), "max_gap_ms" caps one step.
+#[derive(Reflect)]
+#[reflect(no_field_bounds, from_reflect = false)]
+pub struct EnergyIntegrator {
+    #[reflect(ignore)]
+    boot: Accumulator,
+    #[reflect(ignore)]
+    run: Accumulator,
+    #[reflect(ignore)]
+    last_sample: Option<CuTime>,
+    last_reset: bool,
+    wheel_radius_cm: f32,
+    max_rpm: f32,
+    max_gap_ms: u64,
+}
+
+impl Freezable for EnergyIntegrator {
+    fn freeze<E: bincode::enc::Encoder>(&self, encoder: &mut E) -> Result<(), bincode::error::EncodeError> {
+        Encode::encode(&self.boot, encoder)?;
+        Encode::encode(&self.run, encoder)?;
+        Ok(())
+    }
+
+    fn thaw<D: bincode::de::Decoder>(&mut self, decoder: &mut D) -> Result<(), bincode::error::DecodeError> {
+        self.boot = Decode::decode(decoder)?;
+        self.run = Decode::decode(decoder)?;
+        Ok(())
+    }
+}
+
+impl EnergyIntegrator {
+    /// Path length covered by the wheel pair over dt, either direction counts
+    fn distance_m(&self, encoder: Option<&IrEncoderPayload>, dt_ns: u64) -> f64 {
+        let Some(encoder) = encoder else {
+            return 0.0;
+        };
+        let to_speed_m_s = |norm: Option<f32>| {
+            let omega = norm.unwrap_or(0.0).abs() * self.max_rpm * 2.0 * std::f32::consts::PI / 60.0;
+            (omega * self.wheel_radius_cm / 100.0) as f64
+        };
+        let speed_m_s = (to_speed_m_s(encoder.lmtr_normalized_rpm) + to_speed_m_s(encoder.rmtr_normalized_rpm)) / 2.0;
+        speed_m_s * dt_ns as f64 / 1_000_000_000.0
+    }
+}
+
+impl CuTask for EnergyIntegrator {
+    type Input<'m> = input_msg!('m, Ina219Payload, IrEncoderPayload, BstnEnergyReset);
+    type Output<'m> = output_msg!(EnergyBootPayload, EnergyRunPayload);
+    type Resources<'r> = ();
+
+    fn new(config: Option<&ComponentConfig>, _resources: Self::Resources<'_>) -> CuResult<Self>
+    where Self: Sized
+    {
+        let mut wheel_radius_cm = 3.0;
+        let mut max_rpm = 600.0;
+        let mut max_gap_ms = DEFAULT_MAX_GAP_MS;
+
+        if let Some(ComponentConfig(kv)) = config {
+            let get_f32 = |key: &str| kv.get(key).map(|v| { let f: f64 = v.clone().into(); f as f32 });
+            if let Some(f) = get_f32("wheel_radius_cm") { wheel_radius_cm = f; }
+            if let Some(f) = get_f32("max_rpm") { max_rpm = f; }
+            if let Some(f) = get_f32("max_gap_ms") { max_gap_ms = f as u64; }
+        }
+
+        Ok(Self {
+            boot: Accumulator::default(),
+            run: Accumulator::default(),
+            last_sample: None,
+            last_reset: false,
+            wheel_radius_cm,
+            max_rpm,
+            max_gap_ms,
+        })
+    }
+
+    fn process(&mut self, clock: &RobotClock, input: &Self::Input<'_>, output: &mut Self::Output<'_>)
+    -> CuResult<()>
+    {
+        let (ina219, encoder, reset) = input;
+
+        // only on the rising edge, the ZSrc holds the last value until it goes stale
+        let reset_cmd = reset.payload().map_or(false, |r| r.0 == 1);
+        if reset_cmd && !self.last_reset {
+            eprintln!("EC ENERGY: run counters reset by base station at {:.3} mWh", self.run.mwh);
+            self.run = Accumulator::default();
+        }
+        self.last_reset = reset_cmd;
+
+        let now = clock.now();
+        match ina219.payload().filter(|p| p.available()) {
+            Some(reading) => {
+                if let Some(last) = self.last_sample {
+                    let dt_ns = now.as_nanos().saturating_sub(last.as_nanos())
+                        .min(CuDuration::from_millis(self.max_gap_ms).as_nanos());
+                    let distance_m = self.distance_m(encoder.payload(), dt_ns);
+                    self.boot.add(reading, dt_ns, distance_m);
+                    self.run.add(reading, dt_ns, distance_m);
+                }
+                self.last_sample = Some(now);
+            },
+            // restart the step on the next valid reading
+            None => self.last_sample = None,
+        }
+
+        let elapsed_s = self.run.elapsed_ns as f64 / 1_000_000_000.0;
+        output.0.set_payload(EnergyBootPayload { mwh: self.boot.mwh, mah: self.boot.mah });
+        output.1.set_payload(EnergyRunPayload {
+            mwh: self.run.mwh,
+            mah: self.run.mah,
+            peak_mwatts: self.run.peak_mwatts,
+            avg_mwatts: if elapsed_s > 0.0 { self.run.mwh * 3600.0 / elapsed_s } else { 0.0 },
+            elapsed_s,
+            distance_m: self.run.distance_m,
+            mwh_per_m: (self.run.distance_m >= MIN_DISTANCE_M).then(|| self.run.mwh / self.run.distance_m),
+        });
+        Ok(())
+    }
+}
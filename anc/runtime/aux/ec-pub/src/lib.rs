@@ -1,62 +1,229 @@
-
 /// Zenoh publisher task for topics under EC
 
 extern crate cu_bincode as bincode;
 use cu29::prelude::*;
-use cu_powermon::Ina219Payload;
+use cu_powermon::{Ina219Payload, I2cDeviceStats};
 use bincode::{Encode, Decode};
 use serde::{Serialize, Deserialize};
 
-#[derive(Reflect)]
-pub struct EcPub {}
+/// Once per window, the dashboard doesn't need every 56 Hz sample
+pub const DEFAULT_PUBLISH_RATE_HZ: f64 = 5.0;
+/// Even inside the deadband, so a quiet topic isn't mistaken for a dead one
+pub const DEFAULT_MAX_SILENCE_MS: u64 = 1000;
 
-impl Freezable for EcPub {}
+/// One measurable over one publish window
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, Encode, Decode)]
+#[derive(Reflect)]
+pub struct EcWindow {
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+    pub samples: u32,
+}
 
 #[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Encode, Decode)]
 #[derive(Reflect)]
-pub struct PowerMwatts(pub f64);
+pub struct PowerMwatts(pub EcWindow);
 
 #[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Encode, Decode)]
 #[derive(Reflect)]
-pub struct LoadCurrentMamps(pub f64);
+pub struct LoadCurrentMamps(pub EcWindow);
 
 #[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Encode, Decode)]
 #[derive(Reflect)]
-pub struct BusVoltageMvolts(pub f64);
+pub struct BusVoltageMvolts(pub EcWindow);
 
 #[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Encode, Decode)]
 #[derive(Reflect)]
-pub struct ShuntVoltageMvolts(pub f64);
+pub struct ShuntVoltageMvolts(pub EcWindow);
+
+/// Accumulates one measurable and decides whether its closed window is worth publishing
+#[derive(Debug, Default)]
+struct Channel {
+    sum: f64,
+    min: f64,
+    max: f64,
+    samples: u32,
+    deadband: f64,
+    last: Option<EcWindow>,
+    last_published: Option<CuTime>,
+}
+
+impl Channel {
+    fn with_deadband(deadband: f64) -> Self {
+        Self { deadband, ..Default::default() }
+    }
+
+    fn add(&mut self, v: f64) {
+        if self.samples == 0 {
+            self.min = v;
+            self.max = v;
+        } else {
+            self.min = self.min.min(v);
+            self.max = self.max.max(v);
+        }
+        self.sum += v;
+        self.samples += 1;
+    }
+
+    /// Resets the window. Some when it had samples and either its mean, min or max moved by the
+    /// deadband since the last published one, or nothing went out for max_silence_ns.
+    fn close(&mut self, now: CuTime, max_silence_ns: u64) -> Option<EcWindow> {
+        if self.samples == 0 {
+            return None;
+        }
+        let window = EcWindow {
+            mean: self.sum / self.samples as f64,
+            min: self.min,
+            max: self.max,
+            samples: self.samples,
+        };
+        self.sum = 0.0;
+        self.samples = 0;
+
+        let moved = self.last.is_none_or(|last| {
+            (window.mean - last.mean).abs() >= self.deadband
+                || (window.min - last.min).abs() >= self.deadband
+                || (window.max - last.max).abs() >= self.deadband
+        });
+        let silent = self.last_published
+            .is_none_or(|t| now.as_nanos().saturating_sub(t.as_nanos()) >= max_silence_ns);
+        if !(moved || silent) {
+            return None;
+        }
+
+        self.last = Some(window);
+        self.last_published = Some(now);
+        Some(window)
+    }
+}
+
+/// Aggregates the INA219 readings over windows of 1/"publish_rate_hz" and publishes mean/min/max
+/// per window instead of every sample. A window is dropped when it's within the deadband of the last
+/// published one ("deadband_mwatts", "deadband_mamps", "deadband_bus_mvolts", "deadband_shunt_mvolts",
+/// 0 - publish every window), unless "max_silence_ms" has passed. The I2C counters go out at the same
+/// rate when they changed.
+#[derive(Reflect)]
+#[reflect(no_field_bounds, from_reflect = false)]
+pub struct EcPub {
+    #[reflect(ignore)]
+    power: Channel,
+    #[reflect(ignore)]
+    load_current: Channel,
+    #[reflect(ignore)]
+    bus_voltage: Channel,
+    #[reflect(ignore)]
+    shunt_voltage: Channel,
+    #[reflect(ignore)]
+    i2c: Option<I2cDeviceStats>,
+    #[reflect(ignore)]
+    last_i2c: Option<I2cDeviceStats>,
+    #[reflect(ignore)]
+    last_i2c_published: Option<CuTime>,
+    #[reflect(ignore)]
+    window_start: Option<CuTime>,
+    window_ns: u64,
+    max_silence_ns: u64,
+}
+
+impl Freezable for EcPub {}
 
 impl CuTask for EcPub {
     type Input<'m> = input_msg!('m, Ina219Payload);
-    // f64 - ec_power_mwatts
-    // f64 - ec_load_current_mamps
-    // f64 - ec_bus_voltage_mvolts
-    // f64 - ec_shunt_voltage_mvolts
+    // EcWindow - ec_power_mwatts
+    // EcWindow - ec_load_current_mamps
+    // EcWindow - ec_bus_voltage_mvolts
+    // EcWindow - ec_shunt_voltage_mvolts
+    // I2cDeviceStats - ec_i2c
 
-    type Output<'m> = output_msg!(PowerMwatts, LoadCurrentMamps, BusVoltageMvolts, ShuntVoltageMvolts);
+    type Output<'m> = output_msg!(PowerMwatts, LoadCurrentMamps, BusVoltageMvolts, ShuntVoltageMvolts, I2cDeviceStats);
     type Resources<'r> = ();
 
-    fn new(_config: Option<&ComponentConfig>, _resources: Self::Resources<'_>) -> CuResult<Self>
+    fn new(config: Option<&ComponentConfig>, _resources: Self::Resources<'_>) -> CuResult<Self>
     where Self: Sized
     {
-        Ok(Self {})
+        let mut publish_rate_hz = DEFAULT_PUBLISH_RATE_HZ;
+        let mut max_silence_ms = DEFAULT_MAX_SILENCE_MS;
+        let mut deadbands = [0.0; 4];
+
+        if let Some(ComponentConfig(kv)) = config {
+            let get_f64 = |key: &str| kv.get(key).map(|v| -> f64 { v.clone().into() });
+            if let Some(f) = get_f64("publish_rate_hz") { publish_rate_hz = f; }
+            if let Some(f) = get_f64("max_silence_ms") { max_silence_ms = f as u64; }
+            let keys = ["deadband_mwatts", "deadband_mamps", "deadband_bus_mvolts", "deadband_shunt_mvolts"];
+            for (deadband, key) in deadbands.iter_mut().zip(keys) {
+                if let Some(f) = get_f64(key) { *deadband = f; }
+            }
+        }
+
+        if publish_rate_hz <= 0.0 {
+            return Err(CuError::from(format!("EcPub: publish_rate_hz must be positive, got {publish_rate_hz}")));
+        }
+        if deadbands.iter().any(|d| *d < 0.0) {
+            return Err(CuError::from("EcPub: deadbands can't be negative"));
+        }
+        let [power, load_current, bus_voltage, shunt_voltage] = deadbands;
+
+        Ok(Self {
+            power: Channel::with_deadband(power),
+            load_current: Channel::with_deadband(load_current),
+            bus_voltage: Channel::with_deadband(bus_voltage),
+            shunt_voltage: Channel::with_deadband(shunt_voltage),
+            i2c: None,
+            last_i2c: None,
+            last_i2c_published: None,
+            window_start: None,
+            window_ns: (1_000_000_000.0 / publish_rate_hz) as u64,
+            max_silence_ns: CuDuration::from_millis(max_silence_ms).as_nanos(),
+        })
     }
 
-    fn process(&mut self, _clock: &RobotClock, input: &Self::Input<'_>, output: &mut Self::Output<'_>)
+    fn process(&mut self, clock: &RobotClock, input: &Self::Input<'_>, output: &mut Self::Output<'_>)
     -> CuResult<()>
     {
-        if let Some(ina219_payload)= input.payload() {
-            let power = ina219_payload.power;
-            let load_current = ina219_payload.load_current;
-            let bus_voltage = ina219_payload.bus_voltage * 1000.0;
-            let shunt_voltage = ina219_payload.shunt_voltage;
-
-            output.0.set_payload(PowerMwatts(power));
-            output.1.set_payload(LoadCurrentMamps(load_current));
-            output.2.set_payload(BusVoltageMvolts(bus_voltage));
-            output.3.set_payload(ShuntVoltageMvolts(shunt_voltage));
+        if let Some(ina219_payload) = input.payload() {
+            // the counters go out either way, they're what says why a monitor is unavailable
+            self.i2c = Some(ina219_payload.i2c);
+
+            // An unavailable monitor adds nothing, zeros would read as a dead rail. A window with no
+            // samples publishes nothing.
+            if ina219_payload.available() {
+                // cu-powermon already reports in the topic units
+                self.power.add(ina219_payload.power_mw);
+                self.load_current.add(ina219_payload.load_current_ma);
+                self.bus_voltage.add(ina219_payload.bus_voltage_mv);
+                self.shunt_voltage.add(ina219_payload.shunt_voltage_mv);
+            }
+        }
+
+        let now = clock.now();
+        let start = *self.window_start.get_or_insert(now);
+        if now.as_nanos().saturating_sub(start.as_nanos()) < self.window_ns {
+            return Ok(());
+        }
+        self.window_start = Some(now);
+
+        if let Some(w) = self.power.close(now, self.max_silence_ns) {
+            output.0.set_payload(PowerMwatts(w));
+        }
+        if let Some(w) = self.load_current.close(now, self.max_silence_ns) {
+            output.1.set_payload(LoadCurrentMamps(w));
+        }
+        if let Some(w) = self.bus_voltage.close(now, self.max_silence_ns) {
+            output.2.set_payload(BusVoltageMvolts(w));
+        }
+        if let Some(w) = self.shunt_voltage.close(now, self.max_silence_ns) {
+            output.3.set_payload(ShuntVoltageMvolts(w));
+        }
+
+        if let Some(stats) = self.i2c {
+            let silent = self.last_i2c_published
+                .is_none_or(|t| now.as_nanos().saturating_sub(t.as_nanos()) >= self.max_silence_ns);
+            if self.last_i2c != Some(stats) || silent {
+                output.4.set_payload(stats);
+                self.last_i2c = Some(stats);
+                self.last_i2c_published = Some(now);
+            }
         }
 
         Ok(())
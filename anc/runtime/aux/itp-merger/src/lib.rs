@@ -14,25 +14,35 @@ impl Freezable for ItpMerger {}
 #[derive(Reflect)]
 pub struct ItpAccelerateCmd(pub u8);
 
+/// 1 - sweep the camera through the scan schedule (only while stationary), 0 - stop. Level, not edge.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Encode, Decode)]
+#[serde(transparent)]
+#[derive(Reflect)]
+pub struct ItpPanScanCmd(pub u8);
+
 #[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Encode, Decode)]
 #[derive(Reflect)]
 #[reflect(no_field_bounds, from_reflect = false)]
 pub struct ItpTopicsOutputPayload {
-    pub accelerate_cmd: bool
+    pub accelerate_cmd: bool,
+    pub pan_scan_cmd: bool,
 }
 
 #[derive(Reflect)]
 #[reflect(no_field_bounds, from_reflect = false)]
 pub struct ItpMerger {
-    last_cmd: bool
+    last_cmd: bool,
+    pan_scan_cmd: bool,
 }
 
 impl CuTask for ItpMerger {
     // u8 - itp_accelerate_cmd
+    // u8 - itp_pan_scan_cmd
 
     type Input<'m>
     = input_msg!('m,
-            ItpAccelerateCmd
+            ItpAccelerateCmd,
+            ItpPanScanCmd
         );
     type Output<'m> = output_msg!(ItpTopicsOutputPayload);
     type Resources<'r> = ();
@@ -40,13 +50,21 @@ impl CuTask for ItpMerger {
     fn new(_config: Option<&ComponentConfig>, _resources: Self::Resources<'_>) -> CuResult<Self>
         where
             Self: Sized {
-        Ok(Self { last_cmd: false })
+        Ok(Self { last_cmd: false, pan_scan_cmd: false })
     }
 
     fn process(&mut self, _clock: &RobotClock, input: &Self::Input<'_>, output: &mut Self::Output<'_>)
     -> CuResult<()>
     {
-        if let Some(itp_accelerate_cmd) = input.payload()
+        let (itp_accelerate, itp_pan_scan) = *input;
+
+        // held, ZSrc keeps the last value
+        if let Some(itp_pan_scan_cmd) = itp_pan_scan.payload() {
+            self.pan_scan_cmd = itp_pan_scan_cmd.0 == 1;
+        }
+
+        let mut final_cmd = false;
+        if let Some(itp_accelerate_cmd) = itp_accelerate.payload()
         {
             let cmd = match itp_accelerate_cmd.0 {
                 0 => false,
@@ -54,16 +72,19 @@ impl CuTask for ItpMerger {
                 _ => false
             };
 
-            let final_cmd = match (self.last_cmd, cmd) {
+            final_cmd = match (self.last_cmd, cmd) {
                 (false, true) => true,
                 _ => false
             };
 
             self.last_cmd = cmd;
+        }
 
+        if itp_accelerate.payload().is_some() || itp_pan_scan.payload().is_some() {
             output.set_payload(
                 ItpTopicsOutputPayload {
-                    accelerate_cmd: final_cmd
+                    accelerate_cmd: final_cmd,
+                    pan_scan_cmd: self.pan_scan_cmd,
                 }
             );
         }
@@ -0,0 +1,134 @@
+/// Motor calibration data shared by the speed controllers and the speed correction summer.
+/// The L298N + DC motor combo has a big stiction dead-band and a nonlinear duty -> rpm curve,
+/// so a single linear feed-forward gain only fits well around one operating point.
+/// This crate holds per-motor lookup tables (normalized rpm -> duty cycle) with separate
+/// forward/reverse curves, loaded from a RON calibration file.
+
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use cu_propulsion::WheelDirection;
+
+/// Below this normalized rpm the motor is considered commanded to stop, and no duty is applied.
+pub const MIN_NORMALIZED_RPM_CMD: f32 = 0.001;
+
+/// Feed-forward lookup table for one motor in one direction.
+/// `points` are (normalized_rpm, duty_cycle) pairs. They don't need to be sorted in the file.
+/// The first point is effectively the dead-band: any nonzero speed command gets at least its duty.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FfCurve {
+    pub points: Vec<(f32, f32)>,
+}
+
+impl FfCurve {
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    fn sort(&mut self) {
+        self.points.sort_by(|a, b| a.0.total_cmp(&b.0));
+    }
+
+    /// Linear interpolation of the duty cycle needed to reach `normalized_rpm`.
+    /// Clamps to the end points outside the calibrated range.
+    /// Expects `points` to be sorted, which `CalibrationFile::load` takes care of.
+    pub fn duty_for(&self, normalized_rpm: f32) -> f32 {
+        if normalized_rpm < MIN_NORMALIZED_RPM_CMD {
+            return 0.0;
+        }
+
+        let (Some(first), Some(last)) = (self.points.first(), self.points.last()) else {
+            return 0.0;
+        };
+
+        if normalized_rpm <= first.0 {
+            return first.1.clamp(0.0, 1.0);
+        }
+        if normalized_rpm >= last.0 {
+            return last.1.clamp(0.0, 1.0);
+        }
+
+        for w in self.points.windows(2) {
+            let ((x0, y0), (x1, y1)) = (w[0], w[1]);
+            if normalized_rpm >= x0 && normalized_rpm <= x1 {
+                if x1 - x0 <= f32::EPSILON {
+                    return y1.clamp(0.0, 1.0);
+                }
+                let t = (normalized_rpm - x0) / (x1 - x0);
+                return (y0 + t * (y1 - y0)).clamp(0.0, 1.0);
+            }
+        }
+
+        last.1.clamp(0.0, 1.0)
+    }
+}
+
+/// Forward and reverse feed-forward curves for one motor, plus the characterization results they
+/// were derived from. Everything except the curves is informational and optional in the file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MotorCalibration {
+    pub forward: FfCurve,
+    pub reverse: FfCurve,
+    /// Lowest duty cycle that gets the wheel turning
+    #[serde(default)]
+    pub deadband_duty: Option<f32>,
+    /// Least-squares slope of normalized rpm vs duty above the dead-band
+    #[serde(default)]
+    pub gain: Option<f32>,
+    /// First order time constant (63.2% rise time) of a duty step
+    #[serde(default)]
+    pub time_constant_ms: Option<f32>,
+    /// Highest load current seen while the wheel was stalled in the dead-band
+    #[serde(default)]
+    pub stall_current_mamps: Option<f32>,
+    /// Measured rpm at full duty, for the encoder's `max_rpm` and the arbitrator's `max_rpm`
+    #[serde(default)]
+    pub max_rpm: Option<f32>,
+}
+
+impl MotorCalibration {
+    /// Returns None if there's no curve for that direction, so the caller can fall back to the scalar gain
+    pub fn duty_for(&self, normalized_rpm: f32, direction: WheelDirection) -> Option<f32> {
+        let curve = match direction {
+            WheelDirection::Forward => &self.forward,
+            WheelDirection::Reverse => &self.reverse,
+            WheelDirection::Stop => return Some(0.0),
+        };
+
+        if curve.is_empty() {
+            None
+        } else {
+            Some(curve.duty_for(normalized_rpm))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationFile {
+    pub lmtr: MotorCalibration,
+    pub rmtr: MotorCalibration,
+}
+
+impl CalibrationFile {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read motor calibration file {}: {e}", path.display()))?;
+        let mut calib: CalibrationFile = ron::from_str(&contents)
+            .map_err(|e| format!("Failed to parse motor calibration file {}: {e}", path.display()))?;
+
+        calib.lmtr.forward.sort();
+        calib.lmtr.reverse.sort();
+        calib.rmtr.forward.sort();
+        calib.rmtr.reverse.sort();
+        Ok(calib)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| format!("Failed to serialize motor calibration: {e}"))?;
+        fs::write(path, contents)
+            .map_err(|e| format!("Failed to write motor calibration file {}: {e}", path.display()))
+    }
+}
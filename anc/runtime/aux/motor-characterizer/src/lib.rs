@@ -0,0 +1,442 @@
+/// Motor characterization task. Sweeps each motor's duty cycle in steps through cu-propulsion,
+/// records steady-state rpm from cu-irencoder and load current from cu-powermon, then writes a
+/// motor-calib file (dead-band, gain curve, time constant, stall current, max rpm) that the speed
+/// correction summer can load. Only one motor is driven at a time. Runs in the palanuk-characterize
+/// binary, never in the main runtime. The robot should be on a stand with the wheels free.
+
+use cu29::prelude::*;
+use cu_propulsion::{PropulsionPayload, WheelDirection};
+use cu_irencoder::IrEncoderPayload;
+use cu_powermon::Ina219Payload;
+use motor_calib::{CalibrationFile, FfCurve, MotorCalibration};
+
+pub const DEFAULT_DUTY_STEP: f32 = 0.05;
+pub const DEFAULT_MAX_DUTY: f32 = 1.0;
+pub const DEFAULT_SETTLE_MS: u64 = 1500;
+pub const DEFAULT_SAMPLE_MS: u64 = 500;
+pub const DEFAULT_REST_MS: u64 = 1000;
+pub const DEFAULT_MOVING_THRESHOLD: f32 = 0.02;
+pub const DEFAULT_ENCODER_MAX_RPM: f32 = 600.0;
+pub const DEFAULT_OUTPUT_FILE: &str = "motor_calib.ron";
+/// Steps smaller than this (normalized rpm) are too noisy to get a time constant out of
+pub const MIN_TAU_STEP_DELTA: f32 = 0.05;
+const TAU_FRACTION: f32 = 0.632;
+const TRACE_CAPACITY: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Motor {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub enum CharacterizerState {
+    #[default]
+    Resting,
+    Settling,
+    Sampling,
+    Done,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct StepResult {
+    duty: f32,
+    normalized_rpm: f32,
+    current_mamps: f32,
+    tau_ms: Option<f32>,
+    /// Encoder readings averaged into normalized_rpm
+    samples: u32,
+}
+
+#[derive(Reflect)]
+#[reflect(no_field_bounds, from_reflect = false)]
+pub struct MotorCharacterizer {
+    duty_step: f32,
+    max_duty: f32,
+    settle_ms: u64,
+    sample_ms: u64,
+    rest_ms: u64,
+    moving_threshold: f32,
+    encoder_max_rpm: f32,
+    #[reflect(ignore)]
+    output_file: String,
+    #[reflect(ignore)]
+    phases: Vec<(Motor, WheelDirection)>,
+    #[reflect(ignore)]
+    phase_idx: usize,
+    #[reflect(ignore)]
+    state: CharacterizerState,
+    /// Robot clock time the current state was entered
+    #[reflect(ignore)]
+    state_started: CuTime,
+    duty: f32,
+    #[reflect(ignore)]
+    prev_step_rpm: f32,
+    /// (elapsed ms since step start, normalized rpm) for the step in progress
+    #[reflect(ignore)]
+    trace: Vec<(f32, f32)>,
+    #[reflect(ignore)]
+    rpm_acc: (f32, u32),
+    #[reflect(ignore)]
+    current_acc: (f32, u32),
+    #[reflect(ignore)]
+    steps: Vec<StepResult>,
+    #[reflect(ignore)]
+    calib: CalibrationFile,
+}
+
+impl Freezable for MotorCharacterizer {}
+
+impl CuTask for MotorCharacterizer {
+    type Input<'m> = input_msg!('m, IrEncoderPayload, Ina219Payload, Ina219Payload);
+    type Output<'m> = output_msg!(PropulsionPayload);
+    type Resources<'r> = ();
+
+    fn new(config: Option<&ComponentConfig>, _resources: Self::Resources<'_>) -> CuResult<Self>
+    where Self: Sized
+    {
+        let ComponentConfig(kv) =
+            config.ok_or("No ComponentConfig specified for MotorCharacterizer in RON")?;
+
+        let duty_step: f32 = kv.get("duty_step")
+            .map(|v| { let f: f64 = v.clone().into(); f as f32 })
+            .unwrap_or(DEFAULT_DUTY_STEP);
+
+        let max_duty: f32 = kv.get("max_duty")
+            .map(|v| { let f: f64 = v.clone().into(); f as f32 })
+            .unwrap_or(DEFAULT_MAX_DUTY);
+
+        let settle_ms: u64 = kv.get("settle_ms")
+            .map(|v| { let f: f64 = v.clone().into(); f as u64 })
+            .unwrap_or(DEFAULT_SETTLE_MS);
+
+        let sample_ms: u64 = kv.get("sample_ms")
+            .map(|v| { let f: f64 = v.clone().into(); f as u64 })
+            .unwrap_or(DEFAULT_SAMPLE_MS);
+
+        let rest_ms: u64 = kv.get("rest_ms")
+            .map(|v| { let f: f64 = v.clone().into(); f as u64 })
+            .unwrap_or(DEFAULT_REST_MS);
+
+        let moving_threshold: f32 = kv.get("moving_threshold")
+            .map(|v| { let f: f64 = v.clone().into(); f as f32 })
+            .unwrap_or(DEFAULT_MOVING_THRESHOLD);
+
+        // must match max_rpm of the encoder-pair task, since its readings are normalized by it
+        let encoder_max_rpm: f32 = kv.get("encoder_max_rpm")
+            .map(|v| { let f: f64 = v.clone().into(); f as f32 })
+            .unwrap_or(DEFAULT_ENCODER_MAX_RPM);
+
+        let output_file: String = kv.get("output_file")
+            .map(|v| v.clone().into())
+            .unwrap_or(String::from(DEFAULT_OUTPUT_FILE));
+
+        let include_reverse = match kv.get("directions") {
+            Some(v) => {
+                let s: String = v.clone().into();
+                match s.as_str() {
+                    "both" => true,
+                    "forward" => false,
+                    _ => return Err(CuError::from(format!("Invalid directions value: \"{s}\". Valid values: \"both\", \"forward\""))),
+                }
+            },
+            None => true,
+        };
+
+        if duty_step <= 0.0 || max_duty <= 0.0 || max_duty > 1.0 {
+            return Err(CuError::from("MotorCharacterizer: duty_step must be > 0 and max_duty must be in (0, 1]"));
+        }
+
+        let mut phases = vec![(Motor::Left, WheelDirection::Forward)];
+        if include_reverse {
+            phases.push((Motor::Left, WheelDirection::Reverse));
+        }
+        phases.push((Motor::Right, WheelDirection::Forward));
+        if include_reverse {
+            phases.push((Motor::Right, WheelDirection::Reverse));
+        }
+
+        Ok(Self {
+            duty_step,
+            max_duty,
+            settle_ms,
+            sample_ms,
+            rest_ms,
+            moving_threshold,
+            encoder_max_rpm,
+            output_file,
+            phases,
+            phase_idx: 0,
+            state: CharacterizerState::default(),
+            state_started: CuTime::default(),
+            duty: 0.0,
+            prev_step_rpm: 0.0,
+            trace: Vec::with_capacity(TRACE_CAPACITY),
+            rpm_acc: (0.0, 0),
+            current_acc: (0.0, 0),
+            steps: Vec::new(),
+            calib: CalibrationFile::default(),
+        })
+    }
+
+    fn start(&mut self, clock: &RobotClock) -> CuResult<()> {
+        self.state = CharacterizerState::Resting;
+        self.state_started = clock.now();
+        Ok(())
+    }
+
+    fn process(&mut self, clock: &RobotClock, input: &Self::Input<'_>, output: &mut Self::Output<'_>)
+    -> CuResult<()>
+    {
+        let (encoder, lmtr_ina219, rmtr_ina219) = *input;
+
+        if self.state == CharacterizerState::Done {
+            output.set_payload(PropulsionPayload::default());
+            return Ok(());
+        }
+
+        let (motor, direction) = self.phases[self.phase_idx];
+
+        // readings are signed, the sweep only cares about magnitude
+        let rpm = encoder.payload().and_then(|e| match motor {
+            Motor::Left => e.lmtr_normalized_rpm,
+            Motor::Right => e.rmtr_normalized_rpm,
+        }).map(f32::abs);
+        let current_mamps = match motor {
+            Motor::Left => lmtr_ina219.payload(),
+            Motor::Right => rmtr_ina219.payload(),
+        }.filter(|p| p.available()).map(|p| p.load_current_ma.abs() as f32);
+
+        self.advance(clock.now(), rpm, current_mamps);
+
+        let drive = self.state == CharacterizerState::Settling || self.state == CharacterizerState::Sampling;
+        let mut payload = PropulsionPayload::default();
+        if drive {
+            match motor {
+                Motor::Left => {
+                    payload.left_enable = true;
+                    payload.left_speed = self.duty;
+                    payload.left_direction = direction;
+                },
+                Motor::Right => {
+                    payload.right_enable = true;
+                    payload.right_speed = self.duty;
+                    payload.right_direction = direction;
+                }
+            }
+        }
+
+        output.set_payload(payload);
+        output.metadata.set_status(format!("{:?} {:?} d={:.2}", motor, self.state, self.duty));
+        Ok(())
+    }
+}
+
+impl MotorCharacterizer {
+    /// One cycle of the sweep state machine, `rpm` and `current_mamps` are magnitudes for the motor
+    /// of the current phase
+    fn advance(&mut self, now: CuTime, rpm: Option<f32>, current_mamps: Option<f32>) {
+        if self.state == CharacterizerState::Done {
+            return;
+        }
+        let (motor, direction) = self.phases[self.phase_idx];
+        let elapsed_ms = elapsed_ms(now, self.state_started);
+
+        match self.state {
+            CharacterizerState::Resting => {
+                if elapsed_ms >= self.rest_ms {
+                    self.duty = 0.0;
+                    self.prev_step_rpm = rpm.unwrap_or(0.0);
+                    self.next_step(now);
+                    eprintln!("CHAR: {:?} {:?} sweep started", motor, direction);
+                }
+            },
+            CharacterizerState::Settling => {
+                if let Some(rpm) = rpm {
+                    self.trace.push((elapsed_ms as f32, rpm));
+                }
+                if elapsed_ms >= self.settle_ms {
+                    self.state = CharacterizerState::Sampling;
+                    // sample_ms counts from here, the trace keeps counting from the step start
+                    self.state_started = now;
+                    self.rpm_acc = (0.0, 0);
+                    self.current_acc = (0.0, 0);
+                }
+            },
+            CharacterizerState::Sampling => {
+                if let Some(rpm) = rpm {
+                    self.trace.push(((self.settle_ms + elapsed_ms) as f32, rpm));
+                    self.rpm_acc.0 += rpm;
+                    self.rpm_acc.1 += 1;
+                }
+                if let Some(current) = current_mamps {
+                    self.current_acc.0 += current;
+                    self.current_acc.1 += 1;
+                }
+                if elapsed_ms >= self.sample_ms {
+                    self.finish_step(motor, direction);
+
+                    if self.duty + self.duty_step > self.max_duty + f32::EPSILON {
+                        self.finish_phase(now, motor, direction);
+                    } else {
+                        self.next_step(now);
+                    }
+                }
+            },
+            CharacterizerState::Done => (),
+        }
+    }
+
+    fn next_step(&mut self, now: CuTime) {
+        self.duty = (self.duty + self.duty_step).min(self.max_duty);
+        self.trace.clear();
+        self.state = CharacterizerState::Settling;
+        self.state_started = now;
+    }
+
+    fn finish_step(&mut self, motor: Motor, direction: WheelDirection) {
+        let normalized_rpm = if self.rpm_acc.1 > 0 { self.rpm_acc.0 / self.rpm_acc.1 as f32 } else { 0.0 };
+        let current_mamps = if self.current_acc.1 > 0 { self.current_acc.0 / self.current_acc.1 as f32 } else { 0.0 };
+
+        let delta = normalized_rpm - self.prev_step_rpm;
+        let tau_ms = if delta >= MIN_TAU_STEP_DELTA {
+            let target = self.prev_step_rpm + TAU_FRACTION * delta;
+            self.trace.iter().find(|(_, rpm)| *rpm >= target).map(|(t, _)| *t)
+        } else {
+            None
+        };
+
+        eprintln!("CHAR: {:?} {:?} duty={:.3} rpm={:.4} ({} samples) current={:.1}mA tau={:?}ms",
+            motor, direction, self.duty, normalized_rpm, self.rpm_acc.1, current_mamps, tau_ms);
+
+        self.steps.push(StepResult { duty: self.duty, normalized_rpm, current_mamps, tau_ms, samples: self.rpm_acc.1 });
+        self.prev_step_rpm = normalized_rpm;
+    }
+
+    fn finish_phase(&mut self, now: CuTime, motor: Motor, direction: WheelDirection) {
+        let steps = std::mem::take(&mut self.steps);
+        let calib = match motor {
+            Motor::Left => &mut self.calib.lmtr,
+            Motor::Right => &mut self.calib.rmtr,
+        };
+        summarize_phase(&steps, direction, self.moving_threshold, self.encoder_max_rpm, calib);
+
+        self.phase_idx += 1;
+        self.duty = 0.0;
+        self.state_started = now;
+
+        if self.phase_idx >= self.phases.len() {
+            self.state = CharacterizerState::Done;
+            match self.calib.save(&self.output_file) {
+                Ok(_) => info!("MotorCharacterizer: calibration written to {}", path = self.output_file.as_str()),
+                Err(e) => error!("MotorCharacterizer: {}", e.as_str()),
+            }
+            eprintln!("CHAR: done, calibration written to {}", self.output_file);
+        } else {
+            self.state = CharacterizerState::Resting;
+        }
+    }
+}
+
+/// Turns the steps of one sweep into a feed-forward curve, and fills in the scalar results.
+/// Scalars come from the forward sweep only, the reverse sweep only contributes its curve.
+fn summarize_phase(steps: &[StepResult], direction: WheelDirection, moving_threshold: f32, encoder_max_rpm: f32, calib: &mut MotorCalibration) {
+    let moving: Vec<&StepResult> = steps.iter().filter(|s| s.normalized_rpm > moving_threshold).collect();
+
+    // keep the curve strictly increasing in rpm so the interpolation stays monotonic
+    let mut curve = FfCurve::default();
+    for s in &moving {
+        if curve.points.last().is_none_or(|(rpm, _)| s.normalized_rpm > *rpm) {
+            curve.points.push((s.normalized_rpm, s.duty));
+        }
+    }
+
+    match direction {
+        WheelDirection::Forward => calib.forward = curve,
+        WheelDirection::Reverse => {
+            calib.reverse = curve;
+            return;
+        },
+        WheelDirection::Stop => return,
+    }
+
+    calib.deadband_duty = moving.first().map(|s| s.duty);
+
+    calib.stall_current_mamps = steps.iter()
+        .filter(|s| s.normalized_rpm <= moving_threshold)
+        .map(|s| s.current_mamps)
+        .reduce(f32::max);
+
+    let taus: Vec<f32> = moving.iter().filter_map(|s| s.tau_ms).collect();
+    calib.time_constant_ms = if taus.is_empty() { None } else { Some(taus.iter().sum::<f32>() / taus.len() as f32) };
+
+    calib.gain = if moving.len() >= 2 {
+        let n = moving.len() as f32;
+        let mean_x = moving.iter().map(|s| s.duty).sum::<f32>() / n;
+        let mean_y = moving.iter().map(|s| s.normalized_rpm).sum::<f32>() / n;
+        let sxy: f32 = moving.iter().map(|s| (s.duty - mean_x) * (s.normalized_rpm - mean_y)).sum();
+        let sxx: f32 = moving.iter().map(|s| (s.duty - mean_x).powi(2)).sum();
+        if sxx > f32::EPSILON { Some(sxy / sxx) } else { None }
+    } else {
+        None
+    };
+
+    calib.max_rpm = steps.last().map(|s| s.normalized_rpm * encoder_max_rpm);
+}
+
+fn elapsed_ms(now: CuTime, since: CuTime) -> u64 {
+    now.as_nanos().saturating_sub(since.as_nanos()) / 1_000_000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const CYCLE: Duration = Duration::from_millis(18);
+
+    fn characterizer() -> MotorCharacterizer {
+        let mut config = ComponentConfig::new();
+        config.set("directions", String::from("forward"));
+        MotorCharacterizer::new(Some(&config), ()).unwrap()
+    }
+
+    /// Runs cycles at the runtime rate until a step is finished
+    fn run_until_step(ch: &mut MotorCharacterizer, (clock, mock): &(RobotClock, RobotClockMock)) {
+        let steps = ch.steps.len();
+        while ch.steps.len() == steps {
+            mock.increment(CYCLE);
+            ch.advance(clock.now(), Some(0.5), Some(200.0));
+        }
+    }
+
+    #[test]
+    fn samples_for_the_whole_sample_window() {
+        let clock = RobotClock::mock();
+        let mut ch = characterizer();
+        run_until_step(&mut ch, &clock);
+        run_until_step(&mut ch, &clock);
+
+        let expected = (DEFAULT_SAMPLE_MS / CYCLE.as_millis() as u64) as u32;
+        for step in &ch.steps {
+            assert!(step.samples >= expected, "{} samples, expected about {expected}", step.samples);
+            assert!((step.normalized_rpm - 0.5).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn trace_is_timed_from_the_step_start() {
+        let (clock, mock) = RobotClock::mock();
+        let mut ch = characterizer();
+        while ch.state != CharacterizerState::Sampling {
+            mock.increment(CYCLE);
+            ch.advance(clock.now(), Some(0.5), None);
+        }
+        mock.increment(CYCLE);
+        ch.advance(clock.now(), Some(0.5), None);
+
+        let (t, _) = *ch.trace.last().unwrap();
+        let cycle_ms = CYCLE.as_millis() as u64;
+        assert!(t >= DEFAULT_SETTLE_MS as f32 && t < (DEFAULT_SETTLE_MS + 3 * cycle_ms) as f32, "trace at {t}ms");
+    }
+}
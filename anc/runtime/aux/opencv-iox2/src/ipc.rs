@@ -5,6 +5,8 @@ pub const SERVICE_NAME_ABS_LINE_GRADIENT: &str = "nsm/abs_line_gradient";
 pub const SERVICE_NAME_CORNER_DETECTED: &str = "nsm/corner_detected";
 pub const SERVICE_NAME_CORNER_DIRECTION: &str = "nsm/corner_direction";
 pub const SERVICE_NAME_CORNER_POINT: &str = "nsm/corner_point";
+/// The other direction, copper -> NSM
+pub const SERVICE_NAME_PAN_ANGLE: &str = "nsm/pan_angle";
 
 // IPC types
 #[repr(C)]
@@ -45,3 +47,11 @@ pub struct CornerPointMsg {
     pub x: f32,
     pub y: f32,
 }
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, ZeroCopySend)]
+#[type_name("PanAngleMsg")]
+pub struct PanAngleMsg {
+    pub angle_deg: f32,
+    pub settled: u8,
+}
@@ -9,6 +9,8 @@ use serde::{Deserialize, Serialize};
 
 use crate::ipc::{AbsLineGradientMsg, CornerDetectedMsg, CornerDirectionMsg, CornerPointMsg, HeadingErrorMsg, SERVICE_NAME_ABS_LINE_GRADIENT, SERVICE_NAME_CORNER_DETECTED, SERVICE_NAME_CORNER_DIRECTION, SERVICE_NAME_CORNER_POINT, SERVICE_NAME_HEADING_ERROR};
 mod ipc;
+mod pan;
+pub use pan::*;
 
 #[derive(Default, Debug, Clone, Copy, Encode, Decode, PartialEq, Serialize, Deserialize)]
 #[derive(Reflect)]
@@ -0,0 +1,48 @@
+use cu29::prelude::*;
+use iceoryx2::prelude::*;
+use iceoryx2::prelude::ipc_threadsafe::Service;
+use iceoryx2::port::publisher::*;
+use cu_cam_pan::PanStatePayload;
+
+use crate::ipc::{PanAngleMsg, SERVICE_NAME_PAN_ANGLE};
+
+/// Tells the NSM where the camera is pointing so it can take the pan out of the heading error.
+/// Sends the estimated angle every cycle, the NSM keeps the latest.
+#[derive(Reflect)]
+#[reflect(no_field_bounds, from_reflect = false)]
+pub struct NsmPanAnglePub {
+    #[reflect(ignore)]
+    pan_angle_pub: Publisher<Service, PanAngleMsg, ()>,
+}
+
+impl Freezable for NsmPanAnglePub {}
+
+impl CuSinkTask for NsmPanAnglePub {
+    type Input<'m> = input_msg!(PanStatePayload);
+    type Resources<'r> = ();
+
+    fn new(_config: Option<&ComponentConfig>, _resources: Self::Resources<'_>) -> CuResult<Self>
+    where
+        Self: Sized,
+    {
+        let pan_angle_node = NodeBuilder::new().create::<Service>().map_err(|e| -> CuError {CuError::from(format!("build node failed: {:?}", e))})?;
+        let pan_angle_service = pan_angle_node.service_builder(&ServiceName::new(SERVICE_NAME_PAN_ANGLE).unwrap())
+            .publish_subscribe::<PanAngleMsg>()
+            .open_or_create().map_err(|e| -> CuError {CuError::from(format!("build pan_angle service failed: {:?}", e))})?;
+        let pan_angle_pub = pan_angle_service.publisher_builder().create().map_err(|e| -> CuError {CuError::from(format!("build pan_angle pub failed: {:?}", e))})?;
+
+        Ok(Self { pan_angle_pub })
+    }
+
+    fn process(&mut self, _clock: &RobotClock, input: &Self::Input<'_>) -> CuResult<()> {
+        let Some(pan_state) = input.payload() else {
+            return Ok(());
+        };
+
+        self.pan_angle_pub.send_copy(PanAngleMsg {
+            angle_deg: pan_state.angle_deg,
+            settled: pan_state.settled as u8,
+        }).map_err(|e| -> CuError {CuError::from(format!("iox2 pan_angle send failed: {:?}", e))})?;
+        Ok(())
+    }
+}
@@ -2,13 +2,27 @@ extern crate cu_bincode as bincode;
 use cu29::prelude::*;
 use bincode::{Decode, Encode};
 use cu_cam_pan::{CameraPanningPayload, PositionCommand};
-use propulsion_adapter::PropulsionAdapterOutputPayload;
+use propulsion_adapter::{LoopState, PropulsionAdapterOutputPayload};
+use arbitrator::{ManeuverPayload, ManeuverPhase};
+use opencv_iox2::CornerDirection;
+use itp_merger::ItpTopicsOutputPayload;
 
+pub const DEFAULT_CORNER_PAN_DEG: f32 = 30.0;
+pub const DEFAULT_SCAN_MAX_SPEED: f32 = 0.01;
+
+/// Open loop: forwards the base station forcepan.
+/// Closed loop with corner_pan enabled: pans corner_pan_deg toward the corner while the arbitrator
+/// is waiting to steer or steering, so the lane stays in view, and back to front once the maneuver is
+/// done. forcepan is ignored then. With corner_pan disabled closed loop forwards forcepan too.
+/// ITP pan_scan overrides all of the above, but only while the robot is commanded to stand still.
 #[derive(Reflect)]
 #[reflect(no_field_bounds, from_reflect = false)]
 pub struct PannerAdapter {
     #[reflect(ignore)]
-    cmd: PositionCommand
+    cmd: PositionCommand,
+    corner_pan: bool,
+    corner_pan_deg: f32,
+    scan_max_speed: f32,
 }
 
 impl Freezable for PannerAdapter {
@@ -24,21 +38,71 @@ impl Freezable for PannerAdapter {
 }
 
 impl CuTask for PannerAdapter {
-    type Input<'m> = input_msg!('m, PropulsionAdapterOutputPayload);
+    type Input<'m> = input_msg!('m, PropulsionAdapterOutputPayload, ManeuverPayload, ItpTopicsOutputPayload);
     type Output<'m> = output_msg!(CameraPanningPayload);
     type Resources<'r> = ();
 
-    fn new(_config: Option<&ComponentConfig>, _resources: Self::Resources<'_>) -> CuResult<Self>
+    fn new(config: Option<&ComponentConfig>, _resources: Self::Resources<'_>) -> CuResult<Self>
     where Self: Sized
     {
-        Ok(Self { cmd: PositionCommand::default() })
+        let mut corner_pan = false;
+        let mut corner_pan_deg = DEFAULT_CORNER_PAN_DEG;
+        let mut scan_max_speed = DEFAULT_SCAN_MAX_SPEED;
+
+        if let Some(ComponentConfig(kv)) = config {
+            // "enable" or "disable"
+            if let Some(v) = kv.get("corner_pan") {
+                let s: String = v.clone().into();
+                corner_pan = match s.as_str() {
+                    "enable" => true,
+                    "disable" => false,
+                    _ => return Err(CuError::from(format!("Invalid corner_pan value: \"{s}\". Valid values: \"enable\", \"disable\""))),
+                };
+            }
+            if let Some(v) = kv.get("corner_pan_deg") {
+                let f: f64 = v.clone().into();
+                corner_pan_deg = (f as f32).abs();
+            }
+            if let Some(v) = kv.get("scan_max_speed") {
+                let f: f64 = v.clone().into();
+                scan_max_speed = f as f32;
+            }
+        }
+
+        Ok(Self { cmd: PositionCommand::default(), corner_pan, corner_pan_deg, scan_max_speed })
     }
 
     fn process(&mut self, _clock: &RobotClock, input: &Self::Input<'_>, output: &mut Self::Output<'_>,)
     -> CuResult<()>
     {
-        let msg = input.payload().map_or(Err(CuError::from(format!("none pload PannerAdapter"))), |msg| {Ok(msg)})?;
-        output.set_payload(msg.panner_payload);
+        let (prop_adap, maneuver, itp) = input;
+        let msg = prop_adap.payload().map_or(Err(CuError::from(format!("none pload PannerAdapter"))), |msg| {Ok(msg)})?;
+
+        let mut res = msg.panner_payload;
+        if self.corner_pan && msg.loop_state == LoopState::Closed {
+            let maneuver = maneuver.payload().copied().unwrap_or_default();
+            res = match maneuver.phase {
+                ManeuverPhase::CornerPending | ManeuverPhase::Cornering => {
+                    // positive pans left
+                    let angle_deg = match maneuver.direction {
+                        CornerDirection::Left => self.corner_pan_deg,
+                        CornerDirection::Right => -self.corner_pan_deg,
+                    };
+                    CameraPanningPayload { pos_cmd: PositionCommand::Front, angle_deg: Some(angle_deg), scan: false }
+                },
+                ManeuverPhase::Cooldown | ManeuverPhase::None => CameraPanningPayload::default(),
+            };
+        }
+
+        let moving = |enable: bool, speed: f32| enable && speed > self.scan_max_speed;
+        let stationary = !moving(msg.propulsion_payload.left_enable, msg.propulsion_payload.left_speed)
+            && !moving(msg.propulsion_payload.right_enable, msg.propulsion_payload.right_speed);
+        if stationary && itp.payload().is_some_and(|i| i.pan_scan_cmd) {
+            res = CameraPanningPayload { scan: true, ..Default::default() };
+        }
+
+        self.cmd = res.pos_cmd;
+        output.set_payload(res);
         Ok(())
     }
 }
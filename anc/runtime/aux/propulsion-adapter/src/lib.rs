@@ -1,10 +1,16 @@
 extern crate cu_bincode as bincode;
+
+mod ttc;
+pub use ttc::*;
+
 use cu29::prelude::*;
 use bincode::{Decode, Encode};
 use serde::{Deserialize, Serialize};
 use cu_cam_pan::{CameraPanningPayload, PositionCommand};
-use cu_propulsion::{PropulsionPayload, WheelDirection};
-use cu_hcsr04::{HcSr04Payload};
+use cu_propulsion::{AppliedDirections, PropulsionPayload, WheelDirection};
+use cu_hcsr04::{NOfM, SensorPosition};
+use cu_irencoder::IrEncoderPayload;
+use ultrasonic_fusion::SurroundDistancePayload;
 use opencv_splitter::NsmPayload;
 use dual_mtr_ctrlr::DualMtrCtrlrPayload;
 
@@ -54,7 +60,15 @@ pub struct PropulsionAdapterOutputPayload {
     pub panner_payload: CameraPanningPayload,
     pub weighted_error: f32,
     pub is_e_stop_triggered: bool,
+    /// Filtered distance from the side the robot is driving towards (rear when reversing)
     pub distance: Option<f64>,
+    /// Forward speed cap from time-to-collision, 0.0 - 1.0. Applied by the arbitrator to its final
+    /// output so every mode is covered.
+    pub speed_cap: f32,
+    pub ttc_s: Option<f32>,
+    /// Which side `distance`, the e-stop and the speed cap were taken from
+    pub guarded_side: SensorPosition,
+    pub surround: SurroundDistancePayload,
 }
 
 #[derive(Default, Debug, Clone, Copy, Encode, Decode, PartialEq, Serialize, Deserialize)]
@@ -76,7 +90,20 @@ pub struct ZenohTopicsAdapterOutputPayload {
 #[derive(Reflect)]
 #[reflect(no_field_bounds, from_reflect = false)]
 pub struct PropulsionAdapter {
-    e_stop_threshold_cm: f64
+    e_stop_threshold_cm: f64,
+    /// e-stop needs n of the last m raw echoes under the threshold
+    #[reflect(ignore)]
+    e_stop_confirm: NOfM,
+    /// Confirmation result as of the last new echo, held while the sensor republishes it
+    e_stop_confirmed: bool,
+    ttc_enabled: bool,
+    #[reflect(ignore)]
+    ttc_cfg: TtcCfg,
+    #[reflect(ignore)]
+    ttc_limiter: TtcLimiter,
+    /// Side used last cycle, the confirmation and closing speed history restart when it changes
+    #[reflect(ignore)]
+    guarded_side: SensorPosition,
 }
 
 impl Freezable for PropulsionAdapter {
@@ -92,7 +119,8 @@ impl Freezable for PropulsionAdapter {
 }
 
 impl CuTask for PropulsionAdapter {
-    type Input<'m> = input_msg!('m, ZenohTopicsAdapterOutputPayload, HcSr04Payload, NsmPayload);
+    // AppliedDirections - what the motors were last driven with, from cu_propulsion::AppliedDirectionsSrc
+    type Input<'m> = input_msg!('m, ZenohTopicsAdapterOutputPayload, SurroundDistancePayload, NsmPayload, IrEncoderPayload, AppliedDirections);
     type Output<'m> = output_msg!(PropulsionAdapterOutputPayload, DualMtrCtrlrPayload);
     type Resources<'r> = ();
 
@@ -108,13 +136,55 @@ impl CuTask for PropulsionAdapter {
             .clone()
             .into();
 
-        Ok(Self { e_stop_threshold_cm })
+        // 1 of 1 is the old behaviour, any single short echo stops
+        let e_stop_confirm_n: u32 = kv.get("e_stop_confirm_n")
+            .map(|v| v.clone().into())
+            .unwrap_or(1);
+        let e_stop_confirm_m: u32 = kv.get("e_stop_confirm_m")
+            .map(|v| v.clone().into())
+            .unwrap_or(1);
+
+        // "enable" or "disable", disabled leaves only the hard e-stop
+        let ttc_enabled = match kv.get("ttc_scaling") {
+            Some(v) => {
+                let s: String = v.clone().into();
+                match s.as_str() {
+                    "enable" => true,
+                    "disable" => false,
+                    _ => return Err(CuError::from(format!("Invalid ttc_scaling value: \"{s}\". Valid values: \"enable\", \"disable\""))),
+                }
+            },
+            None => false,
+        };
+
+        let mut ttc_cfg = TtcCfg::default();
+        let get_f32 = |key: &str| kv.get(key).map(|v| { let f: f64 = v.clone().into(); f as f32 });
+        if let Some(f) = get_f32("ttc_full_speed_s") { ttc_cfg.ttc_full_speed_s = f; }
+        if let Some(f) = get_f32("ttc_stop_s") { ttc_cfg.ttc_stop_s = f; }
+        if let Some(f) = get_f32("cap_release_per_s") { ttc_cfg.cap_release_per_s = f; }
+        if let Some(f) = get_f32("closing_speed_alpha") { ttc_cfg.closing_speed_alpha = f; }
+        if let Some(f) = get_f32("max_rpm") { ttc_cfg.max_rpm = f; }
+        if let Some(f) = get_f32("wheel_radius_cm") { ttc_cfg.wheel_radius_cm = f; }
+
+        if ttc_cfg.ttc_stop_s >= ttc_cfg.ttc_full_speed_s {
+            return Err(CuError::from("PropulsionAdapter: ttc_stop_s must be below ttc_full_speed_s"));
+        }
+
+        Ok(Self {
+            e_stop_threshold_cm,
+            e_stop_confirm: NOfM::new(e_stop_confirm_n as usize, e_stop_confirm_m as usize),
+            e_stop_confirmed: false,
+            ttc_enabled,
+            ttc_cfg,
+            ttc_limiter: TtcLimiter::default(),
+            guarded_side: SensorPosition::Front,
+        })
     }
 
     fn process(&mut self, clock: &RobotClock, input: &Self::Input<'_>, output: &mut Self::Output<'_>,)
     -> CuResult<()>
     {
-        let (get_zenoh, get_hcsr04, get_nsm) = input;
+        let (get_zenoh, get_surround, get_nsm, get_encoder, get_applied) = input;
 
         // Zenoh commands are required - can't do anything without knowing the mode
         // IMPORTANT: All subscribers from ODD must have received at least something for this to not just
@@ -125,14 +195,49 @@ impl CuTask for PropulsionAdapter {
 
         let loop_state = zenoh_msg.loop_state;
 
-        // Distance sensor: require payload (cu-hcsr04 is sticky)
-        let Some(hcsr04_msg) = get_hcsr04.payload() else {
+        // Distance sensors: require payload (cu-hcsr04 array is sticky)
+        let Some(surround) = get_surround.payload() else {
             return Ok(());
         };
 
+        // guard the rear while reversing, if a rear sensor is mounted and available. Taken from the
+        // directions the motors were actually driven with (one cycle late), the zenoh ones don't know
+        // about the arbitrator's pivots, bypass legs or e-stop. Pivots (one wheel each way) keep the
+        // front, a stop keeps whatever side was guarded since the robot coasts that way.
+        let applied = get_applied.payload().copied().unwrap_or_default();
+        let rear_available = surround.get(SensorPosition::Rear).is_some_and(|r| r.available());
+        let guarded_side = match (applied.left, applied.right) {
+            (WheelDirection::Reverse, WheelDirection::Reverse) if rear_available => SensorPosition::Rear,
+            (WheelDirection::Stop, WheelDirection::Stop) if rear_available => self.guarded_side,
+            _ => SensorPosition::Front,
+        };
+        if guarded_side != self.guarded_side {
+            eprintln!("PROP ADAPTER: guarding {guarded_side:?}");
+            self.guarded_side = guarded_side;
+            self.e_stop_confirm.reset();
+            self.e_stop_confirmed = false;
+            self.ttc_limiter = TtcLimiter::default();
+        }
+        let hcsr04_msg = surround.get(guarded_side).copied().unwrap_or_default();
+
         let distance = hcsr04_msg.distance;
-        let is_e_stop_triggered = distance
-            .map_or(false, |d| d < self.e_stop_threshold_cm);
+        if hcsr04_msg.fresh {
+            self.e_stop_confirmed = self.e_stop_confirm.update(hcsr04_msg.raw_distance
+                .map_or(false, |d| d < self.e_stop_threshold_cm));
+        }
+        let is_e_stop_triggered = self.e_stop_confirmed;
+
+        // graded response ahead of the hard e-stop
+        let (speed_cap, ttc_s) = if self.ttc_enabled {
+            // odometry closing speed is towards the guarded side
+            let sign = if guarded_side == SensorPosition::Rear { -1.0 } else { 1.0 };
+            let wheels = get_encoder.payload()
+                .and_then(|e| Some((sign * e.lmtr_normalized_rpm?, sign * e.rmtr_normalized_rpm?)));
+            let cap = self.ttc_limiter.update(&self.ttc_cfg, clock.now(), distance, wheels);
+            (cap, self.ttc_limiter.ttc_s)
+        } else {
+            (1.0, None)
+        };
 
         // NSM payload: only needed for closed-loop (heading error for PID)
         // For open-loop, use 0.0; for closed-loop, require payload (opencv-splitter is sticky)
@@ -167,7 +272,7 @@ impl CuTask for PropulsionAdapter {
             right_direction: zenoh_msg.right_direction
         };
 
-        let panner_payload = CameraPanningPayload { pos_cmd: zenoh_msg.camera_position };
+        let panner_payload = CameraPanningPayload { pos_cmd: zenoh_msg.camera_position, angle_deg: None, scan: false };
 
         let is_at_rest = matches!(zenoh_msg.work_or_rest_state, WorkOrRestState::AtRest);
 
@@ -215,12 +320,17 @@ impl CuTask for PropulsionAdapter {
             weighted_error,
             is_e_stop_triggered,
             distance,
+            speed_cap,
+            ttc_s,
+            guarded_side,
+            surround: *surround,
         };
 
         output.0.set_payload(prop_adap_output_payload);
         output.1.tov = Tov::Time(clock.now());
         output.1.set_payload(DualMtrCtrlrPayload { error: weighted_error });
         output.1.metadata.set_status(format!("hdng_err: {weighted_error:.2}"));
+        output.0.metadata.set_status(format!("cap: {speed_cap:.2}"));
         Ok(())
     }
 }
@@ -0,0 +1,210 @@
+use cu29::prelude::*;
+
+pub const DEFAULT_TTC_FULL_SPEED_S: f32 = 3.0;
+pub const DEFAULT_TTC_STOP_S: f32 = 0.5;
+/// How fast the cap may rise again once the obstacle clears, per second. Falling is immediate.
+pub const DEFAULT_CAP_RELEASE_PER_S: f32 = 0.5;
+pub const DEFAULT_CLOSING_SPEED_ALPHA: f32 = 0.3;
+/// Closing speeds below this (cm/s) count as not approaching
+const MIN_CLOSING_SPEED_CM_S: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct TtcCfg {
+    /// At or above this time-to-collision the cap is 1.0
+    pub ttc_full_speed_s: f32,
+    /// At or below this the cap is 0.0, the hard e-stop still sits underneath
+    pub ttc_stop_s: f32,
+    pub cap_release_per_s: f32,
+    /// EMA on the sensor-derived closing speed
+    pub closing_speed_alpha: f32,
+    /// For odometry, must match the encoder-pair and arbitrator
+    pub max_rpm: f32,
+    pub wheel_radius_cm: f32,
+}
+
+impl Default for TtcCfg {
+    fn default() -> Self {
+        Self {
+            ttc_full_speed_s: DEFAULT_TTC_FULL_SPEED_S,
+            ttc_stop_s: DEFAULT_TTC_STOP_S,
+            cap_release_per_s: DEFAULT_CAP_RELEASE_PER_S,
+            closing_speed_alpha: DEFAULT_CLOSING_SPEED_ALPHA,
+            max_rpm: 600.0,
+            wheel_radius_cm: 3.0,
+        }
+    }
+}
+
+/// Estimates closing speed from successive distance readings and from wheel odometry, takes the
+/// larger of the two (a stationary obstacle agrees with odometry, one moving towards us shows up
+/// in the sensor), and maps time-to-collision to a forward speed cap in [0, 1].
+#[derive(Debug)]
+pub struct TtcLimiter {
+    last_distance: Option<(f64, CuTime)>,
+    sensor_closing_cm_s: f32,
+    last_update: Option<CuTime>,
+    pub cap: f32,
+    pub ttc_s: Option<f32>,
+}
+
+impl Default for TtcLimiter {
+    fn default() -> Self {
+        Self {
+            last_distance: None,
+            sensor_closing_cm_s: 0.0,
+            last_update: None,
+            cap: 1.0,
+            ttc_s: None,
+        }
+    }
+}
+
+impl TtcLimiter {
+    /// `distance_cm` is the filtered HC-SR04 reading, `wheel_normalized_rpm` the signed (left, right)
+    /// encoder readings, `now` the robot clock
+    pub fn update(&mut self, cfg: &TtcCfg, now: CuTime, distance_cm: Option<f64>, wheel_normalized_rpm: Option<(f32, f32)>) -> f32 {
+        let dt_s = self.last_update
+            .map(|last| now.as_nanos().saturating_sub(last.as_nanos()) as f32 / 1_000_000_000.0)
+            .unwrap_or(0.0);
+        self.last_update = Some(now);
+
+        match (distance_cm, self.last_distance) {
+            (Some(d), Some((last_d, since))) => {
+                let ddt_s = now.as_nanos().saturating_sub(since.as_nanos()) as f32 / 1_000_000_000.0;
+                // the sticky filtered reading repeats between echoes, only take changes
+                if d != last_d && ddt_s > 0.0 {
+                    let closing = (last_d - d) as f32 / ddt_s;
+                    self.sensor_closing_cm_s += cfg.closing_speed_alpha * (closing - self.sensor_closing_cm_s);
+                    self.last_distance = Some((d, now));
+                }
+            },
+            (Some(d), None) => self.last_distance = Some((d, now)),
+            (None, _) => {
+                self.last_distance = None;
+                self.sensor_closing_cm_s = 0.0;
+            },
+        }
+
+        // forward speed of the robot body in cm/s
+        let odom_closing_cm_s = wheel_normalized_rpm
+            .map(|(l, r)| {
+                let rpm = (l + r) / 2.0 * cfg.max_rpm;
+                rpm * 2.0 * std::f32::consts::PI * cfg.wheel_radius_cm / 60.0
+            })
+            .unwrap_or(0.0);
+
+        let closing_cm_s = self.sensor_closing_cm_s.max(odom_closing_cm_s);
+
+        self.ttc_s = match distance_cm {
+            Some(d) if closing_cm_s > MIN_CLOSING_SPEED_CM_S => Some(d as f32 / closing_cm_s),
+            _ => None,
+        };
+
+        let target = match self.ttc_s {
+            Some(ttc) => {
+                let span = (cfg.ttc_full_speed_s - cfg.ttc_stop_s).max(f32::EPSILON);
+                ((ttc - cfg.ttc_stop_s) / span).clamp(0.0, 1.0)
+            },
+            None => 1.0,
+        };
+
+        self.cap = if target < self.cap {
+            target
+        } else {
+            (self.cap + cfg.cap_release_per_s * dt_s).min(target)
+        };
+        self.cap
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const CYCLE: Duration = Duration::from_millis(20);
+
+    /// The cap the limiter settles to for a time-to-collision
+    fn cap_for(cfg: &TtcCfg, ttc_s: f32) -> f32 {
+        ((ttc_s - cfg.ttc_stop_s) / (cfg.ttc_full_speed_s - cfg.ttc_stop_s)).clamp(0.0, 1.0)
+    }
+
+    /// Normalized rpm that drives the robot at `cm_s`
+    fn wheels_at(cfg: &TtcCfg, cm_s: f32) -> f32 {
+        cm_s * 60.0 / (2.0 * std::f32::consts::PI * cfg.wheel_radius_cm) / cfg.max_rpm
+    }
+
+    #[test]
+    fn obstacle_closing_in_on_a_stopped_robot() {
+        let cfg = TtcCfg::default();
+        let (clock, mock) = RobotClock::mock();
+        let mut t = TtcLimiter::default();
+
+        // 100 cm/s towards us, 2 cm a cycle
+        let mut d = 300.0;
+        for _ in 0..40 {
+            t.update(&cfg, clock.now(), Some(d), Some((0.0, 0.0)));
+            d -= 2.0;
+            mock.increment(CYCLE);
+        }
+        let cap = t.update(&cfg, clock.now(), Some(d), Some((0.0, 0.0)));
+
+        let ttc = t.ttc_s.unwrap();
+        let expected = d as f32 / 100.0;
+        assert!((ttc - expected).abs() / expected < 0.01, "ttc {ttc}s vs {expected}s");
+        assert!((cap - cap_for(&cfg, expected)).abs() < 0.01, "cap {cap}");
+    }
+
+    #[test]
+    fn driving_at_a_stationary_obstacle() {
+        let cfg = TtcCfg::default();
+        let (clock, mock) = RobotClock::mock();
+        let wheels = wheels_at(&cfg, 50.0);
+
+        // odometry alone, the sticky reading hasn't changed yet
+        let mut t = TtcLimiter::default();
+        let cap = t.update(&cfg, clock.now(), Some(100.0), Some((wheels, wheels)));
+        assert!((t.ttc_s.unwrap() - 2.0).abs() < 0.01, "ttc {:?}", t.ttc_s);
+        assert!((cap - cap_for(&cfg, 2.0)).abs() < 0.01, "cap {cap}");
+
+        // closer than ttc_stop_s, full stop
+        let cap = t.update(&cfg, clock.now(), Some(20.0), Some((wheels, wheels)));
+        assert_eq!(cap, 0.0);
+
+        // not moving, the obstacle isn't either: no cap
+        let mut t = TtcLimiter::default();
+        for _ in 0..10 {
+            assert_eq!(t.update(&cfg, clock.now(), Some(30.0), Some((0.0, 0.0))), 1.0);
+            assert_eq!(t.ttc_s, None);
+            mock.increment(CYCLE);
+        }
+
+        // reversing away from it doesn't count as closing
+        let cap = t.update(&cfg, clock.now(), Some(30.0), Some((-wheels, -wheels)));
+        assert_eq!(cap, 1.0);
+    }
+
+    #[test]
+    fn no_distance_releases_the_cap_at_the_release_rate() {
+        let cfg = TtcCfg::default();
+        let (clock, mock) = RobotClock::mock();
+        let wheels = wheels_at(&cfg, 50.0);
+        let mut t = TtcLimiter::default();
+
+        assert_eq!(t.update(&cfg, clock.now(), Some(20.0), Some((wheels, wheels))), 0.0);
+
+        // the echo is gone, no time-to-collision but the cap only comes back gradually
+        mock.increment(Duration::from_secs(1));
+        let cap = t.update(&cfg, clock.now(), None, Some((wheels, wheels)));
+        assert_eq!(t.ttc_s, None);
+        assert!((cap - cfg.cap_release_per_s).abs() < 1e-3, "cap {cap}");
+
+        mock.increment(Duration::from_secs(2));
+        assert_eq!(t.update(&cfg, clock.now(), None, None), 1.0);
+
+        // the sensor-derived closing speed restarts with the next reading
+        mock.increment(CYCLE);
+        t.update(&cfg, clock.now(), Some(100.0), Some((0.0, 0.0)));
+        assert_eq!(t.ttc_s, None);
+    }
+}
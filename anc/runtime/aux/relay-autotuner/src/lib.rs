@@ -0,0 +1,443 @@
+/// Relay-feedback (Åström–Hägglund) autotuner. Takes the same input and gives the same
+/// PIDControlOutputPayload as a GenericPIDTask, so it can be swapped in for one of the speed or
+/// lane controllers in the RON. Instead of PID it drives the loop with a relay of amplitude
+/// `relay_amplitude` around `relay_bias`, which makes the closed loop settle into a limit cycle.
+/// The period of that cycle is the ultimate period Tu and its amplitude gives the ultimate gain
+/// Ku = 4d / (pi * sqrt(a^2 - eps^2)). Ziegler–Nichols and Tyreus–Luyben gains are derived from
+/// those and published on the second output, for a ZSink and the copper log.
+///
+/// Proposed ki and kd are in cu-pid units, i.e. per nanosecond, to be pasted straight into the RON.
+
+extern crate cu_bincode as bincode;
+use cu29::prelude::*;
+use cu_pid::PIDControlOutputPayload;
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use core::marker::PhantomData;
+
+pub const DEFAULT_RELAY_BIAS: f32 = 0.0;
+pub const DEFAULT_HYSTERESIS: f32 = 0.0;
+pub const DEFAULT_SETPOINT: f32 = 0.0;
+pub const DEFAULT_CYCLES: u32 = 4;
+pub const DEFAULT_DISCARD_CYCLES: u32 = 1;
+pub const DEFAULT_MAX_DURATION_MS: u64 = 20000;
+
+const NS_PER_S: f32 = 1_000_000_000.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Encode, Decode, Serialize, Deserialize)]
+#[derive(Reflect)]
+#[reflect(no_field_bounds, from_reflect = false)]
+pub enum AutotuneState {
+    #[default]
+    Relaying,
+    Done,
+    /// no sustained oscillation within max_duration_ms
+    Failed,
+}
+
+/// ki is per ns and kd is in ns, matching what GenericPIDTask expects
+#[derive(Debug, Clone, Copy, Default, PartialEq, Encode, Decode, Serialize, Deserialize)]
+#[derive(Reflect)]
+#[reflect(no_field_bounds, from_reflect = false)]
+pub struct AutotuneResultPayload {
+    pub state: AutotuneState,
+    pub cycles_measured: u32,
+    pub ultimate_gain: f32,
+    pub ultimate_period_ms: f32,
+    pub zn_kp: f32,
+    pub zn_ki: f32,
+    pub zn_kd: f32,
+    pub tl_kp: f32,
+    pub tl_ki: f32,
+    pub tl_kd: f32,
+}
+
+impl AutotuneResultPayload {
+    fn from_ultimate(ku: f32, tu_s: f32, cycles_measured: u32) -> Self {
+        let tu_ns = tu_s * NS_PER_S;
+
+        // Ziegler–Nichols classic PID: Kp = 0.6Ku, Ti = Tu/2, Td = Tu/8
+        let zn_kp = 0.6 * ku;
+        let zn_ki = zn_kp / (tu_ns / 2.0);
+        let zn_kd = zn_kp * (tu_ns / 8.0);
+
+        // Tyreus–Luyben: Kp = Ku/2.2, Ti = 2.2Tu, Td = Tu/6.3. Less aggressive, less overshoot
+        let tl_kp = ku / 2.2;
+        let tl_ki = tl_kp / (2.2 * tu_ns);
+        let tl_kd = tl_kp * (tu_ns / 6.3);
+
+        Self {
+            state: AutotuneState::Done,
+            cycles_measured,
+            ultimate_gain: ku,
+            ultimate_period_ms: tu_s * 1000.0,
+            zn_kp,
+            zn_ki,
+            zn_kd,
+            tl_kp,
+            tl_ki,
+            tl_kd,
+        }
+    }
+}
+
+#[derive(Reflect)]
+#[reflect(no_field_bounds, from_reflect = false)]
+pub struct RelayAutotuneTask<I>
+where
+    I: CuMsgPayload,
+{
+    #[reflect(ignore)]
+    _marker: PhantomData<I>,
+    setpoint: f32,
+    relay_amplitude: f32,
+    relay_bias: f32,
+    hysteresis: f32,
+    cycles: u32,
+    discard_cycles: u32,
+    max_duration_ms: u64,
+    output_limit: Option<f32>,
+    #[reflect(ignore)]
+    state: AutotuneState,
+    relay_high: bool,
+    #[reflect(ignore)]
+    started: CuTime,
+    #[reflect(ignore)]
+    last_rising_switch: Option<CuTime>,
+    cycle_min: f32,
+    cycle_max: f32,
+    #[reflect(ignore)]
+    periods_s: Vec<f32>,
+    #[reflect(ignore)]
+    amplitudes: Vec<f32>,
+    #[reflect(ignore)]
+    result: AutotuneResultPayload,
+}
+
+impl<I> Freezable for RelayAutotuneTask<I> where I: CuMsgPayload {}
+
+impl<I> CuTask for RelayAutotuneTask<I>
+where
+    I: CuMsgPayload + 'static + TypePath + Reflect,
+    f32: for<'a> From<&'a I>,
+{
+    type Input<'m> = input_msg!('m, I);
+    type Output<'m> = output_msg!(PIDControlOutputPayload, AutotuneResultPayload);
+    type Resources<'r> = ();
+
+    fn new(config: Option<&ComponentConfig>, _resources: Self::Resources<'_>) -> CuResult<Self>
+    where Self: Sized
+    {
+        let ComponentConfig(kv) =
+            config.ok_or("No ComponentConfig specified for RelayAutotuneTask in RON")?;
+
+        let relay_amplitude: f64 = kv
+            .get("relay_amplitude")
+            .expect("relay_amplitude for RelayAutotuneTask not set in RON config")
+            .clone()
+            .into();
+
+        let relay_bias: f32 = kv.get("relay_bias")
+            .map(|v| { let f: f64 = v.clone().into(); f as f32 })
+            .unwrap_or(DEFAULT_RELAY_BIAS);
+
+        let hysteresis: f32 = kv.get("hysteresis")
+            .map(|v| { let f: f64 = v.clone().into(); f as f32 })
+            .unwrap_or(DEFAULT_HYSTERESIS);
+
+        let setpoint: f32 = kv.get("setpoint")
+            .map(|v| { let f: f64 = v.clone().into(); f as f32 })
+            .unwrap_or(DEFAULT_SETPOINT);
+
+        let cycles: u32 = kv.get("cycles")
+            .map(|v| { let f: f64 = v.clone().into(); f as u32 })
+            .unwrap_or(DEFAULT_CYCLES)
+            .max(1);
+
+        let discard_cycles: u32 = kv.get("discard_cycles")
+            .map(|v| { let f: f64 = v.clone().into(); f as u32 })
+            .unwrap_or(DEFAULT_DISCARD_CYCLES);
+
+        let max_duration_ms: u64 = kv.get("max_duration_ms")
+            .map(|v| { let f: f64 = v.clone().into(); f as u64 })
+            .unwrap_or(DEFAULT_MAX_DURATION_MS);
+
+        let output_limit: Option<f32> = kv.get("output_limit")
+            .map(|v| { let f: f64 = v.clone().into(); f as f32 });
+
+        Ok(Self {
+            _marker: Default::default(),
+            setpoint,
+            relay_amplitude: (relay_amplitude as f32).abs(),
+            relay_bias,
+            hysteresis: hysteresis.abs(),
+            cycles,
+            discard_cycles,
+            max_duration_ms,
+            output_limit,
+            state: AutotuneState::default(),
+            relay_high: false,
+            started: CuDuration::from_nanos(0),
+            last_rising_switch: None,
+            cycle_min: f32::MAX,
+            cycle_max: f32::MIN,
+            periods_s: Vec::new(),
+            amplitudes: Vec::new(),
+            result: AutotuneResultPayload::default(),
+        })
+    }
+
+    fn start(&mut self, clock: &RobotClock) -> CuResult<()> {
+        self.started = clock.now();
+        Ok(())
+    }
+
+    fn process(&mut self, clock: &RobotClock, input: &Self::Input<'_>, output: &mut Self::Output<'_>)
+    -> CuResult<()>
+    {
+        self.step(clock.now(), input.payload().map(f32::from));
+
+        // relay only while tuning, then park the loop at the bias
+        let mut u = match self.state {
+            AutotuneState::Relaying => {
+                if self.relay_high {
+                    self.relay_bias + self.relay_amplitude
+                } else {
+                    self.relay_bias - self.relay_amplitude
+                }
+            },
+            AutotuneState::Done | AutotuneState::Failed => self.relay_bias,
+        };
+        if let Some(limit) = self.output_limit {
+            u = u.clamp(-limit, limit);
+        }
+
+        output.0.tov = Tov::Time(clock.now());
+        output.0.set_payload(PIDControlOutputPayload { output: u, ..Default::default() });
+        output.1.set_payload(self.result);
+        output.1.metadata.set_status(format!("{:?} {}", self.state, self.periods_s.len()));
+        Ok(())
+    }
+}
+
+impl<I> RelayAutotuneTask<I>
+where
+    I: CuMsgPayload,
+{
+    /// One cycle of tuning at robot time `now`, a missing measurement only ages the run
+    fn step(&mut self, now: CuTime, measurement: Option<f32>) {
+        if self.state != AutotuneState::Relaying {
+            return;
+        }
+
+        if let Some(measurement) = measurement {
+            self.relay_step(now, measurement);
+        }
+
+        let elapsed_ns = now.as_nanos().saturating_sub(self.started.as_nanos());
+        if self.state == AutotuneState::Relaying
+            && CuDuration::from_nanos(elapsed_ns) >= CuDuration::from_millis(self.max_duration_ms)
+        {
+            self.state = AutotuneState::Failed;
+            self.result.state = AutotuneState::Failed;
+            error!("RelayAutotuneTask: no sustained oscillation after {}ms", self.max_duration_ms);
+            eprintln!("AUTOTUNE: failed, only {} cycles in {}ms", self.periods_s.len(), self.max_duration_ms);
+        }
+    }
+
+    fn relay_step(&mut self, now: CuTime, measurement: f32) {
+        let error = self.setpoint - measurement;
+
+        self.cycle_min = self.cycle_min.min(measurement);
+        self.cycle_max = self.cycle_max.max(measurement);
+
+        if !self.relay_high && error > self.hysteresis {
+            self.relay_high = true;
+
+            // a rising switch closes the previous cycle
+            if let Some(prev) = self.last_rising_switch {
+                let period_ns = now.as_nanos().checked_sub(prev.as_nanos()).unwrap_or(0);
+                self.periods_s.push(period_ns as f32 / NS_PER_S);
+                self.amplitudes.push((self.cycle_max - self.cycle_min) / 2.0);
+                self.result.cycles_measured = self.periods_s.len() as u32;
+                eprintln!("AUTOTUNE: cycle {} period={:.1}ms amplitude={:.5}",
+                    self.periods_s.len(), period_ns as f32 / 1_000_000.0, (self.cycle_max - self.cycle_min) / 2.0);
+            }
+            self.last_rising_switch = Some(now);
+            self.cycle_min = measurement;
+            self.cycle_max = measurement;
+        } else if self.relay_high && error < -self.hysteresis {
+            self.relay_high = false;
+        }
+
+        let needed = (self.discard_cycles + self.cycles) as usize;
+        if self.periods_s.len() >= needed {
+            self.finish();
+        }
+    }
+
+    fn finish(&mut self) {
+        let skip = self.discard_cycles as usize;
+        let n = self.periods_s.len() - skip;
+        let tu_s = self.periods_s.iter().skip(skip).sum::<f32>() / n as f32;
+        let a = self.amplitudes.iter().skip(skip).sum::<f32>() / n as f32;
+
+        // hysteresis correction, falls back to the plain describing function if a <= eps
+        let a_eff = if a > self.hysteresis {
+            (a * a - self.hysteresis * self.hysteresis).sqrt()
+        } else {
+            a
+        };
+
+        if a_eff <= f32::EPSILON || tu_s <= f32::EPSILON {
+            self.state = AutotuneState::Failed;
+            self.result.state = AutotuneState::Failed;
+            error!("RelayAutotuneTask: degenerate limit cycle, no gains proposed");
+            return;
+        }
+
+        let ku = 4.0 * self.relay_amplitude / (std::f32::consts::PI * a_eff);
+        self.result = AutotuneResultPayload::from_ultimate(ku, tu_s, n as u32);
+        self.state = AutotuneState::Done;
+
+        let r = self.result;
+        info!("RelayAutotuneTask: Ku={} Tu={}ms", r.ultimate_gain, r.ultimate_period_ms);
+        info!("RelayAutotuneTask: ZN kp={} ki={} kd={}", r.zn_kp, r.zn_ki, r.zn_kd);
+        info!("RelayAutotuneTask: TL kp={} ki={} kd={}", r.tl_kp, r.tl_ki, r.tl_kd);
+        eprintln!("AUTOTUNE: Ku={:.5} Tu={:.1}ms | ZN kp={:e} ki={:e} kd={:e} | TL kp={:e} ki={:e} kd={:e}",
+            r.ultimate_gain, r.ultimate_period_ms, r.zn_kp, r.zn_ki, r.zn_kd, r.tl_kp, r.tl_ki, r.tl_kd);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+    use std::time::Duration;
+
+    const RELAY: f32 = 0.2;
+    const SETPOINT: f32 = 0.5;
+
+    fn tuner(hysteresis: f32, cycles: u32, discard_cycles: u32) -> RelayAutotuneTask<PIDControlOutputPayload> {
+        RelayAutotuneTask {
+            _marker: PhantomData,
+            setpoint: SETPOINT,
+            relay_amplitude: RELAY,
+            relay_bias: 0.0,
+            hysteresis,
+            cycles,
+            discard_cycles,
+            max_duration_ms: DEFAULT_MAX_DURATION_MS,
+            output_limit: None,
+            state: AutotuneState::Relaying,
+            relay_high: false,
+            started: CuDuration::from_nanos(0),
+            last_rising_switch: None,
+            cycle_min: f32::MAX,
+            cycle_max: f32::MIN,
+            periods_s: Vec::new(),
+            amplitudes: Vec::new(),
+            result: AutotuneResultPayload::default(),
+        }
+    }
+
+    /// A loop already in its limit cycle: (period ms, amplitude) per cycle, each a full sine around
+    /// the setpoint. Sampled every ms on a mock clock until the tuner is done or the cycles run out.
+    fn run(t: &mut RelayAutotuneTask<PIDControlOutputPayload>, cycles: &[(u64, f32)]) {
+        let (clock, mock) = RobotClock::mock();
+        t.started = clock.now();
+        for &(period_ms, amplitude) in cycles {
+            for ms in 0..period_ms {
+                let y = SETPOINT + amplitude * (2.0 * PI * ms as f32 / period_ms as f32).sin();
+                t.step(clock.now(), Some(y));
+                if t.state != AutotuneState::Relaying {
+                    return;
+                }
+                mock.increment(Duration::from_millis(1));
+            }
+        }
+    }
+
+    fn ku(amplitude: f32, hysteresis: f32) -> f32 {
+        4.0 * RELAY / (PI * (amplitude * amplitude - hysteresis * hysteresis).sqrt())
+    }
+
+    #[test]
+    fn gains_from_ultimate() {
+        let r = AutotuneResultPayload::from_ultimate(2.0, 0.4, 3);
+        assert_eq!(r.state, AutotuneState::Done);
+        assert_eq!(r.cycles_measured, 3);
+        assert!((r.ultimate_period_ms - 400.0).abs() < 1e-3);
+
+        let tu_ns = 0.4 * NS_PER_S;
+        assert!((r.zn_kp - 1.2).abs() < 1e-6);
+        assert!((r.zn_ki - 1.2 / (tu_ns / 2.0)).abs() / r.zn_ki < 1e-6);
+        assert!((r.zn_kd - 1.2 * tu_ns / 8.0).abs() / r.zn_kd < 1e-6);
+        assert!((r.tl_kp - 2.0 / 2.2).abs() < 1e-6);
+        assert!((r.tl_ki - r.tl_kp / (2.2 * tu_ns)).abs() / r.tl_ki < 1e-6);
+        assert!((r.tl_kd - r.tl_kp * tu_ns / 6.3).abs() / r.tl_kd < 1e-6);
+    }
+
+    #[test]
+    fn ku_and_tu_from_a_limit_cycle() {
+        let mut t = tuner(0.0, 3, 0);
+        run(&mut t, &[(400, 0.1); 6]);
+
+        assert_eq!(t.state, AutotuneState::Done);
+        assert_eq!(t.result.cycles_measured, 3);
+        assert!((t.result.ultimate_period_ms - 400.0).abs() < 1.0, "Tu {}ms", t.result.ultimate_period_ms);
+        let expected = ku(0.1, 0.0);
+        assert!((t.result.ultimate_gain - expected).abs() / expected < 0.01, "Ku {} vs {expected}", t.result.ultimate_gain);
+    }
+
+    #[test]
+    fn hysteresis_corrects_the_amplitude() {
+        let mut t = tuner(0.05, 3, 0);
+        run(&mut t, &[(400, 0.1); 6]);
+
+        assert_eq!(t.state, AutotuneState::Done);
+        assert!((t.result.ultimate_period_ms - 400.0).abs() < 1.0, "Tu {}ms", t.result.ultimate_period_ms);
+        let expected = ku(0.1, 0.05);
+        assert!((t.result.ultimate_gain - expected).abs() / expected < 0.01, "Ku {} vs {expected}", t.result.ultimate_gain);
+        // the plain describing function would have underestimated it
+        assert!(t.result.ultimate_gain > ku(0.1, 0.0) * 1.1);
+    }
+
+    #[test]
+    fn discarded_cycles_leave_out_the_transient() {
+        // two slow, large cycles before the loop settles, the switch into the settled part is a
+        // mix of both so it takes two discarded cycles to get rid of it
+        let signal = [(600, 0.3), (600, 0.3), (400, 0.1), (400, 0.1), (400, 0.1), (400, 0.1), (400, 0.1)];
+
+        let mut t = tuner(0.0, 3, 2);
+        run(&mut t, &signal);
+        assert_eq!(t.state, AutotuneState::Done);
+        assert_eq!(t.result.cycles_measured, 3);
+        assert!((t.result.ultimate_period_ms - 400.0).abs() < 1.0, "Tu {}ms", t.result.ultimate_period_ms);
+        let expected = ku(0.1, 0.0);
+        assert!((t.result.ultimate_gain - expected).abs() / expected < 0.01, "Ku {} vs {expected}", t.result.ultimate_gain);
+
+        let mut t = tuner(0.0, 3, 0);
+        run(&mut t, &signal);
+        assert_eq!(t.state, AutotuneState::Done);
+        assert!(t.result.ultimate_period_ms > 450.0, "Tu {}ms", t.result.ultimate_period_ms);
+    }
+
+    #[test]
+    fn fails_without_an_oscillation() {
+        let (clock, mock) = RobotClock::mock();
+        let mut t = tuner(0.0, 3, 0);
+        t.max_duration_ms = 1000;
+        t.started = clock.now();
+
+        // stuck below the setpoint, the relay goes high and stays there
+        for _ in 0..1000 {
+            t.step(clock.now(), Some(SETPOINT - 0.1));
+            mock.increment(Duration::from_millis(1));
+        }
+        assert_eq!(t.state, AutotuneState::Relaying);
+        t.step(clock.now(), Some(SETPOINT - 0.1));
+        assert_eq!(t.state, AutotuneState::Failed);
+        assert_eq!(t.result.state, AutotuneState::Failed);
+    }
+}
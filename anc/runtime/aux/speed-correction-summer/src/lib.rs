@@ -1,10 +1,22 @@
 
 use cu29::prelude::*;
-use cu_propulsion::PropulsionPayload;
+use cu_propulsion::{PropulsionPayload, WheelDirection};
 use cu_pid::PIDControlOutputPayload;
 use cu_irencoder::IrEncoderPayload;
+use motor_calib::CalibrationFile;
+use speed_ctrlrs::{SetpointMode, WheelVelocitySetpointPayload};
+use cu_powermon::Ina219Payload;
+
+mod stall;
+pub use stall::{BstnStallReset, StallFaultPayload};
+use stall::{StallCfg, StallDetector};
+mod overcurrent;
+pub use overcurrent::{OvercurrentFaultPayload, OvercurrentState};
+use overcurrent::{OvercurrentCfg, OvercurrentProtector};
 
 pub const MAX_PID_CORRECTION: f32 = 0.25;
+/// Must match max_rpm of the arbitrator, which scales the setpoints
+pub const DEFAULT_MAX_RPM: f32 = 600.0;
 
 #[derive(Reflect)]
 #[reflect(no_field_bounds, from_reflect = false)]
@@ -15,6 +27,27 @@ pub struct SpeedCorrectionSummer {
     k_ff_rmtr: f32,
     max_pid_correction: f32,
     speed_correction_enabled: bool,
+    /// Per-motor duty vs normalized rpm lookup tables. Used instead of k_ff_lmtr/k_ff_rmtr when present.
+    #[reflect(ignore)]
+    ff_calibration: Option<CalibrationFile>,
+    #[reflect(ignore)]
+    setpoint_mode: SetpointMode,
+    max_rpm: f32,
+    stall_detection_enabled: bool,
+    #[reflect(ignore)]
+    stall_cfg: StallCfg,
+    #[reflect(ignore)]
+    lmtr_stall: StallDetector,
+    #[reflect(ignore)]
+    rmtr_stall: StallDetector,
+    last_stall_reset: bool,
+    overcurrent_protection_enabled: bool,
+    #[reflect(ignore)]
+    overcurrent_cfg: OvercurrentCfg,
+    #[reflect(ignore)]
+    lmtr_overcurrent: OvercurrentProtector,
+    #[reflect(ignore)]
+    rmtr_overcurrent: OvercurrentProtector,
 }
 
 impl Default for SpeedCorrectionSummer {
@@ -25,6 +58,18 @@ impl Default for SpeedCorrectionSummer {
             k_ff_rmtr: 1.0,
             max_pid_correction: MAX_PID_CORRECTION,
             speed_correction_enabled: true,
+            ff_calibration: None,
+            setpoint_mode: SetpointMode::default(),
+            max_rpm: DEFAULT_MAX_RPM,
+            stall_detection_enabled: false,
+            stall_cfg: StallCfg::default(),
+            lmtr_stall: StallDetector::default(),
+            rmtr_stall: StallDetector::default(),
+            last_stall_reset: false,
+            overcurrent_protection_enabled: false,
+            overcurrent_cfg: OvercurrentCfg::default(),
+            lmtr_overcurrent: OvercurrentProtector::default(),
+            rmtr_overcurrent: OvercurrentProtector::default(),
         }
     }
 }
@@ -32,8 +77,18 @@ impl Default for SpeedCorrectionSummer {
 impl Freezable for SpeedCorrectionSummer {}
 
 impl CuTask for SpeedCorrectionSummer {
-    type Input<'m> = input_msg!('m, PIDControlOutputPayload, PIDControlOutputPayload, PropulsionPayload, IrEncoderPayload);
-    type Output<'m> = output_msg!(PropulsionPayload);
+    // lmtr pid, rmtr pid, feedforward, encoder, setpoints, lmtr ina219, rmtr ina219, stall reset
+    type Input<'m> = input_msg!('m,
+            PIDControlOutputPayload,
+            PIDControlOutputPayload,
+            PropulsionPayload,
+            IrEncoderPayload,
+            WheelVelocitySetpointPayload,
+            Ina219Payload,
+            Ina219Payload,
+            BstnStallReset
+        );
+    type Output<'m> = output_msg!(PropulsionPayload, StallFaultPayload, OvercurrentFaultPayload);
     type Resources<'r> = ();
 
     fn new(config: Option<&ComponentConfig>, _resources: Self::Resources<'_>) -> CuResult<Self>
@@ -75,6 +130,95 @@ impl CuTask for SpeedCorrectionSummer {
                     let f: f64 = v.clone().into();
                     inst.max_pid_correction = f as f32;
                 }
+
+                if let Some(v) = kv.get("ff_calibration_file") {
+                    let path: String = v.clone().into();
+                    let calib = CalibrationFile::load(&path).map_err(|e| CuError::from(e.as_str()))?;
+                    info!("SpeedCorrectionSummer: loaded feed-forward calibration from {}", path = path.as_str());
+                    inst.ff_calibration = Some(calib);
+                }
+
+                if let Some(v) = kv.get("setpoint_mode") {
+                    let s: String = v.clone().into();
+                    inst.setpoint_mode = SetpointMode::parse(&s).map_err(|e| CuError::from(e.as_str()))?;
+                }
+
+                if let Some(v) = kv.get("max_rpm") {
+                    let f: f64 = v.clone().into();
+                    inst.max_rpm = f as f32;
+                }
+
+                if let Some(v) = kv.get("stall_detection") {
+                    let stall_detection: String = v.clone().into();
+                    inst.stall_detection_enabled = match stall_detection.as_str() {
+                        "enable" => true,
+                        "disable" => false,
+                        _ => panic!("Invalid stall_detection value: \"{stall_detection}\". Valid values: \"enable\", \"disable\""),
+                    };
+                }
+
+                if let Some(v) = kv.get("stall_duty_threshold") {
+                    let f: f64 = v.clone().into();
+                    inst.stall_cfg.duty_threshold = f as f32;
+                }
+
+                if let Some(v) = kv.get("stall_rpm_threshold") {
+                    let f: f64 = v.clone().into();
+                    inst.stall_cfg.rpm_threshold = f as f32;
+                }
+
+                if let Some(v) = kv.get("stall_time_ms") {
+                    let f: f64 = v.clone().into();
+                    inst.stall_cfg.stall_time_ms = f as u64;
+                }
+
+                if let Some(v) = kv.get("stall_current_threshold_mamps") {
+                    let f: f64 = v.clone().into();
+                    inst.stall_cfg.current_threshold_mamps = Some(f);
+                }
+
+                if let Some(v) = kv.get("overcurrent_protection") {
+                    let overcurrent_protection: String = v.clone().into();
+                    inst.overcurrent_protection_enabled = match overcurrent_protection.as_str() {
+                        "enable" => true,
+                        "disable" => false,
+                        _ => return Err(CuError::from(format!("Invalid overcurrent_protection value: \"{overcurrent_protection}\". Valid values: \"enable\", \"disable\""))),
+                    };
+                }
+
+                if let Some(v) = kv.get("overcurrent_continuous_mamps") {
+                    let f: f64 = v.clone().into();
+                    inst.overcurrent_cfg.continuous_mamps = f;
+                }
+
+                if let Some(v) = kv.get("overcurrent_peak_mamps") {
+                    let f: f64 = v.clone().into();
+                    inst.overcurrent_cfg.peak_mamps = f;
+                }
+
+                if let Some(v) = kv.get("overcurrent_i2t_s") {
+                    let f: f64 = v.clone().into();
+                    inst.overcurrent_cfg.i2t_s = f as f32;
+                }
+
+                if let Some(v) = kv.get("overcurrent_derate_start") {
+                    let f: f64 = v.clone().into();
+                    inst.overcurrent_cfg.derate_start = f as f32;
+                }
+
+                if let Some(v) = kv.get("overcurrent_derate_min") {
+                    let f: f64 = v.clone().into();
+                    inst.overcurrent_cfg.derate_min = f as f32;
+                }
+
+                if let Some(v) = kv.get("monitor_max_current_ma") {
+                    let f: f64 = v.clone().into();
+                    inst.overcurrent_cfg.monitor_max_mamps = f;
+                }
+
+                if inst.overcurrent_protection_enabled {
+                    inst.overcurrent_cfg.validate()?;
+                }
             },
             None => ()
         }
@@ -82,13 +226,18 @@ impl CuTask for SpeedCorrectionSummer {
         Ok(inst)
     }
 
-    fn process(&mut self, _clock: &RobotClock, input: &Self::Input<'_>, output: &mut Self::Output<'_>,)
+    fn process(&mut self, clock: &RobotClock, input: &Self::Input<'_>, output: &mut Self::Output<'_>,)
     -> CuResult<()>
     {
         let lmtr_speed_ctrlr_outpload = input.0.payload();
         let rmtr_speed_ctrlr_outpload = input.1.payload();
         let feedforward = input.2.payload();
-        let _encoder = input.3.payload();
+        let encoder = input.3.payload();
+        let setpoint = input.4.payload();
+        // an unavailable monitor is the same as no current gate
+        let lmtr_ina219 = input.5.payload().filter(|p| p.available());
+        let rmtr_ina219 = input.6.payload().filter(|p| p.available());
+        let stall_reset = input.7.payload();
 
         if let Some(ff) = feedforward {
             let mut output_msg = ff.clone();
@@ -99,11 +248,26 @@ impl CuTask for SpeedCorrectionSummer {
                 let rmtr_pid = rmtr_speed_ctrlr_outpload.map(|p| p.output).unwrap_or(0.0)
                     .clamp(-self.max_pid_correction, self.max_pid_correction);
 
-                let lmtr_ff = ff.left_speed;
-                let rmtr_ff = ff.right_speed;
+                // feed-forward from the rpm setpoints, normalized back for the LUT/k_ff
+                let (lmtr_ff, rmtr_ff) = match (self.setpoint_mode, setpoint) {
+                    (SetpointMode::Velocity, Some(sp)) => (
+                        (sp.lmtr_rpm.abs() / self.max_rpm).clamp(0.0, 1.0),
+                        (sp.rmtr_rpm.abs() / self.max_rpm).clamp(0.0, 1.0),
+                    ),
+                    _ => (ff.left_speed, ff.right_speed),
+                };
 
-                let lmtr_summed_speed = lmtr_pid + (self.k_ff_lmtr * lmtr_ff);
-                let rmtr_summed_speed = rmtr_pid + (self.k_ff_rmtr * rmtr_ff);
+                // fall back to the scalar gain for any motor/direction without a curve
+                let (lmtr_ff_duty, rmtr_ff_duty) = match &self.ff_calibration {
+                    Some(calib) => (
+                        calib.lmtr.duty_for(lmtr_ff, ff.left_direction).unwrap_or(self.k_ff_lmtr * lmtr_ff),
+                        calib.rmtr.duty_for(rmtr_ff, ff.right_direction).unwrap_or(self.k_ff_rmtr * rmtr_ff),
+                    ),
+                    None => (self.k_ff_lmtr * lmtr_ff, self.k_ff_rmtr * rmtr_ff),
+                };
+
+                let lmtr_summed_speed = lmtr_pid + lmtr_ff_duty;
+                let rmtr_summed_speed = rmtr_pid + rmtr_ff_duty;
 
                 output_msg.left_speed = lmtr_summed_speed.clamp(0.0, 1.0);
                 output_msg.right_speed = rmtr_summed_speed.clamp(0.0, 1.0);
@@ -112,11 +276,123 @@ impl CuTask for SpeedCorrectionSummer {
             self.last_output = Some(output_msg);
         }
 
-        if let Some(msg) = self.last_output {
-            output.set_payload(msg);
+        // only on the rising edge, the ZSrc holds the last value until it goes stale
+        let reset_cmd = stall_reset.map_or(false, |r| r.0 == 1);
+        if reset_cmd && !self.last_stall_reset {
+            if self.lmtr_stall.latched || self.rmtr_stall.latched {
+                info!("SpeedCorrectionSummer: stall fault reset");
+                eprintln!("STALL: reset by base station");
+            }
+            self.lmtr_stall.reset();
+            self.rmtr_stall.reset();
+            if self.lmtr_overcurrent.state == OvercurrentState::Tripped || self.rmtr_overcurrent.state == OvercurrentState::Tripped {
+                info!("SpeedCorrectionSummer: overcurrent fault reset");
+                eprintln!("OVERCURRENT: reset by base station");
+            }
+            self.lmtr_overcurrent.reset();
+            self.rmtr_overcurrent.reset();
+        }
+        self.last_stall_reset = reset_cmd;
+
+        // integrate every cycle, the budget has to drain while there's no command too
+        if self.overcurrent_protection_enabled {
+            self.overcurrent_update(clock.now(), lmtr_ina219, rmtr_ina219);
         }
+
+        if let Some(mut msg) = self.last_output {
+            if self.stall_detection_enabled {
+                self.stall_handler(&mut msg, clock.now(), encoder, lmtr_ina219, rmtr_ina219);
+            }
+            if self.overcurrent_protection_enabled {
+                self.overcurrent_handler(&mut msg);
+            }
+            output.0.set_payload(msg);
+        }
+
+        output.1.set_payload(StallFaultPayload {
+            lmtr_stalled: self.lmtr_stall.latched,
+            rmtr_stalled: self.rmtr_stall.latched,
+        });
+        output.2.set_payload(OvercurrentFaultPayload {
+            lmtr: self.lmtr_overcurrent.state,
+            rmtr: self.rmtr_overcurrent.state,
+            lmtr_i2t: self.lmtr_overcurrent.used(&self.overcurrent_cfg),
+            rmtr_i2t: self.rmtr_overcurrent.used(&self.overcurrent_cfg),
+        });
         Ok(())
     }
 
 }
 
+
+impl SpeedCorrectionSummer {
+    /// Cuts a motor that's been driven above stall_duty_threshold without the encoder seeing it turn
+    /// for stall_time_ms, optionally only if its INA219 also reads above stall_current_threshold_mamps.
+    /// Stays cut until reset from the base station.
+    fn stall_handler(&mut self, msg: &mut PropulsionPayload, now: CuTime, encoder: Option<&IrEncoderPayload>,
+        lmtr_ina219: Option<&Ina219Payload>, rmtr_ina219: Option<&Ina219Payload>)
+    {
+        let lmtr_duty = if msg.left_enable && msg.left_direction != WheelDirection::Stop { msg.left_speed } else { 0.0 };
+        let rmtr_duty = if msg.right_enable && msg.right_direction != WheelDirection::Stop { msg.right_speed } else { 0.0 };
+
+        let lmtr_rpm = encoder.and_then(|e| e.lmtr_normalized_rpm);
+        let rmtr_rpm = encoder.and_then(|e| e.rmtr_normalized_rpm);
+
+        if self.lmtr_stall.update(&self.stall_cfg, now, lmtr_duty, lmtr_rpm, lmtr_ina219.map(|p| p.load_current_ma)) {
+            error!("SpeedCorrectionSummer: left motor stall, motor cut until reset");
+            eprintln!("STALL: left motor stalled at duty={:.3}, cut until reset", lmtr_duty);
+        }
+
+        if self.rmtr_stall.update(&self.stall_cfg, now, rmtr_duty, rmtr_rpm, rmtr_ina219.map(|p| p.load_current_ma)) {
+            error!("SpeedCorrectionSummer: right motor stall, motor cut until reset");
+            eprintln!("STALL: right motor stalled at duty={:.3}, cut until reset", rmtr_duty);
+        }
+
+        if self.lmtr_stall.latched {
+            msg.left_enable = false;
+            msg.left_speed = 0.0;
+            msg.left_direction = WheelDirection::Stop;
+        }
+
+        if self.rmtr_stall.latched {
+            msg.right_enable = false;
+            msg.right_speed = 0.0;
+            msg.right_direction = WheelDirection::Stop;
+        }
+    }
+
+    /// i2t on each motor's INA219 current, see OvercurrentProtector. Instant trip above
+    /// overcurrent_peak_mamps, a trip stays until reset from the base station (same reset as the stall).
+    fn overcurrent_update(&mut self, now: CuTime, lmtr_ina219: Option<&Ina219Payload>, rmtr_ina219: Option<&Ina219Payload>) {
+        let lmtr_current = lmtr_ina219.map(|p| p.load_current_ma);
+        let rmtr_current = rmtr_ina219.map(|p| p.load_current_ma);
+
+        if self.lmtr_overcurrent.update(&self.overcurrent_cfg, now, lmtr_current) {
+            error!("SpeedCorrectionSummer: left motor overcurrent, motor cut until reset");
+            eprintln!("OVERCURRENT: left motor tripped at {:.0}mA, cut until reset", lmtr_current.unwrap_or(0.0));
+        }
+
+        if self.rmtr_overcurrent.update(&self.overcurrent_cfg, now, rmtr_current) {
+            error!("SpeedCorrectionSummer: right motor overcurrent, motor cut until reset");
+            eprintln!("OVERCURRENT: right motor tripped at {:.0}mA, cut until reset", rmtr_current.unwrap_or(0.0));
+        }
+    }
+
+    /// Scales a derating motor's duty down, cuts a tripped one
+    fn overcurrent_handler(&self, msg: &mut PropulsionPayload) {
+        msg.left_speed *= self.lmtr_overcurrent.scale(&self.overcurrent_cfg);
+        msg.right_speed *= self.rmtr_overcurrent.scale(&self.overcurrent_cfg);
+
+        if self.lmtr_overcurrent.state == OvercurrentState::Tripped {
+            msg.left_enable = false;
+            msg.left_speed = 0.0;
+            msg.left_direction = WheelDirection::Stop;
+        }
+
+        if self.rmtr_overcurrent.state == OvercurrentState::Tripped {
+            msg.right_enable = false;
+            msg.right_speed = 0.0;
+            msg.right_direction = WheelDirection::Stop;
+        }
+    }
+}
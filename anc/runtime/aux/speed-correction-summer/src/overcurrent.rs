@@ -0,0 +1,328 @@
+extern crate cu_bincode as bincode;
+use cu29::prelude::*;
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+/// Keep both under max_current_ma of the motor INA219s, the current register saturates just below it
+/// and a peak at or above it would never be reached
+pub const DEFAULT_OVERCURRENT_CONTINUOUS_MAMPS: f64 = 700.0;
+pub const DEFAULT_OVERCURRENT_PEAK_MAMPS: f64 = 950.0;
+pub const DEFAULT_OVERCURRENT_I2T_S: f32 = 2.0;
+pub const DEFAULT_OVERCURRENT_DERATE_START: f32 = 0.5;
+pub const DEFAULT_OVERCURRENT_DERATE_MIN: f32 = 0.3;
+/// A stalled cycle doesn't count as seconds of overload
+const MAX_STEP_S: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+#[derive(Reflect)]
+pub enum OvercurrentState {
+    #[default]
+    Ok,
+    /// Duty scaled down, the i2t budget is running out
+    Derating,
+    /// Motor cut until BstnStallReset
+    Tripped,
+}
+
+/// `*_i2t` is the used fraction of the i2t budget, 1.0 trips
+#[derive(Debug, Clone, Copy, Default, Encode, Decode, PartialEq, Serialize, Deserialize)]
+#[derive(Reflect)]
+#[reflect(no_field_bounds, from_reflect = false)]
+pub struct OvercurrentFaultPayload {
+    pub lmtr: OvercurrentState,
+    pub rmtr: OvercurrentState,
+    pub lmtr_i2t: f32,
+    pub rmtr_i2t: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OvercurrentCfg {
+    pub continuous_mamps: f64,
+    /// Instant trip above this
+    pub peak_mamps: f64,
+    /// How long the motor may run at peak_mamps before tripping, less overload lasts longer
+    pub i2t_s: f32,
+    /// Budget fraction where derating starts, the duty scale goes linearly from 1.0 there to derate_min at 1.0
+    pub derate_start: f32,
+    pub derate_min: f32,
+    /// max_current_ma the motor INA219s are calibrated for, the highest current they can report
+    pub monitor_max_mamps: f64,
+}
+
+impl Default for OvercurrentCfg {
+    fn default() -> Self {
+        Self {
+            continuous_mamps: DEFAULT_OVERCURRENT_CONTINUOUS_MAMPS,
+            peak_mamps: DEFAULT_OVERCURRENT_PEAK_MAMPS,
+            i2t_s: DEFAULT_OVERCURRENT_I2T_S,
+            derate_start: DEFAULT_OVERCURRENT_DERATE_START,
+            derate_min: DEFAULT_OVERCURRENT_DERATE_MIN,
+            monitor_max_mamps: cu_powermon::DEFAULT_MAX_CURRENT_MA,
+        }
+    }
+}
+
+impl OvercurrentCfg {
+    pub fn validate(&self) -> CuResult<()> {
+        if !(self.peak_mamps > self.continuous_mamps && self.continuous_mamps >= 0.0 && self.i2t_s > 0.0) {
+            return Err(CuError::from("SpeedCorrectionSummer: overcurrent protection needs 0 <= overcurrent_continuous_mamps < overcurrent_peak_mamps and overcurrent_i2t_s > 0"));
+        }
+        if self.peak_mamps >= self.monitor_max_mamps {
+            return Err(CuError::from(format!(
+                "SpeedCorrectionSummer: overcurrent_peak_mamps {} must be below the motor monitors' max_current_ma {}, their reading saturates there",
+                self.peak_mamps, self.monitor_max_mamps)));
+        }
+        Ok(())
+    }
+
+    /// mA^2 s
+    fn budget(&self) -> f64 {
+        (self.peak_mamps.powi(2) - self.continuous_mamps.powi(2)) * self.i2t_s as f64
+    }
+}
+
+/// One per motor. Integrates (I^2 - I_continuous^2) dt, so the budget fills above the continuous
+/// limit and drains below it. The fill level survives a reset, a tripped motor has to cool down
+/// (sit cut for a while) before it can take the same overload again.
+#[derive(Debug, Default)]
+pub struct OvercurrentProtector {
+    accumulated: f64,
+    last_sample: Option<CuTime>,
+    pub state: OvercurrentState,
+}
+
+impl OvercurrentProtector {
+    pub fn used(&self, cfg: &OvercurrentCfg) -> f32 {
+        let budget = cfg.budget();
+        if budget > 0.0 { (self.accumulated / budget) as f32 } else { 0.0 }
+    }
+
+    /// Returns true on the tick the motor trips. None current (monitor unavailable) holds the state.
+    /// `now` is the robot clock, the integration runs on it.
+    pub fn update(&mut self, cfg: &OvercurrentCfg, now: CuTime, current_mamps: Option<f64>) -> bool {
+        let Some(current) = current_mamps.map(f64::abs) else {
+            self.last_sample = None;
+            return false;
+        };
+
+        let dt_s = self.last_sample
+            .map_or(0.0, |last| now.as_nanos().saturating_sub(last.as_nanos()) as f64 / 1_000_000_000.0)
+            .min(MAX_STEP_S);
+        self.last_sample = Some(now);
+
+        self.accumulated = (self.accumulated + (current.powi(2) - cfg.continuous_mamps.powi(2)) * dt_s)
+            .clamp(0.0, cfg.budget());
+
+        if self.state == OvercurrentState::Tripped {
+            return false;
+        }
+
+        if current >= cfg.peak_mamps || self.accumulated >= cfg.budget() {
+            self.state = OvercurrentState::Tripped;
+            return true;
+        }
+
+        self.state = if self.used(cfg) >= cfg.derate_start {
+            OvercurrentState::Derating
+        } else {
+            OvercurrentState::Ok
+        };
+        false
+    }
+
+    /// Duty multiplier for this motor
+    pub fn scale(&self, cfg: &OvercurrentCfg) -> f32 {
+        match self.state {
+            OvercurrentState::Ok => 1.0,
+            OvercurrentState::Tripped => 0.0,
+            OvercurrentState::Derating => {
+                let span = (1.0 - cfg.derate_start).max(f32::EPSILON);
+                let t = ((self.used(cfg) - cfg.derate_start) / span).clamp(0.0, 1.0);
+                1.0 - t * (1.0 - cfg.derate_min)
+            },
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.state = OvercurrentState::Ok;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const CYCLE: Duration = Duration::from_millis(18);
+
+    fn elapsed_ms(clock: &RobotClock, since: CuTime) -> u64 {
+        (clock.now().as_nanos() - since.as_nanos()) / 1_000_000
+    }
+
+    /// Feeds `mamps` every cycle for `ms`, stops at the trip and returns how far in it was
+    fn run(p: &mut OvercurrentProtector, cfg: &OvercurrentCfg, (clock, mock): &(RobotClock, RobotClockMock), ms: u64, mamps: f64) -> Option<u64> {
+        let start = clock.now();
+        while elapsed_ms(clock, start) < ms {
+            if p.update(cfg, clock.now(), Some(mamps)) {
+                return Some(elapsed_ms(clock, start));
+            }
+            mock.increment(CYCLE);
+        }
+        None
+    }
+
+    /// ms of a constant overload it takes to fill `fraction` of the budget
+    fn time_to(cfg: &OvercurrentCfg, mamps: f64, fraction: f64) -> f64 {
+        cfg.budget() * fraction / (mamps.powi(2) - cfg.continuous_mamps.powi(2)) * 1000.0
+    }
+
+    #[test]
+    fn default_cfg_is_valid() {
+        assert!(OvercurrentCfg::default().validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_a_peak_the_monitor_cant_report() {
+        let cfg = OvercurrentCfg { peak_mamps: 1000.0, monitor_max_mamps: 1000.0, ..Default::default() };
+        assert!(cfg.validate().is_err());
+        let cfg = OvercurrentCfg { peak_mamps: 600.0, ..Default::default() };
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn peak_trips_instantly_either_way() {
+        let cfg = OvercurrentCfg::default();
+        let (clock, mock) = RobotClock::mock();
+
+        let mut p = OvercurrentProtector::default();
+        assert!(p.update(&cfg, clock.now(), Some(cfg.peak_mamps)));
+        assert_eq!(p.state, OvercurrentState::Tripped);
+        assert_eq!(p.scale(&cfg), 0.0);
+        // only the tick it trips on says so
+        mock.increment(CYCLE);
+        assert!(!p.update(&cfg, clock.now(), Some(cfg.peak_mamps)));
+
+        let mut p = OvercurrentProtector::default();
+        assert!(p.update(&cfg, clock.now(), Some(-cfg.peak_mamps)));
+    }
+
+    #[test]
+    fn continuous_current_never_trips() {
+        let cfg = OvercurrentCfg::default();
+        let clock = RobotClock::mock();
+        let mut p = OvercurrentProtector::default();
+        assert_eq!(run(&mut p, &cfg, &clock, 60_000, cfg.continuous_mamps), None);
+        assert_eq!(p.state, OvercurrentState::Ok);
+        assert_eq!(p.used(&cfg), 0.0);
+    }
+
+    #[test]
+    fn overload_derates_then_trips_on_the_budget() {
+        let cfg = OvercurrentCfg::default();
+        let (clock, mock) = RobotClock::mock();
+        let start = clock.now();
+        let mamps = 900.0;
+        let derate_ms = time_to(&cfg, mamps, cfg.derate_start as f64);
+        let trip_ms = time_to(&cfg, mamps, 1.0);
+        let cycle_ms = CYCLE.as_millis() as f64;
+
+        let mut p = OvercurrentProtector::default();
+        let mut derating_from = None;
+        let mut tripped_at = None;
+        while tripped_at.is_none() && elapsed_ms(&clock, start) < 10_000 {
+            if p.update(&cfg, clock.now(), Some(mamps)) {
+                tripped_at = Some(elapsed_ms(&clock, start));
+            }
+            if p.state == OvercurrentState::Derating {
+                derating_from.get_or_insert(elapsed_ms(&clock, start));
+                let scale = p.scale(&cfg);
+                assert!(scale <= 1.0 && scale >= cfg.derate_min, "scale {scale}");
+            }
+            mock.increment(CYCLE);
+        }
+
+        let derating_from = derating_from.unwrap() as f64;
+        assert!(derating_from >= derate_ms && derating_from < derate_ms + cycle_ms, "derating from {derating_from}ms");
+        let tripped_at = tripped_at.unwrap() as f64;
+        assert!(tripped_at >= trip_ms && tripped_at < trip_ms + cycle_ms, "tripped at {tripped_at}ms");
+        assert_eq!(p.scale(&cfg), 0.0);
+    }
+
+    #[test]
+    fn derate_scale_is_linear_in_the_budget() {
+        let cfg = OvercurrentCfg::default();
+        let mut p = OvercurrentProtector { state: OvercurrentState::Derating, ..Default::default() };
+
+        p.accumulated = cfg.budget() * cfg.derate_start as f64;
+        assert!((p.scale(&cfg) - 1.0).abs() < 1e-6);
+        p.accumulated = cfg.budget() * (1.0 + cfg.derate_start as f64) / 2.0;
+        assert!((p.scale(&cfg) - (1.0 + cfg.derate_min) / 2.0).abs() < 1e-6);
+        p.accumulated = cfg.budget();
+        assert!((p.scale(&cfg) - cfg.derate_min).abs() < 1e-6);
+    }
+
+    #[test]
+    fn budget_drains_below_continuous() {
+        let cfg = OvercurrentCfg::default();
+        let clock = RobotClock::mock();
+        let mut p = OvercurrentProtector::default();
+        run(&mut p, &cfg, &clock, time_to(&cfg, 900.0, 0.75) as u64, 900.0);
+        assert_eq!(p.state, OvercurrentState::Derating);
+
+        run(&mut p, &cfg, &clock, 5000, 0.0);
+        assert_eq!(p.state, OvercurrentState::Ok);
+        assert_eq!(p.used(&cfg), 0.0);
+        assert_eq!(p.scale(&cfg), 1.0);
+    }
+
+    #[test]
+    fn trip_latches_and_reset_keeps_the_budget() {
+        let cfg = OvercurrentCfg::default();
+        let clock = RobotClock::mock();
+        let mut p = OvercurrentProtector::default();
+        run(&mut p, &cfg, &clock, 10_000, 900.0).unwrap();
+
+        // cut, the current drops, still tripped
+        clock.1.increment(CYCLE);
+        run(&mut p, &cfg, &clock, 500, 0.0);
+        assert_eq!(p.state, OvercurrentState::Tripped);
+        assert!(p.used(&cfg) < 1.0);
+
+        // reset straight back into the same overload: the budget is nearly full, it trips again well
+        // before a cold motor would
+        p.reset();
+        assert_eq!(p.state, OvercurrentState::Ok);
+        let retripped_after = run(&mut p, &cfg, &clock, 10_000, 900.0).unwrap();
+        assert!((retripped_after as f64) < time_to(&cfg, 900.0, 0.5), "retripped after {retripped_after}ms");
+    }
+
+    #[test]
+    fn unavailable_monitor_holds_and_doesnt_count_the_gap() {
+        let cfg = OvercurrentCfg::default();
+        let clock = RobotClock::mock();
+        let mut p = OvercurrentProtector::default();
+        run(&mut p, &cfg, &clock, 1000, 900.0);
+        let used = p.used(&cfg);
+        let state = p.state;
+
+        assert!(!p.update(&cfg, clock.0.now(), None));
+        assert_eq!(p.state, state);
+
+        // back after 10 s, the first sample only restarts the integration
+        clock.1.increment(Duration::from_secs(10));
+        assert!(!p.update(&cfg, clock.0.now(), Some(900.0)));
+        assert_eq!(p.used(&cfg), used);
+    }
+
+    #[test]
+    fn a_stalled_cycle_counts_at_most_max_step() {
+        let cfg = OvercurrentCfg::default();
+        let (clock, mock) = RobotClock::mock();
+        let mut p = OvercurrentProtector::default();
+        p.update(&cfg, clock.now(), Some(900.0));
+        mock.increment(Duration::from_secs(5));
+        p.update(&cfg, clock.now(), Some(900.0));
+        let expected = (900.0f64.powi(2) - cfg.continuous_mamps.powi(2)) * MAX_STEP_S / cfg.budget();
+        assert!((p.used(&cfg) as f64 - expected).abs() < 1e-6);
+    }
+}
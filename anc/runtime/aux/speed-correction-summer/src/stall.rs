@@ -0,0 +1,185 @@
+extern crate cu_bincode as bincode;
+use cu29::prelude::*;
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_STALL_DUTY_THRESHOLD: f32 = 0.4;
+pub const DEFAULT_STALL_RPM_THRESHOLD: f32 = 0.02;
+pub const DEFAULT_STALL_TIME_MS: u64 = 500;
+
+/// IMPORTANT: #[serde(transparent)] so that cu-zenoh-src decodes the raw u8 from the wire, same as zsrc-merger
+/// 1 - reset, 0 - idle. Only the rising edge resets.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Encode, Decode)]
+#[serde(transparent)]
+#[derive(Reflect)]
+pub struct BstnStallReset(pub u8);
+
+/// Latched until a BstnStallReset rising edge
+#[derive(Debug, Clone, Copy, Default, Encode, Decode, PartialEq, Serialize, Deserialize)]
+#[derive(Reflect)]
+#[reflect(no_field_bounds, from_reflect = false)]
+pub struct StallFaultPayload {
+    pub lmtr_stalled: bool,
+    pub rmtr_stalled: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct StallCfg {
+    pub duty_threshold: f32,
+    pub rpm_threshold: f32,
+    pub stall_time_ms: u64,
+    /// Only corroborates when the INA219 reading is there, a missing reading doesn't block detection
+    pub current_threshold_mamps: Option<f64>,
+}
+
+impl Default for StallCfg {
+    fn default() -> Self {
+        Self {
+            duty_threshold: DEFAULT_STALL_DUTY_THRESHOLD,
+            rpm_threshold: DEFAULT_STALL_RPM_THRESHOLD,
+            stall_time_ms: DEFAULT_STALL_TIME_MS,
+            current_threshold_mamps: None,
+        }
+    }
+}
+
+/// One per motor
+#[derive(Debug, Default)]
+pub struct StallDetector {
+    suspect_since: Option<CuTime>,
+    pub latched: bool,
+}
+
+impl StallDetector {
+    /// Returns true on the tick the fault latches. `now` is the robot clock.
+    pub fn update(&mut self, cfg: &StallCfg, now: CuTime, duty: f32, normalized_rpm: Option<f32>, current_mamps: Option<f64>) -> bool {
+        if self.latched {
+            return false;
+        }
+
+        let current_agrees = match (cfg.current_threshold_mamps, current_mamps) {
+            (Some(threshold), Some(current)) => current.abs() >= threshold,
+            _ => true,
+        };
+
+        // no encoder reading means we can't tell, don't accumulate
+        let suspect = duty >= cfg.duty_threshold
+            && normalized_rpm.is_some_and(|rpm| rpm.abs() <= cfg.rpm_threshold)
+            && current_agrees;
+
+        if !suspect {
+            self.suspect_since = None;
+            return false;
+        }
+
+        let since = *self.suspect_since.get_or_insert(now);
+        let elapsed_ns = now.as_nanos().saturating_sub(since.as_nanos());
+
+        if CuDuration::from_nanos(elapsed_ns) >= CuDuration::from_millis(cfg.stall_time_ms) {
+            self.latched = true;
+            self.suspect_since = None;
+            return true;
+        }
+        false
+    }
+
+    pub fn reset(&mut self) {
+        self.latched = false;
+        self.suspect_since = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const CYCLE: Duration = Duration::from_millis(18);
+
+    /// Feeds the same sample every cycle for `ms`, stops at the latch and returns how long it took
+    fn run(d: &mut StallDetector, cfg: &StallCfg, clock: &(RobotClock, RobotClockMock), ms: u64,
+        duty: f32, rpm: Option<f32>, current: Option<f64>) -> Option<u64>
+    {
+        let (clock, mock) = clock;
+        let start = clock.now().as_nanos();
+        while clock.now().as_nanos() - start <= ms * 1_000_000 {
+            if d.update(cfg, clock.now(), duty, rpm, current) {
+                return Some((clock.now().as_nanos() - start) / 1_000_000);
+            }
+            mock.increment(CYCLE);
+        }
+        None
+    }
+
+    #[test]
+    fn latches_after_the_hold_time() {
+        let cfg = StallCfg::default();
+        let clock = RobotClock::mock();
+        let mut d = StallDetector::default();
+
+        let latched_after = run(&mut d, &cfg, &clock, 2000, 0.6, Some(0.0), None).unwrap();
+        assert!(latched_after >= cfg.stall_time_ms && latched_after < cfg.stall_time_ms + 18, "latched after {latched_after}ms");
+        assert!(d.latched);
+    }
+
+    #[test]
+    fn a_turning_wheel_restarts_the_hold_time() {
+        let cfg = StallCfg::default();
+        let clock = RobotClock::mock();
+        let mut d = StallDetector::default();
+
+        assert_eq!(run(&mut d, &cfg, &clock, cfg.stall_time_ms - 100, 0.6, Some(0.0), None), None);
+        assert_eq!(run(&mut d, &cfg, &clock, 0, 0.6, Some(0.3), None), None);
+        assert_eq!(run(&mut d, &cfg, &clock, cfg.stall_time_ms - 100, 0.6, Some(0.0), None), None);
+        assert!(!d.latched);
+    }
+
+    #[test]
+    fn no_latch_below_the_duty_threshold() {
+        let cfg = StallCfg::default();
+        let clock = RobotClock::mock();
+        let mut d = StallDetector::default();
+
+        assert_eq!(run(&mut d, &cfg, &clock, 5000, cfg.duty_threshold - 0.01, Some(0.0), None), None);
+        // no encoder reading, can't tell
+        assert_eq!(run(&mut d, &cfg, &clock, 5000, 1.0, None, None), None);
+        assert!(!d.latched);
+    }
+
+    #[test]
+    fn current_corroborates_when_there_is_a_reading() {
+        let cfg = StallCfg { current_threshold_mamps: Some(500.0), ..Default::default() };
+        let clock = RobotClock::mock();
+
+        // a jammed encoder on a free wheel draws little current
+        let mut d = StallDetector::default();
+        assert_eq!(run(&mut d, &cfg, &clock, 5000, 0.6, Some(0.0), Some(200.0)), None);
+
+        // either sign, the INA219 reads negative when the motor is reversed
+        let mut d = StallDetector::default();
+        assert!(run(&mut d, &cfg, &clock, 2000, 0.6, Some(0.0), Some(-700.0)).is_some());
+
+        // no reading doesn't block it
+        let mut d = StallDetector::default();
+        assert!(run(&mut d, &cfg, &clock, 2000, 0.6, Some(0.0), None).is_some());
+    }
+
+    #[test]
+    fn latch_holds_until_reset() {
+        let cfg = StallCfg::default();
+        let clock = RobotClock::mock();
+        let mut d = StallDetector::default();
+        assert!(run(&mut d, &cfg, &clock, 2000, 0.6, Some(0.0), None).is_some());
+
+        // the motor is cut, nothing suspect any more, still latched and only reported once
+        assert_eq!(run(&mut d, &cfg, &clock, 5000, 0.0, Some(0.0), None), None);
+        assert_eq!(run(&mut d, &cfg, &clock, 5000, 0.6, Some(0.0), None), None);
+        assert!(d.latched);
+
+        d.reset();
+        assert!(!d.latched);
+        // still jammed, it takes the full hold time again
+        let latched_after = run(&mut d, &cfg, &clock, 2000, 0.6, Some(0.0), None).unwrap();
+        assert!(latched_after >= cfg.stall_time_ms, "latched after {latched_after}ms");
+    }
+}
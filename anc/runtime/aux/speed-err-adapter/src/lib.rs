@@ -1,9 +1,13 @@
-
 use cu29::prelude::*;
 use cu_irencoder::IrEncoderPayload;
-use speed_ctrlrs::{LmtrSpeedErrPayload, RmtrSpeedErrPayload};
+use speed_ctrlrs::{LmtrSpeedErrPayload, RmtrSpeedErrPayload, SetpointMode, WheelVelocitySetpointPayload};
 use cu_propulsion::PropulsionPayload;
 
+/// Must match max_rpm of the encoder-pair task, encoder readings are normalized by it
+pub const DEFAULT_MAX_RPM: f32 = 600.0;
+/// Below this |setpoint| a wheel is considered commanded to stop
+pub const MIN_RPM_SETPOINT: f32 = 0.5;
+
 #[derive(Reflect)]
 #[reflect(no_field_bounds, from_reflect = false)]
 pub struct SpeedErrAdapter {
@@ -11,47 +15,105 @@ pub struct SpeedErrAdapter {
     rmtr_speed_err: Option<f32>,
     lmtr_actual: f32,
     rmtr_actual: f32,
+    #[reflect(ignore)]
+    setpoint_mode: SetpointMode,
+    max_rpm: f32,
 }
 
 impl Freezable for SpeedErrAdapter {}
 
 impl CuTask for SpeedErrAdapter {
-    type Input<'m> = input_msg!('m, IrEncoderPayload, PropulsionPayload);
+    type Input<'m> = input_msg!('m, IrEncoderPayload, PropulsionPayload, WheelVelocitySetpointPayload);
     type Output<'m> = output_msg!(LmtrSpeedErrPayload, RmtrSpeedErrPayload);
     type Resources<'r> = ();
 
-    fn new(_config: Option<&ComponentConfig>, _resources: Self::Resources<'_>) -> CuResult<Self>
+    fn new(config: Option<&ComponentConfig>, _resources: Self::Resources<'_>) -> CuResult<Self>
     where Self: Sized
     {
+        let mut setpoint_mode = SetpointMode::default();
+        let mut max_rpm = DEFAULT_MAX_RPM;
+
+        if let Some(ComponentConfig(kv)) = config {
+            if let Some(v) = kv.get("setpoint_mode") {
+                let s: String = v.clone().into();
+                setpoint_mode = SetpointMode::parse(&s).map_err(|e| CuError::from(e.as_str()))?;
+            }
+
+            if let Some(v) = kv.get("max_rpm") {
+                let f: f64 = v.clone().into();
+                max_rpm = f as f32;
+            }
+        }
+
         Ok(Self {
             lmtr_speed_err: None,
             rmtr_speed_err: None,
             lmtr_actual: 0.0,
             rmtr_actual: 0.0,
+            setpoint_mode,
+            max_rpm,
         })
     }
 
     fn process(&mut self, clock: &RobotClock, input: &Self::Input<'_>, output: &mut Self::Output<'_>,)
     -> CuResult<()>
     {
-        let actual_speed = input.0.payload();
         // used in pid output calculations
         output.0.tov = Tov::Time(clock.now());
         output.1.tov = Tov::Time(clock.now());
 
+        match self.setpoint_mode {
+            SetpointMode::Ratio => self.ratio_errors(input.0.payload(), input.1.payload()),
+            SetpointMode::Velocity => self.velocity_errors(input.0.payload(), input.2.payload()),
+        }
+
+        if let (Some(lmtr_speed_err), Some(rmtr_speed_err)) = (self.lmtr_speed_err, self.rmtr_speed_err) {
+            output.0.set_payload(LmtrSpeedErrPayload { error: lmtr_speed_err });
+            output.1.set_payload(RmtrSpeedErrPayload { error: rmtr_speed_err });
+        }
+
+        Ok(())
+    }
+
+}
+
+impl SpeedErrAdapter {
+    /// Each wheel tracks its own setpoint, in rpm.
+    /// Magnitudes are compared since the speed loops only correct the duty cycle, direction is
+    /// carried separately by PropulsionPayload.
+    fn velocity_errors(&mut self, actual_speed: Option<&IrEncoderPayload>, setpoint: Option<&WheelVelocitySetpointPayload>) {
         if let Some(actual_speed) = actual_speed {
             if let Some(lmtr) = actual_speed.lmtr_normalized_rpm {
-                self.lmtr_actual = lmtr.clamp(0.0, 0.9);
+                self.lmtr_actual = lmtr.abs() * self.max_rpm;
             }
             if let Some(rmtr) = actual_speed.rmtr_normalized_rpm {
-                self.rmtr_actual = rmtr.clamp(0.0, 0.9);
+                self.rmtr_actual = rmtr.abs() * self.max_rpm;
+            }
+        }
+
+        let (lmtr_target, rmtr_target) = setpoint
+            .map(|s| (s.lmtr_rpm.abs(), s.rmtr_rpm.abs()))
+            .unwrap_or((0.0, 0.0));
+
+        // Zero out errors per wheel when stopped to prevent integral windup in downstream PIDs
+        self.lmtr_speed_err = Some(if lmtr_target < MIN_RPM_SETPOINT { 0.0 } else { self.lmtr_actual - lmtr_target });
+        self.rmtr_speed_err = Some(if rmtr_target < MIN_RPM_SETPOINT { 0.0 } else { self.rmtr_actual - rmtr_target });
+    }
+
+    /// Legacy mode, normalized units
+    fn ratio_errors(&mut self, actual_speed: Option<&IrEncoderPayload>, cmd: Option<&PropulsionPayload>) {
+        if let Some(actual_speed) = actual_speed {
+            if let Some(lmtr) = actual_speed.lmtr_normalized_rpm {
+                self.lmtr_actual = lmtr.abs().clamp(0.0, 0.9);
+            }
+            if let Some(rmtr) = actual_speed.rmtr_normalized_rpm {
+                self.rmtr_actual = rmtr.abs().clamp(0.0, 0.9);
             }
         }
 
         // lane PID controller will give a distribution ratio setpoint
         // in 'openloop' (blind, no vision input) the command left and right should be the same when the intention
         // is to go straihgt
-        let cmd = input.1.payload();
         let (lmtr_target_ratio, rmtr_target_ratio, cmd_is_zero) = if let Some(cmd) = cmd {
             let total_cmd = cmd.left_speed + cmd.right_speed;
             if total_cmd > 0.0 {
@@ -75,13 +137,5 @@ impl CuTask for SpeedErrAdapter {
             self.lmtr_speed_err = Some(self.lmtr_actual - lmtr_target);
             self.rmtr_speed_err = Some(self.rmtr_actual - rmtr_target);
         }
-
-        if let (Some(lmtr_speed_err), Some(rmtr_speed_err)) = (self.lmtr_speed_err, self.rmtr_speed_err) {
-            output.0.set_payload(LmtrSpeedErrPayload { error: lmtr_speed_err });
-            output.1.set_payload(RmtrSpeedErrPayload { error: rmtr_speed_err });
-        }
-
-        Ok(())
     }
-
 }
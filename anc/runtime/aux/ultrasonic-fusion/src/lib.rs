@@ -0,0 +1,158 @@
+extern crate cu_bincode as bincode;
+use cu29::prelude::*;
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use cu_hcsr04::{HcSr04ArrayPayload, HcSr04Payload, SensorPosition};
+
+/// One reading per side of the robot. A side with no sensor mounted stays at default and is marked
+/// in `present` (indexed by SensorPosition), use `get` so that isn't mistaken for a clear side.
+#[derive(Debug, Clone, Copy, Default, Encode, Decode, PartialEq, Serialize, Deserialize)]
+#[derive(Reflect)]
+#[reflect(no_field_bounds, from_reflect = false)]
+pub struct SurroundDistancePayload {
+    pub front: HcSr04Payload,
+    pub left: HcSr04Payload,
+    pub right: HcSr04Payload,
+    pub rear: HcSr04Payload,
+    pub present: [bool; 4],
+}
+
+impl SurroundDistancePayload {
+    /// None if no sensor is mounted on that side
+    pub fn get(&self, position: SensorPosition) -> Option<&HcSr04Payload> {
+        if !self.present[position as usize] {
+            return None;
+        }
+        Some(match position {
+            SensorPosition::Front => &self.front,
+            SensorPosition::Left => &self.left,
+            SensorPosition::Right => &self.right,
+            SensorPosition::Rear => &self.rear,
+        })
+    }
+
+    fn get_mut(&mut self, position: SensorPosition) -> &mut HcSr04Payload {
+        match position {
+            SensorPosition::Front => &mut self.front,
+            SensorPosition::Left => &mut self.left,
+            SensorPosition::Right => &mut self.right,
+            SensorPosition::Rear => &mut self.rear,
+        }
+    }
+}
+
+/// Keeps the closer of two optional readings, a missing one never wins
+fn closest(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// Fuses the cu-hcsr04 array into front/left/right/rear. Several sensors on one side (e.g. two
+/// angled front sensors) are combined conservatively: the closest distance, the freshest echo, and
+/// timed out only if every sensor on that side is. Unavailable sensors are left out, a side is only
+/// unavailable if all of its sensors are.
+#[derive(Reflect)]
+#[reflect(no_field_bounds, from_reflect = false)]
+pub struct UltrasonicFusion {}
+
+impl Freezable for UltrasonicFusion {}
+
+impl CuTask for UltrasonicFusion {
+    type Input<'m> = input_msg!('m, HcSr04ArrayPayload);
+    type Output<'m> = output_msg!(SurroundDistancePayload);
+    type Resources<'r> = ();
+
+    fn new(_config: Option<&ComponentConfig>, _resources: Self::Resources<'_>) -> CuResult<Self>
+    where Self: Sized
+    {
+        Ok(Self {})
+    }
+
+    fn process(&mut self, _clock: &RobotClock, input: &Self::Input<'_>, output: &mut Self::Output<'_>,)
+    -> CuResult<()>
+    {
+        let Some(array) = input.payload() else {
+            return Ok(());
+        };
+
+        let mut res = SurroundDistancePayload::default();
+
+        let count = (array.count as usize).min(array.readings.len());
+        for (position, reading) in array.positions[..count].iter().zip(&array.readings[..count]) {
+            let idx = *position as usize;
+            if !res.present[idx] {
+                res.present[idx] = true;
+                *res.get_mut(*position) = *reading;
+                continue;
+            }
+
+            let fused = res.get_mut(*position);
+            if !reading.available() {
+                continue;
+            }
+            if !fused.available() {
+                *fused = *reading;
+                continue;
+            }
+            fused.distance = closest(fused.distance, reading.distance);
+            // a republished echo was already counted, only new ones are fused
+            match (fused.fresh, reading.fresh) {
+                (true, true) => fused.raw_distance = closest(fused.raw_distance, reading.raw_distance),
+                (false, true) => {
+                    fused.raw_distance = reading.raw_distance;
+                    fused.fresh = true;
+                },
+                _ => (),
+            }
+            fused.last_valid_age_ms = match (fused.last_valid_age_ms, reading.last_valid_age_ms) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, None) => a,
+                (None, b) => b,
+            };
+            fused.timed_out = fused.timed_out && reading.timed_out;
+        }
+
+        output.set_payload(res);
+        output.metadata.set_status(format!("f: {:?} r: {:?}", res.front.distance.map(|d| d as u32), res.rear.distance.map(|d| d as u32)));
+        Ok(())
+    }
+}
+
+/// Puts the single-sensor cu_hcsr04::CuHcSr04 on the front side, for graphs that run that driver
+/// instead of the array. The other sides are marked absent.
+#[derive(Reflect)]
+#[reflect(no_field_bounds, from_reflect = false)]
+pub struct FrontSensorFusion {}
+
+impl Freezable for FrontSensorFusion {}
+
+impl CuTask for FrontSensorFusion {
+    type Input<'m> = input_msg!('m, HcSr04Payload);
+    type Output<'m> = output_msg!(SurroundDistancePayload);
+    type Resources<'r> = ();
+
+    fn new(_config: Option<&ComponentConfig>, _resources: Self::Resources<'_>) -> CuResult<Self>
+    where Self: Sized
+    {
+        Ok(Self {})
+    }
+
+    fn process(&mut self, _clock: &RobotClock, input: &Self::Input<'_>, output: &mut Self::Output<'_>,)
+    -> CuResult<()>
+    {
+        let Some(front) = input.payload() else {
+            return Ok(());
+        };
+
+        let mut res = SurroundDistancePayload::default();
+        res.present[SensorPosition::Front as usize] = true;
+        res.front = *front;
+
+        output.set_payload(res);
+        output.metadata.set_status(format!("f: {:?}", res.front.distance.map(|d| d as u32)));
+        Ok(())
+    }
+}
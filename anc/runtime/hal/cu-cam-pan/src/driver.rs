@@ -0,0 +1,99 @@
+use cu29::prelude::*;
+use crate::{PanSlew, ServoCalib};
+
+/// Hold at center after enabling the PWM, the SG90 needs this to find its way from wherever it was
+pub const DEFAULT_INIT_SETTLE_MS: u64 = 1750;
+/// A stalled cycle doesn't turn into a big jump, the servo catches up over the next steps instead
+pub(crate) const MAX_STEP_S: f32 = 0.05;
+
+/// Where the interpolation runs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DriveMode {
+    /// Stepped from process() on the robot clock, deterministic and shows up in the copper log
+    #[default]
+    Cycle,
+    /// Own SCHED_RR thread stepping every 10ms, for when the task rate is too low for a smooth slew
+    Thread,
+}
+
+impl DriveMode {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "cycle" => Ok(DriveMode::Cycle),
+            "thread" => Ok(DriveMode::Thread),
+            _ => Err(format!("Invalid drive_mode value: \"{s}\". Valid values: \"cycle\", \"thread\"")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PanDriveState {
+    #[default]
+    Idle,
+    Initializing,
+    Tracking,
+}
+
+/// Non-blocking servo driver. Each step returns the angle to write to the PWM, if any. Same state
+/// machine in both drive modes, only the caller differs.
+#[derive(Debug, Default)]
+pub struct PanStepper {
+    pub state: PanDriveState,
+    pub slew: PanSlew,
+    pub init_settle_ms: u64,
+    state_since: CuTime,
+    last_step: CuTime,
+}
+
+impl PanStepper {
+    pub fn new(init_settle_ms: u64) -> Self {
+        Self { init_settle_ms, ..Default::default() }
+    }
+
+    fn enter(&mut self, state: PanDriveState, now: CuTime) {
+        eprintln!("CAM PAN: {:?} -> {:?}", self.state, state);
+        self.state = state;
+        self.state_since = now;
+        self.last_step = now;
+    }
+
+    pub fn step(&mut self, calib: &ServoCalib, now: CuTime, target_deg: f32) -> Option<f32> {
+        match self.state {
+            PanDriveState::Idle => {
+                self.slew = PanSlew::default();
+                self.enter(PanDriveState::Initializing, now);
+                Some(0.0)
+            },
+            PanDriveState::Initializing => {
+                let elapsed_ns = now.as_nanos().saturating_sub(self.state_since.as_nanos());
+                if elapsed_ns >= CuDuration::from_millis(self.init_settle_ms).as_nanos() {
+                    self.enter(PanDriveState::Tracking, now);
+                }
+                None
+            },
+            PanDriveState::Tracking => {
+                let dt_s = now.as_nanos().saturating_sub(self.last_step.as_nanos()) as f32 / 1_000_000_000.0;
+                self.last_step = now;
+
+                self.slew.set_target(calib, target_deg);
+                if self.slew.settled() {
+                    return None;
+                }
+                Some(self.slew.step(calib, dt_s.min(MAX_STEP_S)))
+            },
+        }
+    }
+
+    /// One interpolation step back to center, None once it's there
+    pub fn park_step(&mut self, calib: &ServoCalib, dt_s: f32) -> Option<f32> {
+        self.slew.set_target(calib, 0.0);
+        if self.slew.settled() {
+            return None;
+        }
+        Some(self.slew.step(calib, dt_s))
+    }
+
+    pub fn angle_deg(&self) -> f32 {
+        self.slew.angle_deg
+    }
+}
@@ -1,5 +1,13 @@
 extern crate cu_bincode as bincode;
-use std::{str::FromStr, sync::{Arc, Mutex, atomic::{AtomicBool, AtomicU8, Ordering}}};
+
+mod servo;
+mod scan;
+mod driver;
+pub use servo::*;
+pub use scan::*;
+pub use driver::*;
+
+use std::{sync::{Arc, Mutex, atomic::{AtomicBool, AtomicU32, Ordering}}};
 use std::thread::{JoinHandle, Builder, sleep};
 use std::time::Duration;
 use libc::*;
@@ -7,24 +15,41 @@ use dumb_sysfs_pwm::{Pwm, PwmBuilder};
 use cu29::prelude::*;
 use bincode::{Decode, Encode};
 use serde::{Deserialize, Serialize};
+use cu_health::required_key;
 
-/// We're assuming that during operation, we won't send commands before the previous sent command has been
-/// fully actuated by the servo. The SG90 is a cheap, crappy servo that easily gets confused by quick
-/// changes in the PWM duty cycle. No point in overengineering the code (it already is) just to handle
-/// HW race conditions arising from cheap HW.
+/// The SG90 is a cheap, crappy servo that easily gets confused by quick changes in the PWM duty cycle,
+/// so the commanded angle is slewed toward the target at max_slew_deg_s instead of jumping. A new
+/// target can arrive at any time, the slew just turns around.
+/// By default the slew is stepped from process() (drive_mode "cycle"), see driver.rs.
 
 const PERIOD_NS: u32 = 20000000; /// Period in ns for 50Hz
-const DUTY_CYCLE_POS_FRONT: f32 = 0.075; /// 1.5ms out of 20ms
-const DUTY_CYCLE_POS_LEFT: f32 = 0.1; /// 1.0ms out of 20ms
-const DUTY_CYCLE_POS_RIGHT: f32 = 0.05; /// 2.0ms out of 20ms
-const IPOLATE_DIV: f32 = 1000.0;
+/// Interpolation step in thread mode and while parking, one servo frame is 20ms so there's no point going finer
+const STEP_MS: u64 = 10;
+/// Time for the servo to reach center before the PWM is switched off
+const PARK_SETTLE_MS: u64 = 900;
 
-/// this payload has no HW feedback
+/// `angle_deg` overrides the `pos_cmd` preset when set. Positive is left, 0 is front.
+/// `scan` overrides both and sweeps the scan schedule for as long as it is set.
 #[derive(Debug, Clone, Copy, Default, Encode, Decode, PartialEq, Serialize, Deserialize)]
 #[derive(Reflect)]
 #[reflect(no_field_bounds, from_reflect = false)]
 pub struct CameraPanningPayload {
     pub pos_cmd: PositionCommand,
+    pub angle_deg: Option<f32>,
+    pub scan: bool,
+}
+
+/// No HW feedback, `angle_deg` is estimated from the slew. `settled` once it has reached `target_deg`.
+/// While scanning, `scan_step` is the step the servo is dwelling on, None while moving between steps.
+#[derive(Debug, Clone, Copy, Default, Encode, Decode, PartialEq, Serialize, Deserialize)]
+#[derive(Reflect)]
+#[reflect(no_field_bounds, from_reflect = false)]
+pub struct PanStatePayload {
+    pub angle_deg: f32,
+    pub target_deg: f32,
+    pub settled: bool,
+    pub scanning: bool,
+    pub scan_step: Option<u16>,
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Encode, Decode, Serialize, Deserialize)]
@@ -37,23 +62,25 @@ pub enum PositionCommand {
     Right
 }
 
-impl PositionCommand {
-    #[inline(always)]
-    fn to_u8(self) -> u8 {
-        match self {
-            PositionCommand::Front => 0,
-            PositionCommand::Left  => 1,
-            PositionCommand::Right => 2,
-        }
+/// Preset angles, "preset_left_deg" / "preset_right_deg" in the RON config
+#[derive(Debug, Clone, Copy)]
+pub struct PanPresets {
+    pub left_deg: f32,
+    pub right_deg: f32,
+}
+
+impl Default for PanPresets {
+    fn default() -> Self {
+        Self { left_deg: DEFAULT_PRESET_LEFT_DEG, right_deg: DEFAULT_PRESET_RIGHT_DEG }
     }
+}
 
-    #[inline(always)]
-    fn from_u8(x: u8) -> PositionCommand {
-        match x {
-            0 => PositionCommand::Front,
-            1 => PositionCommand::Left,
-            2 => PositionCommand::Right,
-            _ => PositionCommand::Front,  // fallback / sanitize
+impl PositionCommand {
+    pub fn to_angle_deg(self, presets: &PanPresets) -> f32 {
+        match self {
+            PositionCommand::Front => 0.0,
+            PositionCommand::Left  => presets.left_deg,
+            PositionCommand::Right => presets.right_deg,
         }
     }
 }
@@ -62,11 +89,59 @@ pub struct CameraPanningControllerInstances {
     sg90_pos_cmd: Pwm
 }
 
+impl CameraPanningControllerInstances {
+    fn enable(&mut self) -> CuResult<()> {
+        // make sure PWM params are initialized
+        _ = self.sg90_pos_cmd.set_period_ns(PERIOD_NS);
+
+        // check if controller is enabled yet
+        if !self.sg90_pos_cmd.get_enable() {
+            self.sg90_pos_cmd.set_enable(true)
+                .map_err(|e| CuError::from(format!("cu-cam-pan: failed to enable PWM: {e:?}")))?;
+        }
+        Ok(())
+    }
+
+    fn write_angle(&mut self, calib: &ServoCalib, angle_deg: f32) {
+        _ = self.sg90_pos_cmd.set_duty_cycle(calib.duty_cycle(angle_deg, PERIOD_NS));
+    }
+
+    /// Blocking: slews back to center, waits for the servo and switches the PWM off. Only for shutdown.
+    fn park(&mut self, calib: &ServoCalib, stepper: &mut PanStepper) {
+        let step_s = STEP_MS as f32 / 1000.0;
+        while let Some(angle) = stepper.park_step(calib, step_s) {
+            self.write_angle(calib, angle);
+            sleep(Duration::from_millis(STEP_MS));
+        }
+        sleep(Duration::from_millis(PARK_SETTLE_MS));
+
+        _ = self.sg90_pos_cmd.set_enable(false);
+        _ = self.sg90_pos_cmd.set_duty_cycle(0.0);
+        _ = self.sg90_pos_cmd.unexport();
+    }
+}
+
 #[derive(Reflect)]
 #[reflect(no_field_bounds, from_reflect = false)]
 pub struct CameraPanning {
     task_running: Arc<AtomicBool>,
-    recvd_pos_cmd: Arc<AtomicU8>,
+    /// f32 bits, degrees
+    target_deg: Arc<AtomicU32>,
+    /// f32 bits, degrees, written by whoever steps the servo
+    estimated_deg: Arc<AtomicU32>,
+    #[reflect(ignore)]
+    calib: ServoCalib,
+    #[reflect(ignore)]
+    presets: PanPresets,
+    #[reflect(ignore)]
+    scan_cfg: ScanCfg,
+    #[reflect(ignore)]
+    scan: PanScan,
+    #[reflect(ignore)]
+    drive_mode: DriveMode,
+    /// Cycle mode only, thread mode moves it into the thread
+    #[reflect(ignore)]
+    stepper: Option<PanStepper>,
     #[reflect(ignore)]
     pin_controller_instances: Arc<Mutex<CameraPanningControllerInstances>>,
     #[reflect(ignore)]
@@ -75,43 +150,100 @@ pub struct CameraPanning {
 
 impl Freezable for CameraPanning {}
 
-impl CuSinkTask for CameraPanning {
-    type Input<'m> = input_msg!(CameraPanningPayload);
+impl CuTask for CameraPanning {
+    type Input<'m> = input_msg!('m, CameraPanningPayload);
+    type Output<'m> = output_msg!(PanStatePayload);
     type Resources<'r> = ();
 
     fn new(config: Option<&ComponentConfig>,  _resources: Self::Resources<'_>) -> Result<Self, CuError>
     where Self: Sized
     {
-        let ComponentConfig(kv) =
-            config.ok_or("No ComponentConfig specified for GPIO in RON")?;
+        let config = config.ok_or("No ComponentConfig specified for GPIO in RON")?;
+        let ComponentConfig(kv) = config;
+
+        let sg90_pos_cmd_pin_offset: u32 = required_key(config, "CameraPanning", "sg90_pos_cmd_pin")?;
+
+        let get_f32 = |key: &str| kv.get(key).map(|v| { let f: f64 = v.clone().into(); f as f32 });
+
+        let mut calib = ServoCalib::default();
+        if let Some(f) = get_f32("min_pulse_us") { calib.min_pulse_us = f; }
+        if let Some(f) = get_f32("max_pulse_us") { calib.max_pulse_us = f; }
+        if let Some(f) = get_f32("center_trim_us") { calib.center_trim_us = f; }
+        if let Some(f) = get_f32("range_deg") { calib.range_deg = f; }
+        if let Some(f) = get_f32("max_slew_deg_s") { calib.max_slew_deg_s = f; }
 
-        let sg90_pos_cmd_pin_offset: u32 = kv
-            .get("sg90_pos_cmd_pin")
-            .expect("l298n_en_a for Propulsion not set in RON config")
-            .clone()
-            .into();
+        if calib.min_pulse_us >= calib.max_pulse_us || calib.max_pulse_us * 1000.0 > PERIOD_NS as f32 {
+            return Err(CuError::from("CameraPanning: need min_pulse_us < max_pulse_us <= 20000"));
+        }
+        if calib.range_deg <= 0.0 || calib.max_slew_deg_s <= 0.0 {
+            return Err(CuError::from("CameraPanning: range_deg and max_slew_deg_s must be positive"));
+        }
+
+        let mut presets = PanPresets::default();
+        if let Some(f) = get_f32("preset_left_deg") { presets.left_deg = calib.clamp_angle(f); }
+        if let Some(f) = get_f32("preset_right_deg") { presets.right_deg = calib.clamp_angle(f); }
+
+        let mut scan_cfg = ScanCfg::default();
+        if let Some(f) = get_f32("scan_min_deg") { scan_cfg.min_deg = calib.clamp_angle(f); }
+        if let Some(f) = get_f32("scan_max_deg") { scan_cfg.max_deg = calib.clamp_angle(f); }
+        if let Some(f) = get_f32("scan_step_deg") { scan_cfg.step_deg = f; }
+        if let Some(f) = get_f32("scan_dwell_ms") { scan_cfg.dwell_ms = f as u64; }
+        scan_cfg.end_dwell_ms = get_f32("scan_end_dwell_ms").map_or(scan_cfg.dwell_ms, |f| f as u64);
+
+        if scan_cfg.min_deg > scan_cfg.max_deg || scan_cfg.step_deg <= 0.0 {
+            return Err(CuError::from("CameraPanning: need scan_min_deg <= scan_max_deg and a positive scan_step_deg"));
+        }
 
-        let sg90_pos_cmd_instance = PwmBuilder::new(0, sg90_pos_cmd_pin_offset, PERIOD_NS).build().unwrap();
+        let drive_mode = match kv.get("drive_mode") {
+            Some(v) => {
+                let s: String = v.clone().into();
+                DriveMode::parse(&s).map_err(|e| CuError::from(e.as_str()))?
+            },
+            None => DriveMode::default(),
+        };
+        let init_settle_ms = get_f32("init_settle_ms").map_or(DEFAULT_INIT_SETTLE_MS, |f| f as u64);
+
+        let sg90_pos_cmd_instance = PwmBuilder::new(0, sg90_pos_cmd_pin_offset, PERIOD_NS).build()
+            .map_err(|e| CuError::from(format!("CameraPanning: failed to export PWM channel {sg90_pos_cmd_pin_offset}: {e}")))?;
         let pin_controller_instances = CameraPanningControllerInstances {
             sg90_pos_cmd: sg90_pos_cmd_instance
         };
 
         Ok(Self {
             task_running: Arc::new(AtomicBool::new(true)),
-            recvd_pos_cmd: Arc::new(AtomicU8::new(PositionCommand::to_u8(PositionCommand::default()))),
+            target_deg: Arc::new(AtomicU32::new(0.0f32.to_bits())),
+            estimated_deg: Arc::new(AtomicU32::new(0.0f32.to_bits())),
+            calib,
+            presets,
+            scan_cfg,
+            scan: PanScan::default(),
+            drive_mode,
+            stepper: Some(PanStepper::new(init_settle_ms)),
             ipolate_thread_hdl: None,
             pin_controller_instances: Arc::new(Mutex::new(pin_controller_instances)),
         })
     }
 
-    fn start(&mut self, _clock: &RobotClock) -> CuResult<()> {
+    fn start(&mut self, clock: &RobotClock) -> CuResult<()> {
+        if self.drive_mode == DriveMode::Cycle {
+            // stepped from process(), nothing else to set up
+            return self.pin_controller_instances.lock()
+                .map_err(|_| CuError::from("cu-cam-pan: controller lock poisoned"))?
+                .enable();
+        }
 
+        let Some(mut stepper) = self.stepper.take() else {
+            return Err(CuError::from("cu-cam-pan: already started"));
+        };
         let task_running = Arc::clone(&self.task_running);
-        let pos_cmd = Arc::clone(&self.recvd_pos_cmd);
+        let target_deg = Arc::clone(&self.target_deg);
+        let estimated_deg = Arc::clone(&self.estimated_deg);
+        let calib = self.calib;
         let controller = Arc::clone(&self.pin_controller_instances);
+        let clock = clock.clone();
 
         let ipolate_thread_hdl = Builder::new()
-            .name(String::from_str("cu-cam-pan-ipolate-thread").unwrap())
+            .name(String::from("cu-cam-pan-ipolate-thread"))
             .stack_size(8 * 1024 * 1024)
             .spawn(move || -> CuResult<()> {
             #[cfg(target_os = "linux")]
@@ -126,112 +258,105 @@ impl CuSinkTask for CameraPanning {
                     info!("cu-cam-pan ipolate thread: sched_setscheduler call returned 0");
                 },
                 _ => { // Refer here: https://man7.org/linux/man-pages/man2/sched_setscheduler.2.html
-                    return Err(CuError::from("cu-cam-pan ipolate thread: sched_setscheduler call returned -1. sched_setscheduler failed."));
+                    // not fatal, the slew is just less smooth under load
+                    eprintln!("CAM PAN: sched_setscheduler failed, ipolate thread runs with the default policy");
                 }
             }
 
-            let mut controller = match controller.try_lock() {
-                Ok(ret) => ret,
-                Err(_) => return Err(CuError::from(format!("Failed to acquire lock on CameraPanningControllerInstances")))
-            };
+            let lock = || controller.lock()
+                .map_err(|_| CuError::from("cu-cam-pan ipolate thread: controller lock poisoned"));
 
-            // make sure PWM params are initialized
-            _ = controller.sg90_pos_cmd.set_period_ns(PERIOD_NS);
-
-            // check if controller is enabled yet
-            if !controller.sg90_pos_cmd.get_enable() {
-                controller.sg90_pos_cmd.set_enable(true).unwrap();
-                // should probably add a reset routine here, with a helper function to make sure the servo
-                // resets to the front position
-            }
-
-            // Initialize at middle position
-            _ = controller.sg90_pos_cmd.set_duty_cycle(0.075);
-            sleep(Duration::from_millis(1750));
-
-            let mut current_duty_cycle = (DUTY_CYCLE_POS_FRONT * IPOLATE_DIV) as u32;
+            lock()?.enable()?;
 
             while task_running.load(Ordering::Relaxed) {
-                let pos_cmd_copy = PositionCommand::from_u8(pos_cmd.load(Ordering::Acquire));
-                // FIXME: reduce casts
-                let target_duty_cycle = match pos_cmd_copy {
-                    PositionCommand::Front => {
-                        (DUTY_CYCLE_POS_FRONT * IPOLATE_DIV) as u32
-                    },
-                    PositionCommand::Left => {
-                        (DUTY_CYCLE_POS_LEFT * IPOLATE_DIV) as u32
-                    },
-                    PositionCommand::Right => {
-                        (DUTY_CYCLE_POS_RIGHT * IPOLATE_DIV) as u32
-                    }
-                };
-
-                if target_duty_cycle != current_duty_cycle {
-                    if current_duty_cycle < target_duty_cycle {
-                        for duty_cycle in (current_duty_cycle..=target_duty_cycle).step_by(1) {
-                            _ = controller.sg90_pos_cmd.set_duty_cycle(duty_cycle as f32 / IPOLATE_DIV);
-                            sleep(Duration::from_millis(10));
-                        }
-                    }
-                    else {
-                        for duty_cycle in (target_duty_cycle..=current_duty_cycle).rev().step_by(1) {
-                            _ = controller.sg90_pos_cmd.set_duty_cycle(duty_cycle as f32 / IPOLATE_DIV);
-                            sleep(Duration::from_millis(10));
-                        }
-                    }
-                    current_duty_cycle = target_duty_cycle;
+                let target = f32::from_bits(target_deg.load(Ordering::Acquire));
+                if let Some(angle) = stepper.step(&calib, clock.now(), target) {
+                    lock()?.write_angle(&calib, angle);
+                    estimated_deg.store(angle.to_bits(), Ordering::Release);
                 }
-
-                sleep(Duration::from_millis(1750));
+                sleep(Duration::from_millis(STEP_MS));
             }
 
             // Cleanup
             // Interpolate back to middle position
-            let target_duty_cycle = (DUTY_CYCLE_POS_FRONT * IPOLATE_DIV) as u32;
-            if current_duty_cycle != target_duty_cycle {
-                if current_duty_cycle < target_duty_cycle {
-                    for duty_cycle in (current_duty_cycle..=target_duty_cycle).step_by(1) {
-                        _ = controller.sg90_pos_cmd.set_duty_cycle(duty_cycle as f32 / IPOLATE_DIV);
-                        sleep(Duration::from_millis(10));
-                    }
-                } else {
-                    for duty_cycle in (target_duty_cycle..=current_duty_cycle).rev().step_by(1) {
-                        _ = controller.sg90_pos_cmd.set_duty_cycle(duty_cycle as f32 / IPOLATE_DIV);
-                        sleep(Duration::from_millis(10));
-                    }
-                }
-            }
-            sleep(Duration::from_millis(900));
-
-            _ = controller.sg90_pos_cmd.set_enable(false);
-            _ = controller.sg90_pos_cmd.set_duty_cycle(0.0);
-            _ = controller.sg90_pos_cmd.unexport();
+            lock()?.park(&calib, &mut stepper);
+            estimated_deg.store(stepper.angle_deg().to_bits(), Ordering::Release);
             Ok(())
-        });
+        })
+        .map_err(|e| CuError::from(format!("cu-cam-pan: failed to spawn ipolate-thread: {e}")))?;
 
-        self.ipolate_thread_hdl = Some(ipolate_thread_hdl.expect("Failed to spawn ipolate-thread"));
+        self.ipolate_thread_hdl = Some(ipolate_thread_hdl);
         Ok(())
     }
 
-    fn process(&mut self, _clock: &RobotClock, input: &Self::Input<'_>) -> Result<(), CuError> {
-        let pos_cmd = Arc::clone(&mut self.recvd_pos_cmd);
-        let payload = input.payload();
-        match payload {
+    fn process(&mut self, clock: &RobotClock, input: &Self::Input<'_>, output: &mut Self::Output<'_>) -> Result<(), CuError> {
+        let estimated_deg = f32::from_bits(self.estimated_deg.load(Ordering::Acquire));
+        let mut target_deg = f32::from_bits(self.target_deg.load(Ordering::Relaxed));
+        let settled = PanSlew { angle_deg: estimated_deg, target_deg }.settled();
+
+        match input.payload() {
+            Some(nonempty_payload) if nonempty_payload.scan => {
+                let new_target_deg = self.calib.clamp_angle(self.scan.update(&self.scan_cfg, clock.now(), settled));
+                if new_target_deg != target_deg {
+                    self.target_deg.store(new_target_deg.to_bits(), Ordering::Release);
+                    target_deg = new_target_deg;
+                }
+            },
             Some(nonempty_payload) => {
-                eprintln!("CAM PAN: received cmd={:?}", nonempty_payload.pos_cmd);
-                pos_cmd.store(nonempty_payload.pos_cmd.to_u8(), Ordering::Release);
+                self.scan.stop();
+                let new_target_deg = self.calib.clamp_angle(nonempty_payload.angle_deg
+                    .unwrap_or(nonempty_payload.pos_cmd.to_angle_deg(&self.presets)));
+                if new_target_deg != target_deg {
+                    eprintln!("CAM PAN: received cmd={:?} angle={:?} -> target {new_target_deg:.1}deg",
+                        nonempty_payload.pos_cmd, nonempty_payload.angle_deg);
+                    self.target_deg.store(new_target_deg.to_bits(), Ordering::Release);
+                    target_deg = new_target_deg;
+                }
             },
-            None => ()
+            None => self.scan.stop(),
+        }
+
+        let mut estimated_deg = estimated_deg;
+        if self.drive_mode == DriveMode::Cycle && let Some(stepper) = self.stepper.as_mut() {
+            if let Some(angle) = stepper.step(&self.calib, clock.now(), target_deg) {
+                self.pin_controller_instances.lock()
+                    .map_err(|_| CuError::from("cu-cam-pan: controller lock poisoned"))?
+                    .write_angle(&self.calib, angle);
+                self.estimated_deg.store(angle.to_bits(), Ordering::Release);
+                estimated_deg = angle;
+            }
         }
+
+        let slew = PanSlew { angle_deg: estimated_deg, target_deg };
+        output.set_payload(PanStatePayload {
+            angle_deg: slew.angle_deg,
+            target_deg,
+            settled: slew.settled(),
+            scanning: self.scan.active(),
+            scan_step: self.scan.dwelling_step(),
+        });
+        output.metadata.set_status(format!("{:.1}deg", slew.angle_deg));
         Ok(())
     }
 
     fn stop(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        if self.drive_mode == DriveMode::Cycle {
+            if let Some(stepper) = self.stepper.as_mut() {
+                self.pin_controller_instances.lock()
+                    .map_err(|_| CuError::from("cu-cam-pan: controller lock poisoned"))?
+                    .park(&self.calib, stepper);
+                self.estimated_deg.store(stepper.angle_deg().to_bits(), Ordering::Release);
+                *stepper = PanStepper::new(stepper.init_settle_ms);
+            }
+            return Ok(());
+        }
+
         self.task_running.store(false, Ordering::Relaxed);
         let hdl = self.ipolate_thread_hdl.take();
         match hdl {
             Some(hdl) => {
-                let ret = hdl.join().expect("CameraPanning PWM duty cycle interpolation thread panicked upon stop command issued");
+                let ret = hdl.join()
+                    .map_err(|_| CuError::from("CameraPanning PWM duty cycle interpolation thread panicked upon stop command issued"))?;
                 match ret {
                     Ok(_) => (),
                     Err(_) => return Err(CuError::from("Failed to stop cu-cam-pan"))
@@ -245,11 +370,168 @@ impl CuSinkTask for CameraPanning {
 
 #[cfg(test)]
 mod tests {
-    // use super::*;
+    use super::*;
+    use std::time::Duration;
+
+    const CYCLE: Duration = Duration::from_millis(18);
+
+    /// Past the init settle and tracking from center, on a mock clock
+    fn tracking_stepper(calib: &ServoCalib) -> (PanStepper, (RobotClock, RobotClockMock)) {
+        let (clock, mock) = RobotClock::mock();
+        let mut stepper = PanStepper::new(DEFAULT_INIT_SETTLE_MS);
+        assert_eq!(stepper.step(calib, clock.now(), 0.0), Some(0.0));
+        mock.increment(Duration::from_millis(DEFAULT_INIT_SETTLE_MS));
+        assert_eq!(stepper.step(calib, clock.now(), 0.0), None);
+        assert_eq!(stepper.state, PanDriveState::Tracking);
+        (stepper, (clock, mock))
+    }
 
-    // #[test]
-    // fn it_works() {
-    //     let result = add(2, 2);
-    //     assert_eq!(result, 4);
-    // }
+    #[test]
+    fn stepper_centers_and_waits_out_the_init_settle() {
+        let calib = ServoCalib::default();
+        let (clock, mock) = RobotClock::mock();
+        let mut stepper = PanStepper::new(DEFAULT_INIT_SETTLE_MS);
+
+        assert_eq!(stepper.step(&calib, clock.now(), 30.0), Some(0.0));
+        assert_eq!(stepper.state, PanDriveState::Initializing);
+
+        // nothing is written while the servo finds center, whatever the target
+        mock.increment(Duration::from_millis(DEFAULT_INIT_SETTLE_MS - 1));
+        assert_eq!(stepper.step(&calib, clock.now(), 30.0), None);
+        assert_eq!(stepper.state, PanDriveState::Initializing);
+        mock.increment(Duration::from_millis(1));
+        assert_eq!(stepper.step(&calib, clock.now(), 30.0), None);
+        assert_eq!(stepper.state, PanDriveState::Tracking);
+        assert_eq!(stepper.angle_deg(), 0.0);
+    }
+
+    #[test]
+    fn stepper_slew_is_rate_limited() {
+        let calib = ServoCalib::default();
+        let (mut stepper, (clock, mock)) = tracking_stepper(&calib);
+
+        mock.increment(Duration::from_millis(20));
+        let angle = stepper.step(&calib, clock.now(), 45.0).unwrap();
+        assert!((angle - calib.max_slew_deg_s * 0.02).abs() < 1e-3, "{angle}deg");
+
+        // a stalled cycle doesn't turn into a jump
+        mock.increment(Duration::from_secs(1));
+        let after_stall = stepper.step(&calib, clock.now(), 45.0).unwrap();
+        assert!((after_stall - angle - calib.max_slew_deg_s * driver::MAX_STEP_S).abs() < 1e-3, "{after_stall}deg");
+
+        // a new target turns the slew around
+        mock.increment(Duration::from_millis(20));
+        let turned = stepper.step(&calib, clock.now(), -45.0).unwrap();
+        assert!((after_stall - turned - calib.max_slew_deg_s * 0.02).abs() < 1e-3, "{turned}deg");
+    }
+
+    #[test]
+    fn stepper_stops_writing_once_settled() {
+        let calib = ServoCalib::default();
+        let (mut stepper, (clock, mock)) = tracking_stepper(&calib);
+
+        let mut writes = 0;
+        loop {
+            mock.increment(CYCLE);
+            if stepper.step(&calib, clock.now(), 30.0).is_none() {
+                break;
+            }
+            writes += 1;
+            assert!(writes < 100, "never settled");
+        }
+        assert!((stepper.angle_deg() - 30.0).abs() < 0.1);
+        // 30deg at 180deg/s in 18ms cycles
+        assert_eq!(writes, 10);
+
+        // and stays quiet on the same target
+        mock.increment(CYCLE);
+        assert_eq!(stepper.step(&calib, clock.now(), 30.0), None);
+        // targets past the travel are clamped
+        mock.increment(CYCLE);
+        stepper.step(&calib, clock.now(), 90.0);
+        assert_eq!(stepper.slew.target_deg, calib.max_angle_deg());
+    }
+
+    #[test]
+    fn park_slews_back_to_center() {
+        let calib = ServoCalib::default();
+        let (mut stepper, (clock, mock)) = tracking_stepper(&calib);
+        loop {
+            mock.increment(CYCLE);
+            if stepper.step(&calib, clock.now(), 45.0).is_none() {
+                break;
+            }
+        }
+
+        let step_s = STEP_MS as f32 / 1000.0;
+        let max_step = calib.max_slew_deg_s * step_s;
+        let mut last = stepper.angle_deg();
+        let mut steps = 0;
+        while let Some(angle) = stepper.park_step(&calib, step_s) {
+            assert!(angle < last && last - angle <= max_step + 1e-3, "{last} -> {angle}deg");
+            last = angle;
+            steps += 1;
+            assert!(steps < 100, "never parked");
+        }
+        assert!(stepper.angle_deg().abs() < 0.1);
+        assert_eq!(stepper.park_step(&calib, step_s), None);
+    }
+
+    #[test]
+    fn scan_ping_pongs_over_the_steps() {
+        // -30, -10, 10, 30
+        let cfg = ScanCfg { min_deg: -30.0, max_deg: 30.0, step_deg: 20.0, dwell_ms: 100, end_dwell_ms: 100 };
+        assert_eq!(cfg.steps(), 4);
+
+        let (clock, mock) = RobotClock::mock();
+        let mut scan = PanScan::default();
+        let mut stops = vec![scan.update(&cfg, clock.now(), false)];
+        assert!(scan.active());
+
+        // a servo that's always there
+        while stops.len() < 8 {
+            mock.increment(CYCLE);
+            let target = scan.update(&cfg, clock.now(), true);
+            if target != *stops.last().unwrap() {
+                stops.push(target);
+            }
+        }
+        assert_eq!(stops, [-30.0, -10.0, 10.0, 30.0, 10.0, -10.0, -30.0, -10.0]);
+
+        scan.stop();
+        assert!(!scan.active());
+        assert_eq!(scan.update(&cfg, clock.now(), true), -30.0);
+    }
+
+    #[test]
+    fn scan_dwells_once_settled() {
+        // -45..45 in 15deg steps, longer at the ends
+        let cfg = ScanCfg { end_dwell_ms: 1000, ..ScanCfg::default() };
+        let (clock, mock) = RobotClock::mock();
+        let mut scan = PanScan::default();
+
+        assert_eq!(scan.update(&cfg, clock.now(), false), -45.0);
+        // the dwell doesn't start while the servo is moving, however long that takes
+        mock.increment(Duration::from_secs(5));
+        assert_eq!(scan.update(&cfg, clock.now(), false), -45.0);
+        assert_eq!(scan.dwelling_step(), None);
+
+        // end step, end_dwell_ms from when it settled
+        assert_eq!(scan.update(&cfg, clock.now(), true), -45.0);
+        assert_eq!(scan.dwelling_step(), Some(0));
+        mock.increment(Duration::from_millis(999));
+        assert_eq!(scan.update(&cfg, clock.now(), true), -45.0);
+        mock.increment(Duration::from_millis(1));
+        assert_eq!(scan.update(&cfg, clock.now(), true), -30.0);
+        assert_eq!(scan.dwelling_step(), None);
+
+        // intermediate step, dwell_ms
+        mock.increment(Duration::from_millis(100));
+        assert_eq!(scan.update(&cfg, clock.now(), true), -30.0);
+        assert_eq!(scan.dwelling_step(), Some(1));
+        mock.increment(Duration::from_millis(DEFAULT_SCAN_DWELL_MS - 1));
+        assert_eq!(scan.update(&cfg, clock.now(), true), -30.0);
+        mock.increment(Duration::from_millis(1));
+        assert_eq!(scan.update(&cfg, clock.now(), true), -15.0);
+    }
 }
@@ -0,0 +1,105 @@
+use cu29::prelude::*;
+
+pub const DEFAULT_SCAN_MIN_DEG: f32 = -45.0;
+pub const DEFAULT_SCAN_MAX_DEG: f32 = 45.0;
+pub const DEFAULT_SCAN_STEP_DEG: f32 = 15.0;
+pub const DEFAULT_SCAN_DWELL_MS: u64 = 500;
+
+/// Sweep schedule. The servo stops on every step from min to max and back, and holds for dwell_ms
+/// once it has settled there (end_dwell_ms at both ends), so the vision service gets still frames.
+#[derive(Debug, Clone, Copy)]
+pub struct ScanCfg {
+    pub min_deg: f32,
+    pub max_deg: f32,
+    pub step_deg: f32,
+    pub dwell_ms: u64,
+    pub end_dwell_ms: u64,
+}
+
+impl Default for ScanCfg {
+    fn default() -> Self {
+        Self {
+            min_deg: DEFAULT_SCAN_MIN_DEG,
+            max_deg: DEFAULT_SCAN_MAX_DEG,
+            step_deg: DEFAULT_SCAN_STEP_DEG,
+            dwell_ms: DEFAULT_SCAN_DWELL_MS,
+            end_dwell_ms: DEFAULT_SCAN_DWELL_MS,
+        }
+    }
+}
+
+impl ScanCfg {
+    /// Number of stops from min to max, both ends included
+    pub fn steps(&self) -> u16 {
+        (((self.max_deg - self.min_deg) / self.step_deg).ceil() as u16) + 1
+    }
+
+    pub fn step_angle_deg(&self, step: u16) -> f32 {
+        (self.min_deg + step as f32 * self.step_deg).min(self.max_deg)
+    }
+}
+
+/// Ping-pong over the scan steps. Stepped from CameraPanning::process, the robot clock drives the dwell.
+#[derive(Debug, Default)]
+pub struct PanScan {
+    active: bool,
+    step: u16,
+    ascending: bool,
+    dwell_started: Option<CuTime>,
+}
+
+impl PanScan {
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    pub fn stop(&mut self) {
+        if self.active {
+            eprintln!("CAM PAN: scan stopped at step {}", self.step);
+        }
+        *self = Self::default();
+    }
+
+    /// Step index while dwelling (servo settled on it), None while moving between steps
+    pub fn dwelling_step(&self) -> Option<u16> {
+        self.dwell_started.map(|_| self.step)
+    }
+
+    /// Returns the target angle for this cycle. `settled` is whether the servo has reached the
+    /// previously returned target.
+    pub fn update(&mut self, cfg: &ScanCfg, now: CuTime, settled: bool) -> f32 {
+        if !self.active {
+            eprintln!("CAM PAN: scan started {:.1}..{:.1}deg in {} steps", cfg.min_deg, cfg.max_deg, cfg.steps());
+            self.active = true;
+            self.step = 0;
+            self.ascending = true;
+            self.dwell_started = None;
+            return cfg.step_angle_deg(self.step);
+        }
+
+        if !settled {
+            return cfg.step_angle_deg(self.step);
+        }
+
+        let last = cfg.steps().saturating_sub(1);
+        let dwell_ms = if self.step == 0 || self.step == last { cfg.end_dwell_ms } else { cfg.dwell_ms };
+
+        let since = *self.dwell_started.get_or_insert(now);
+        if now.as_nanos().saturating_sub(since.as_nanos()) < CuDuration::from_millis(dwell_ms).as_nanos() {
+            return cfg.step_angle_deg(self.step);
+        }
+
+        // dwell done, next step
+        self.dwell_started = None;
+        if last == 0 {
+            return cfg.step_angle_deg(0);
+        }
+        if self.ascending && self.step >= last {
+            self.ascending = false;
+        } else if !self.ascending && self.step == 0 {
+            self.ascending = true;
+        }
+        self.step = if self.ascending { self.step + 1 } else { self.step - 1 };
+        cfg.step_angle_deg(self.step)
+    }
+}
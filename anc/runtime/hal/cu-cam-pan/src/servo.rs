@@ -0,0 +1,83 @@
+/// Servo calibration. Positive angles pan left, 0 is the center (front) position.
+/// The SG90 defaults map +-45 degrees onto 1.0 - 2.0 ms, same travel as the old fixed duty cycles.
+pub const DEFAULT_MIN_PULSE_US: f32 = 1000.0;
+pub const DEFAULT_MAX_PULSE_US: f32 = 2000.0;
+pub const DEFAULT_CENTER_TRIM_US: f32 = 0.0;
+/// Travel between min_pulse_us and max_pulse_us
+pub const DEFAULT_RANGE_DEG: f32 = 90.0;
+/// The old interpolation did 45 degrees in ~250 ms
+pub const DEFAULT_MAX_SLEW_DEG_S: f32 = 180.0;
+pub const DEFAULT_PRESET_LEFT_DEG: f32 = 45.0;
+pub const DEFAULT_PRESET_RIGHT_DEG: f32 = -45.0;
+
+/// Below this the servo is taken as settled on the target
+const SETTLED_EPS_DEG: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ServoCalib {
+    pub min_pulse_us: f32,
+    pub max_pulse_us: f32,
+    /// Added to the center pulse, for servo horns that aren't mounted dead straight
+    pub center_trim_us: f32,
+    pub range_deg: f32,
+    pub max_slew_deg_s: f32,
+}
+
+impl Default for ServoCalib {
+    fn default() -> Self {
+        Self {
+            min_pulse_us: DEFAULT_MIN_PULSE_US,
+            max_pulse_us: DEFAULT_MAX_PULSE_US,
+            center_trim_us: DEFAULT_CENTER_TRIM_US,
+            range_deg: DEFAULT_RANGE_DEG,
+            max_slew_deg_s: DEFAULT_MAX_SLEW_DEG_S,
+        }
+    }
+}
+
+impl ServoCalib {
+    pub fn max_angle_deg(&self) -> f32 {
+        self.range_deg / 2.0
+    }
+
+    pub fn clamp_angle(&self, angle_deg: f32) -> f32 {
+        angle_deg.clamp(-self.max_angle_deg(), self.max_angle_deg())
+    }
+
+    /// Pulse width for an angle, never outside [min_pulse_us, max_pulse_us] even with trim
+    pub fn pulse_us(&self, angle_deg: f32) -> f32 {
+        let center = (self.min_pulse_us + self.max_pulse_us) / 2.0 + self.center_trim_us;
+        let us_per_deg = (self.max_pulse_us - self.min_pulse_us) / self.range_deg;
+        (center + self.clamp_angle(angle_deg) * us_per_deg).clamp(self.min_pulse_us, self.max_pulse_us)
+    }
+
+    pub fn duty_cycle(&self, angle_deg: f32, period_ns: u32) -> f32 {
+        self.pulse_us(angle_deg) * 1000.0 / period_ns as f32
+    }
+}
+
+/// Moves the commanded angle toward the target at no more than max_slew_deg_s. There's no position
+/// feedback on the servo, so the commanded angle doubles as the estimated angle.
+#[derive(Debug, Default)]
+pub struct PanSlew {
+    pub angle_deg: f32,
+    pub target_deg: f32,
+}
+
+impl PanSlew {
+    pub fn set_target(&mut self, calib: &ServoCalib, target_deg: f32) {
+        self.target_deg = calib.clamp_angle(target_deg);
+    }
+
+    /// Returns the new commanded angle
+    pub fn step(&mut self, calib: &ServoCalib, dt_s: f32) -> f32 {
+        let max_step = calib.max_slew_deg_s * dt_s;
+        let diff = self.target_deg - self.angle_deg;
+        self.angle_deg += diff.clamp(-max_step, max_step);
+        self.angle_deg
+    }
+
+    pub fn settled(&self) -> bool {
+        (self.target_deg - self.angle_deg).abs() < SETTLED_EPS_DEG
+    }
+}
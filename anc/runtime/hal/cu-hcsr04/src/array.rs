@@ -0,0 +1,365 @@
+use std::os::fd::AsRawFd;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{Builder, JoinHandle, sleep};
+use std::time::Duration;
+use gpio_cdev::*;
+use cu29::prelude::*;
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use cu_health::{Availability, Criticality, required_key};
+use crate::{DistanceFilter, DistanceFilterCfg, HcSr04Payload};
+
+pub const MAX_HCSR04_SENSORS: usize = 6;
+pub const DEFAULT_GPIO_CHIP: &str = "/dev/gpiochip4";
+/// Quiet time after one sensor's echo window before the next one fires, so stray echoes from the
+/// previous ping die out instead of being picked up as a short reading
+pub const DEFAULT_INTER_SENSOR_GAP_MS: u64 = 10;
+/// ~4 m round trip, the HC-SR04's rated range
+const ECHO_TIMEOUT_MS: i32 = 25;
+const TRIG_PULSE_US: u64 = 10;
+/// round trip, speed of sound ~343 m/s
+const US_PER_CM: f64 = 58.3;
+const MIN_DIST_CM: f64 = 2.0;
+const MAX_DIST_CM: f64 = 400.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+#[derive(Reflect)]
+pub enum SensorPosition {
+    #[default]
+    Front,
+    Left,
+    Right,
+    Rear,
+}
+
+impl SensorPosition {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "front" => Ok(SensorPosition::Front),
+            "left" => Ok(SensorPosition::Left),
+            "right" => Ok(SensorPosition::Right),
+            "rear" => Ok(SensorPosition::Rear),
+            _ => Err(format!("Invalid sensor position: \"{s}\". Valid values: \"front\", \"left\", \"right\", \"rear\"")),
+        }
+    }
+}
+
+/// One filtered reading per configured sensor, in config order. Only the first `count` are used.
+#[derive(Debug, Clone, Copy, Default, Encode, Decode, PartialEq, Serialize, Deserialize)]
+#[derive(Reflect)]
+#[reflect(no_field_bounds, from_reflect = false)]
+pub struct HcSr04ArrayPayload {
+    pub count: u8,
+    pub positions: [SensorPosition; MAX_HCSR04_SENSORS],
+    pub readings: [HcSr04Payload; MAX_HCSR04_SENSORS],
+}
+
+struct SensorLines {
+    trig: LineHandle,
+    echo: LineEventHandle,
+}
+
+/// Latest reading handed from the sequencer thread to process(). `seq` counts completed pings, a
+/// full round of the array takes longer than a cycle so process() mostly sees the same one again.
+#[derive(Debug, Clone, Copy)]
+struct SensorSlot {
+    available: bool,
+    dist: Option<f64>,
+    seq: u64,
+}
+
+/// N HC-SR04s fired one at a time from a single thread, so no sensor hears another's ping.
+/// The echo width comes from the kernel edge timestamps, not from wakeup latency.
+/// Config (N = 0..num_sensors-1):
+///   "num_sensors", "sensorN_trig_pin", "sensorN_echo_pin", "sensorN_position" ("front", "left", "right", "rear")
+///   "inter_sensor_gap_ms", "gpio_chip", plus the same filter keys as CuHcSr04 (applied per sensor)
+///   "criticality": with "optional" a sensor whose lines can't be requested, or that fails mid-run,
+///   is reported unavailable and the others keep going. "required" (default) fails the task.
+#[derive(Reflect)]
+#[reflect(no_field_bounds, from_reflect = false)]
+pub struct CuHcSr04Array {
+    #[reflect(ignore)]
+    positions: Vec<SensorPosition>,
+    /// None per sensor that failed to come up (optional only)
+    #[reflect(ignore)]
+    lines: Option<Vec<Option<SensorLines>>>,
+    inter_sensor_gap_ms: u64,
+    #[reflect(ignore)]
+    criticality: Criticality,
+    #[reflect(ignore)]
+    latest: Arc<Mutex<Vec<SensorSlot>>>,
+    #[reflect(ignore)]
+    running: Arc<AtomicBool>,
+    #[reflect(ignore)]
+    thread_hdl: Option<JoinHandle<CuResult<()>>>,
+    #[reflect(ignore)]
+    filter_cfg: DistanceFilterCfg,
+    #[reflect(ignore)]
+    filters: Vec<DistanceFilter>,
+    /// Per sensor, the seq of the ping already fed to its filter and the reading published for it
+    #[reflect(ignore)]
+    seen: Vec<(u64, HcSr04Payload)>,
+}
+
+impl Freezable for CuHcSr04Array {}
+
+/// Some(width in ns) of the echo pulse following a trigger, None on timeout
+fn measure_echo(echo: &mut LineEventHandle) -> CuResult<Option<u64>> {
+    let mut pollfd = libc::pollfd { fd: echo.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+    let mut rising: Option<u64> = None;
+
+    loop {
+        let ready = unsafe { libc::poll(&mut pollfd, 1, ECHO_TIMEOUT_MS) };
+        if ready <= 0 {
+            return Ok(None);
+        }
+
+        let event = echo.get_event()
+            .map_err(|e| CuError::from(format!("hcsr04 array: failed to read echo event: {e}")))?;
+        match (event.event_type(), rising) {
+            (EventType::RisingEdge, _) => rising = Some(event.timestamp()),
+            (EventType::FallingEdge, Some(start)) => return Ok(Some(event.timestamp().saturating_sub(start))),
+            // falling edge left over from a previous ping
+            (EventType::FallingEdge, None) => (),
+        }
+    }
+}
+
+/// One trigger and echo, Some(distance in cm) if it's in range
+fn ping(sensor: &mut SensorLines) -> CuResult<Option<f64>> {
+    drain_events(&mut sensor.echo)?;
+
+    sensor.trig.set_value(1)
+        .map_err(|e| CuError::from(format!("hcsr04 array: trig high failed: {e}")))?;
+    sleep(Duration::from_micros(TRIG_PULSE_US));
+    sensor.trig.set_value(0)
+        .map_err(|e| CuError::from(format!("hcsr04 array: trig low failed: {e}")))?;
+
+    Ok(measure_echo(&mut sensor.echo)?
+        .map(|width_ns| width_ns as f64 / 1000.0 / US_PER_CM)
+        .filter(|d| (MIN_DIST_CM..=MAX_DIST_CM).contains(d)))
+}
+
+/// Throws away events queued since the last ping
+fn drain_events(echo: &mut LineEventHandle) -> CuResult<()> {
+    let mut pollfd = libc::pollfd { fd: echo.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+    while unsafe { libc::poll(&mut pollfd, 1, 0) } > 0 {
+        echo.get_event()
+            .map_err(|e| CuError::from(format!("hcsr04 array: failed to drain echo events: {e}")))?;
+    }
+    Ok(())
+}
+
+impl CuSrcTask for CuHcSr04Array {
+    type Output<'m> = output_msg!(HcSr04ArrayPayload);
+    type Resources<'r> = ();
+
+    fn new(config: Option<&ComponentConfig>, _resources: Self::Resources<'_>) -> CuResult<Self>
+    where Self: Sized
+    {
+        let config = config.ok_or("No ComponentConfig specified for CuHcSr04Array in RON")?;
+        let ComponentConfig(kv) = config;
+
+        let num_sensors: u32 = required_key(config, "CuHcSr04Array", "num_sensors")?;
+        let num_sensors = num_sensors as usize;
+        if num_sensors == 0 || num_sensors > MAX_HCSR04_SENSORS {
+            return Err(CuError::from(format!("CuHcSr04Array: num_sensors must be 1..={MAX_HCSR04_SENSORS}")));
+        }
+        let criticality = Criticality::from_config(config)?;
+
+        let gpio_chip: String = kv.get("gpio_chip")
+            .map(|v| v.clone().into())
+            .unwrap_or(String::from(DEFAULT_GPIO_CHIP));
+
+        let inter_sensor_gap_ms: u64 = kv.get("inter_sensor_gap_ms")
+            .map(|v| { let f: f64 = v.clone().into(); f as u64 })
+            .unwrap_or(DEFAULT_INTER_SENSOR_GAP_MS);
+
+        let mut chip = criticality.degrade("CuHcSr04Array", Chip::new(&gpio_chip)
+            .map_err(|e| CuError::from(format!("CuHcSr04Array: failed to open {gpio_chip}: {e}"))))?;
+
+        let mut positions = Vec::with_capacity(num_sensors);
+        let mut lines = Vec::with_capacity(num_sensors);
+        for i in 0..num_sensors {
+            let trig_pin: u32 = required_key(config, "CuHcSr04Array", &format!("sensor{i}_trig_pin"))?;
+            let echo_pin: u32 = required_key(config, "CuHcSr04Array", &format!("sensor{i}_echo_pin"))?;
+
+            let position: String = required_key(config, "CuHcSr04Array", &format!("sensor{i}_position"))?;
+            positions.push(SensorPosition::parse(&position).map_err(|e| CuError::from(e.as_str()))?);
+
+            let Some(chip) = chip.as_mut() else {
+                lines.push(None);
+                continue;
+            };
+            let requested = chip.get_line(trig_pin)
+                .and_then(|l| l.request(LineRequestFlags::OUTPUT, 0, &format!("hcsr04-{i}-trig")))
+                .map_err(|e| CuError::from(format!("CuHcSr04Array: failed to request trig line {trig_pin}: {e}")))
+                .and_then(|trig| {
+                    let echo = chip.get_line(echo_pin)
+                        .and_then(|l| l.events(LineRequestFlags::INPUT, EventRequestFlags::BOTH_EDGES, &format!("hcsr04-{i}-echo")))
+                        .map_err(|e| CuError::from(format!("CuHcSr04Array: failed to request echo events on line {echo_pin}: {e}")))?;
+                    Ok(SensorLines { trig, echo })
+                });
+            lines.push(criticality.degrade(&format!("CuHcSr04Array sensor{i}"), requested)?);
+        }
+
+        let mut filter_cfg = DistanceFilterCfg::default();
+        if let Some(v) = kv.get("median_window") {
+            let f: f64 = v.clone().into();
+            filter_cfg.median_window = (f as usize).max(1);
+        }
+        if let Some(v) = kv.get("max_rate_cm_s") {
+            let f: f64 = v.clone().into();
+            filter_cfg.max_rate_cm_s = Some(f);
+        }
+        if let Some(v) = kv.get("max_rejects") {
+            let f: f64 = v.clone().into();
+            filter_cfg.max_rejects = f as u32;
+        }
+        if let Some(v) = kv.get("echo_timeout_ms") {
+            let f: f64 = v.clone().into();
+            filter_cfg.echo_timeout_ms = f as u64;
+        }
+
+        Ok(Self {
+            filters: (0..num_sensors).map(|_| DistanceFilter::default()).collect(),
+            latest: Arc::new(Mutex::new(lines.iter()
+                .map(|l| SensorSlot { available: l.is_some(), dist: None, seq: 0 })
+                .collect())),
+            seen: vec![(0, HcSr04Payload::default()); num_sensors],
+            positions,
+            lines: Some(lines),
+            inter_sensor_gap_ms,
+            criticality,
+            running: Arc::new(AtomicBool::new(false)),
+            thread_hdl: None,
+            filter_cfg,
+        })
+    }
+
+    fn start(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        let Some(mut lines) = self.lines.take() else {
+            return Err(CuError::from("CuHcSr04Array: already started"));
+        };
+
+        self.running.store(true, Ordering::Relaxed);
+        let running = Arc::clone(&self.running);
+        let latest = Arc::clone(&self.latest);
+        let gap = Duration::from_millis(self.inter_sensor_gap_ms);
+        let criticality = self.criticality;
+
+        let thread_hdl = Builder::new()
+            .name(String::from("cu-hcsr04-array-sequencer"))
+            .spawn(move || -> CuResult<()> {
+                let mut seqs = vec![0u64; lines.len()];
+                while running.load(Ordering::Relaxed) {
+                    for (i, slot) in lines.iter_mut().enumerate() {
+                        let Some(sensor) = slot.as_mut() else {
+                            continue;
+                        };
+
+                        seqs[i] += 1;
+                        let seq = seqs[i];
+                        let reading = match (ping(sensor), criticality) {
+                            (Ok(dist), _) => SensorSlot { available: true, dist, seq },
+                            (Err(e), Criticality::Required) => return Err(e),
+                            // stop pinging it, the rest of the array keeps going
+                            (Err(e), Criticality::Optional) => {
+                                eprintln!("CuHcSr04Array sensor{i}: unavailable, running degraded: {e}");
+                                *slot = None;
+                                SensorSlot { available: false, dist: None, seq }
+                            },
+                        };
+
+                        if let Ok(mut latest) = latest.lock() {
+                            latest[i] = reading;
+                        }
+
+                        sleep(gap);
+                    }
+
+                    if lines.iter().all(Option::is_none) {
+                        eprintln!("CuHcSr04Array: no sensor left, sequencer stopped");
+                        break;
+                    }
+                }
+                Ok(())
+            })
+            .map_err(|e| CuError::from(format!("CuHcSr04Array: failed to spawn sequencer thread: {e}")))?;
+
+        self.thread_hdl = Some(thread_hdl);
+        Ok(())
+    }
+
+    fn stop(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        self.running.store(false, Ordering::Relaxed);
+        match self.thread_hdl.take() {
+            Some(hdl) => {
+                let ret = hdl.join()
+                    .map_err(|_| CuError::from("hcsr04 array sequencer thread panicked upon stop command issued"))?;
+                match ret {
+                    Ok(_) => (),
+                    Err(_) => return Err(CuError::from("Failed to stop CuHcSr04Array"))
+                }
+            },
+            None => ()
+        }
+        Ok(())
+    }
+
+    fn process(&mut self, clock: &RobotClock, output: &mut Self::Output<'_>) -> CuResult<()> {
+        // The sequencer ends early on a required sensor failing, pass that up instead of publishing
+        // stale readings. It also ends once every optional sensor is gone, those slots are unavailable.
+        if self.thread_hdl.as_ref().is_some_and(|h| h.is_finished()) {
+            if let Some(hdl) = self.thread_hdl.take() {
+                hdl.join()
+                    .map_err(|_| CuError::from("hcsr04 array sequencer thread panicked"))??;
+            }
+        }
+
+        let latest: Vec<SensorSlot> = match self.latest.lock() {
+            Ok(l) => l.clone(),
+            Err(_) => return Err(CuError::from("CuHcSr04Array: sequencer thread poisoned the readings lock")),
+        };
+
+        let now = clock.now();
+        let mut payload = HcSr04ArrayPayload {
+            count: self.positions.len() as u8,
+            ..Default::default()
+        };
+
+        for (i, slot) in latest.into_iter().enumerate() {
+            payload.positions[i] = self.positions[i];
+            if !slot.available {
+                payload.readings[i] = HcSr04Payload::UNAVAILABLE;
+                continue;
+            }
+
+            let filter = &mut self.filters[i];
+            let (seen_seq, previous) = &mut self.seen[i];
+            let fresh = slot.seq != *seen_seq;
+            // the same echo fed twice would count twice in the median window and keep the last
+            // valid one looking new, only a new ping goes through the filter
+            let (distance, raw_distance) = if fresh {
+                *seen_seq = slot.seq;
+                (filter.update(&self.filter_cfg, now, slot.dist), slot.dist)
+            } else {
+                (previous.distance, previous.raw_distance)
+            };
+            let timed_out = filter.timed_out(&self.filter_cfg, now);
+            *previous = HcSr04Payload {
+                distance: if timed_out { None } else { distance },
+                raw_distance,
+                fresh,
+                last_valid_age_ms: filter.last_valid_age_ms(now),
+                timed_out,
+                availability: Availability::Available,
+            };
+            payload.readings[i] = *previous;
+        }
+
+        output.set_payload(payload);
+        Ok(())
+    }
+}
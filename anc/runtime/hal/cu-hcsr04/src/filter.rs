@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+use cu29::prelude::*;
+
+/// 1 disables the median stage
+pub const DEFAULT_MEDIAN_WINDOW: usize = 1;
+/// No valid echo for this long and the filtered distance goes to None
+pub const DEFAULT_ECHO_TIMEOUT_MS: u64 = 200;
+/// Consecutive implausible samples after which the reading is taken as real (something really did
+/// step in front of the sensor)
+pub const DEFAULT_MAX_REJECTS: u32 = 3;
+
+#[derive(Debug, Clone, Copy)]
+pub struct DistanceFilterCfg {
+    pub median_window: usize,
+    /// cm/s, None disables the check
+    pub max_rate_cm_s: Option<f64>,
+    pub max_rejects: u32,
+    pub echo_timeout_ms: u64,
+}
+
+impl Default for DistanceFilterCfg {
+    fn default() -> Self {
+        Self {
+            median_window: DEFAULT_MEDIAN_WINDOW,
+            max_rate_cm_s: None,
+            max_rejects: DEFAULT_MAX_REJECTS,
+            echo_timeout_ms: DEFAULT_ECHO_TIMEOUT_MS,
+        }
+    }
+}
+
+/// Stages: rate-of-change check -> median of the last N accepted echoes.
+/// A missing echo (None from the driver) doesn't enter the window, it only ages the last valid one.
+#[derive(Debug, Default)]
+pub struct DistanceFilter {
+    window: VecDeque<f64>,
+    last_accepted: Option<(f64, CuTime)>,
+    rejects: u32,
+}
+
+impl DistanceFilter {
+    /// Returns the filtered distance, None once the last valid echo is older than `echo_timeout_ms`.
+    /// `now` is the robot clock.
+    pub fn update(&mut self, cfg: &DistanceFilterCfg, now: CuTime, raw: Option<f64>) -> Option<f64> {
+        if let Some(raw) = raw {
+            let accepted = match (cfg.max_rate_cm_s, self.last_accepted) {
+                (Some(max_rate), Some((last, since))) => {
+                    let dt_s = now.as_nanos().saturating_sub(since.as_nanos()) as f64 / 1_000_000_000.0;
+                    (raw - last).abs() <= max_rate * dt_s || self.rejects >= cfg.max_rejects
+                },
+                _ => true,
+            };
+
+            if accepted {
+                self.rejects = 0;
+                self.last_accepted = Some((raw, now));
+                self.window.push_back(raw);
+                while self.window.len() > cfg.median_window.max(1) {
+                    self.window.pop_front();
+                }
+            } else {
+                self.rejects += 1;
+            }
+        }
+
+        if self.timed_out(cfg, now) {
+            self.window.clear();
+            return None;
+        }
+
+        let mut sorted: Vec<f64> = self.window.iter().copied().collect();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        sorted.get(sorted.len() / 2).copied()
+    }
+
+    /// None if there never was a valid echo
+    pub fn last_valid_age_ms(&self, now: CuTime) -> Option<u64> {
+        self.last_accepted.map(|(_, since)| now.as_nanos().saturating_sub(since.as_nanos()) / 1_000_000)
+    }
+
+    pub fn timed_out(&self, cfg: &DistanceFilterCfg, now: CuTime) -> bool {
+        self.last_valid_age_ms(now).is_none_or(|age| age > cfg.echo_timeout_ms)
+    }
+}
+
+/// N-of-M confirmation: true once at least `n` of the last `m` samples were true.
+/// Used for the e-stop so one spurious short echo doesn't stop the robot.
+#[derive(Debug)]
+pub struct NOfM {
+    n: usize,
+    m: usize,
+    history: VecDeque<bool>,
+}
+
+impl NOfM {
+    pub fn new(n: usize, m: usize) -> Self {
+        let m = m.max(1);
+        Self { n: n.clamp(1, m), m, history: VecDeque::with_capacity(m) }
+    }
+
+    pub fn update(&mut self, sample: bool) -> bool {
+        self.history.push_back(sample);
+        while self.history.len() > self.m {
+            self.history.pop_front();
+        }
+        self.history.iter().filter(|s| **s).count() >= self.n
+    }
+
+    pub fn reset(&mut self) {
+        self.history.clear();
+    }
+}
@@ -1,26 +1,64 @@
 extern crate cu_bincode as bincode;
 
+mod filter;
+pub use filter::*;
+mod array;
+pub use array::*;
+
 use std::time::Duration;
 use cu29::prelude::*;
 use bincode::{Decode, Encode};
 use hcsr04_gpio_cdev::*;
 
 use serde::{Deserialize, Serialize};
+use cu_health::{Availability, Criticality, required_key};
 
 #[derive(Reflect)]
 #[reflect(no_field_bounds, from_reflect = false)]
 pub struct CuHcSr04 {
+    /// None when an optional sensor failed to come up
     #[reflect(ignore)]
-    threaded_driver_instance: HcSr04Threaded,
+    threaded_driver_instance: Option<HcSr04Threaded>,
     #[reflect(ignore)]
     last_value: Option<HcSr04Payload>,
+    #[reflect(ignore)]
+    filter_cfg: DistanceFilterCfg,
+    #[reflect(ignore)]
+    filter: DistanceFilter,
 }
 
+/// `distance` is filtered, `raw_distance` is the latest driver reading (None if no echo). `fresh` is
+/// set on the cycle that reading came in, a sequenced sensor that wasn't pinged again since
+/// republishes it with `fresh` cleared, so per-echo logic (e.g. an N-of-M e-stop) counts it once.
+/// `last_valid_age_ms` is None until the first valid echo. `timed_out` is set once the last valid
+/// echo is older than `echo_timeout_ms`, `distance` is None then. An unavailable sensor reports
+/// no distance and timed out.
+
 #[derive(Debug, Clone, Copy, Encode, Decode, Default, PartialEq, Serialize, Deserialize)]
 #[derive(Reflect)]
 #[reflect(no_field_bounds, from_reflect = false)]
 pub struct HcSr04Payload {
     pub distance: Option<f64>,
+    pub raw_distance: Option<f64>,
+    pub fresh: bool,
+    pub last_valid_age_ms: Option<u64>,
+    pub timed_out: bool,
+    pub availability: Availability,
+}
+
+impl HcSr04Payload {
+    pub const UNAVAILABLE: Self = Self {
+        distance: None,
+        raw_distance: None,
+        fresh: false,
+        last_valid_age_ms: None,
+        timed_out: true,
+        availability: Availability::Unavailable,
+    };
+
+    pub fn available(&self) -> bool {
+        self.availability == Availability::Available
+    }
 }
 
 impl Freezable for CuHcSr04 {}
@@ -32,50 +70,67 @@ impl CuSrcTask for CuHcSr04 {
     fn new(config: Option<&ComponentConfig>,  _resources: Self::Resources<'_>) -> CuResult<Self>
     where Self:Sized
     {
-        let ComponentConfig(kv) =
-            config.ok_or("No ComponentConfig specified for GPIO in RON")?;
-
-        let trig_pin_offset: u32 = kv
-            .get("trig_pin")
-            .expect("trig_pin for HcSr04 not set in RON config")
-            .clone()
-            .into();
-
-        let echo_pin_offset: u32 = kv
-            .get("echo_pin")
-            .expect("echo_pin for HcSr04 not set in RON config")
-            .clone()
-            .into();
-
-        let dist_threshold_cm: u32 = kv
-            .get("dist_threshold_cm")
-            .expect("threshold for HcSr04 not set in RON config")
-            .clone()
-            .into();
-
-        let threaded_driver_instance = HcSr04Threaded::new(
+        let config = config.ok_or("No ComponentConfig specified for HcSr04 in RON")?;
+        let ComponentConfig(kv) = config;
+
+        let trig_pin_offset: u32 = required_key(config, "HcSr04", "trig_pin")?;
+        let echo_pin_offset: u32 = required_key(config, "HcSr04", "echo_pin")?;
+        let dist_threshold_cm: u32 = required_key(config, "HcSr04", "dist_threshold_cm")?;
+        let criticality = Criticality::from_config(config)?;
+
+        let mut filter_cfg = DistanceFilterCfg::default();
+        if let Some(v) = kv.get("median_window") {
+            let f: f64 = v.clone().into();
+            filter_cfg.median_window = (f as usize).max(1);
+        }
+        if let Some(v) = kv.get("max_rate_cm_s") {
+            let f: f64 = v.clone().into();
+            filter_cfg.max_rate_cm_s = Some(f);
+        }
+        if let Some(v) = kv.get("max_rejects") {
+            let f: f64 = v.clone().into();
+            filter_cfg.max_rejects = f as u32;
+        }
+        if let Some(v) = kv.get("echo_timeout_ms") {
+            let f: f64 = v.clone().into();
+            filter_cfg.echo_timeout_ms = f as u64;
+        }
+
+        let threaded_driver_instance = criticality.degrade("HcSr04", HcSr04Threaded::new(
             trig_pin_offset,
             echo_pin_offset,
             DistanceUnit::Cm(dist_threshold_cm as f64),
             None,
             Duration::from_millis(10))
-        .expect("GPIO (threaded) driver error");
+        .map_err(|e| CuError::from(format!("HcSr04: GPIO (threaded) driver error: {e:?}"))))?;
 
         Ok(Self {
             threaded_driver_instance,
             last_value: None,
+            filter_cfg,
+            filter: DistanceFilter::default(),
         })
     }
 
-    fn process(&mut self, _clock: &RobotClock, output: &mut Self::Output<'_>) -> CuResult<()> {
-        let dist_cm = self.threaded_driver_instance.dist_cm();
-
-        match dist_cm {
-            Some(dist) => {
-                self.last_value = Some(HcSr04Payload { distance: Some(dist.to_val()) })
-            },
-            _ => self.last_value = Some(HcSr04Payload { distance: None })
-        }
+    fn process(&mut self, clock: &RobotClock, output: &mut Self::Output<'_>) -> CuResult<()> {
+        let Some(driver) = self.threaded_driver_instance.as_mut() else {
+            output.set_payload(HcSr04Payload::UNAVAILABLE);
+            return Ok(());
+        };
+
+        let raw_distance = driver.dist_cm().map(|dist| dist.to_val());
+        let now = clock.now();
+        let distance = self.filter.update(&self.filter_cfg, now, raw_distance);
+
+        self.last_value = Some(HcSr04Payload {
+            distance,
+            raw_distance,
+            // the threaded driver keeps its own latest echo, every read is taken as a new one
+            fresh: true,
+            last_valid_age_ms: self.filter.last_valid_age_ms(now),
+            timed_out: self.filter.timed_out(&self.filter_cfg, now),
+            availability: Availability::Available,
+        });
 
         if let Some(payload) = self.last_value {
             output.set_payload(payload);
@@ -87,5 +142,100 @@ impl CuSrcTask for CuHcSr04 {
 
 #[cfg(test)]
 mod tests {
-    // leave it to an LLM later
+    use super::*;
+
+    const CYCLE: Duration = Duration::from_millis(18);
+
+    /// One sample per cycle on the mock clock, returns the filtered distances
+    fn feed(f: &mut DistanceFilter, cfg: &DistanceFilterCfg, clock: &(RobotClock, RobotClockMock), raw: &[Option<f64>]) -> Vec<Option<f64>> {
+        let (clock, mock) = clock;
+        raw.iter()
+            .map(|r| {
+                let d = f.update(cfg, clock.now(), *r);
+                mock.increment(CYCLE);
+                d
+            })
+            .collect()
+    }
+
+    #[test]
+    fn median_drops_a_single_outlier() {
+        let cfg = DistanceFilterCfg { median_window: 5, ..Default::default() };
+        let clock = RobotClock::mock();
+        let mut f = DistanceFilter::default();
+
+        let out = feed(&mut f, &cfg, &clock, &[Some(50.0), Some(52.0), Some(200.0), Some(51.0), Some(49.0)]);
+        assert_eq!(out, vec![Some(50.0), Some(52.0), Some(52.0), Some(51.0), Some(51.0)]);
+
+        // window of 1 passes everything through
+        let cfg = DistanceFilterCfg::default();
+        let mut f = DistanceFilter::default();
+        assert_eq!(feed(&mut f, &cfg, &clock, &[Some(50.0), Some(200.0)]), vec![Some(50.0), Some(200.0)]);
+    }
+
+    #[test]
+    fn rate_check_rejects_jumps_until_max_rejects() {
+        let cfg = DistanceFilterCfg { max_rate_cm_s: Some(300.0), max_rejects: 3, ..Default::default() };
+        let clock = RobotClock::mock();
+        let mut f = DistanceFilter::default();
+
+        // 300 cm/s over an 18 ms cycle is 5.4 cm
+        let out = feed(&mut f, &cfg, &clock, &[Some(100.0), Some(95.0), Some(30.0), Some(30.0), Some(30.0)]);
+        assert_eq!(out, vec![Some(100.0), Some(95.0), Some(95.0), Some(95.0), Some(95.0)]);
+
+        // it really is there, taken after max_rejects in a row
+        assert_eq!(feed(&mut f, &cfg, &clock, &[Some(30.0)]), vec![Some(30.0)]);
+
+        // the allowed step grows with the time since the last accepted echo
+        let out = feed(&mut f, &cfg, &clock, &[None, None, None, Some(42.0)]);
+        assert_eq!(out, vec![Some(30.0), Some(30.0), Some(30.0), Some(42.0)]);
+    }
+
+    #[test]
+    fn age_and_timeout() {
+        let cfg = DistanceFilterCfg { median_window: 3, ..Default::default() };
+        let (clock, mock) = RobotClock::mock();
+        let mut f = DistanceFilter::default();
+
+        // never a valid echo
+        assert_eq!(f.update(&cfg, clock.now(), None), None);
+        assert_eq!(f.last_valid_age_ms(clock.now()), None);
+        assert!(f.timed_out(&cfg, clock.now()));
+
+        assert_eq!(f.update(&cfg, clock.now(), Some(80.0)), Some(80.0));
+        assert_eq!(f.last_valid_age_ms(clock.now()), Some(0));
+        assert!(!f.timed_out(&cfg, clock.now()));
+
+        // missed echoes only age it
+        mock.increment(Duration::from_millis(cfg.echo_timeout_ms));
+        assert_eq!(f.update(&cfg, clock.now(), None), Some(80.0));
+        assert_eq!(f.last_valid_age_ms(clock.now()), Some(cfg.echo_timeout_ms));
+        assert!(!f.timed_out(&cfg, clock.now()));
+
+        mock.increment(CYCLE);
+        assert_eq!(f.update(&cfg, clock.now(), None), None);
+        assert!(f.timed_out(&cfg, clock.now()));
+
+        // the window was cleared, the next echo starts over
+        assert_eq!(f.update(&cfg, clock.now(), Some(20.0)), Some(20.0));
+        assert!(!f.timed_out(&cfg, clock.now()));
+    }
+
+    #[test]
+    fn n_of_m_confirms_and_releases() {
+        let mut c = NOfM::new(3, 5);
+        let out: Vec<bool> = [false, true, true, true, false, false, false]
+            .into_iter()
+            .map(|s| c.update(s))
+            .collect();
+        assert_eq!(out, vec![false, false, false, true, true, true, false]);
+
+        c.reset();
+        assert!(!c.update(true));
+
+        // clamped to 1 of 1, every sample decides
+        let mut c = NOfM::new(0, 0);
+        assert!(c.update(true));
+        assert!(!c.update(false));
+    }
 }
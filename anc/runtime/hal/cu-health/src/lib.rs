@@ -0,0 +1,71 @@
+/// Shared by the HAL tasks: config helpers that return CuErrors instead of panicking, and the
+/// criticality/availability pair that lets optional hardware start degraded instead of taking the
+/// whole runtime down.
+
+extern crate cu_bincode as bincode;
+use cu29::prelude::*;
+use cu29::config::Value;
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+/// "criticality" in a task's RON config
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Criticality {
+    /// Init failure is returned as an error and stops the runtime
+    #[default]
+    Required,
+    /// Init failure is logged, the task starts unavailable and says so in its payload
+    Optional,
+}
+
+impl Criticality {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "required" => Ok(Criticality::Required),
+            "optional" => Ok(Criticality::Optional),
+            _ => Err(format!("Invalid criticality value: \"{s}\". Valid values: \"required\", \"optional\"")),
+        }
+    }
+
+    pub fn from_config(config: &ComponentConfig) -> CuResult<Self> {
+        let ComponentConfig(kv) = config;
+        match kv.get("criticality") {
+            Some(v) => {
+                let s: String = v.clone().into();
+                Criticality::parse(&s).map_err(|e| CuError::from(e.as_str()))
+            },
+            None => Ok(Criticality::default()),
+        }
+    }
+
+    /// Turns a hardware init result into the driver for Required, or into None (logged) for Optional
+    pub fn degrade<T>(self, task: &str, res: CuResult<T>) -> CuResult<Option<T>> {
+        match (res, self) {
+            (Ok(driver), _) => Ok(Some(driver)),
+            (Err(e), Criticality::Required) => Err(e),
+            (Err(e), Criticality::Optional) => {
+                eprintln!("{task}: unavailable, running degraded: {e}");
+                Ok(None)
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+#[derive(Reflect)]
+pub enum Availability {
+    #[default]
+    Available,
+    /// Optional hardware that failed to initialize, or stopped answering. Readings are meaningless.
+    Unavailable,
+}
+
+/// Missing keys are an error, not a panic
+pub fn required_key<T>(config: &ComponentConfig, task: &str, key: &str) -> CuResult<T>
+where Value: Into<T>
+{
+    let ComponentConfig(kv) = config;
+    kv.get(key)
+        .map(|v| v.clone().into())
+        .ok_or_else(|| CuError::from(format!("{task}: {key} not set in RON config")))
+}
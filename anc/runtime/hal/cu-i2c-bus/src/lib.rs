@@ -0,0 +1,388 @@
+/// One I2C bus shared by every task with a device on it, handed out through copper resources.
+/// Transactions are serialized, transient errors retried, a stuck bus gets its handles reopened
+/// (see I2cBus::recover for what that can't fix), and each device address keeps its own error counters.
+
+extern crate cu_bincode as bincode;
+use cu29::prelude::*;
+use cu29::resource::{BundleContext, ResourceBundle, ResourceManager};
+use cu29::bundle_resources;
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use i2cdev::linux::{LinuxI2CDevice, LinuxI2CError};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub const DEFAULT_I2C_BUS: &str = "/dev/i2c-1";
+pub const DEFAULT_RETRIES: u32 = 2;
+pub const DEFAULT_RETRY_DELAY_US: u64 = 200;
+pub const DEFAULT_STUCK_AFTER: u32 = 5;
+pub const DEFAULT_RECOVERY_BACKOFF_MS: u64 = 100;
+
+/// Per device address, since the bus came up. Exported in the device payloads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Encode, Decode, Serialize, Deserialize)]
+#[derive(Reflect)]
+pub struct I2cDeviceStats {
+    pub transactions: u32,
+    /// Attempts that failed and were retried
+    pub retries: u32,
+    /// Transactions that still failed after every retry
+    pub failures: u32,
+    /// Bus recoveries since boot, the same for every device on the bus
+    pub recoveries: u32,
+}
+
+/// From the resource's RON config:
+///   "path" - the i2c-dev node
+///   "retries", "retry_delay_us" - per transaction, for NACKs, lost arbitration and timeouts
+///   "stuck_after" - this many failed transactions in a row, any device, and the bus is recovered
+///   "recovery_backoff_ms" - transactions fail fast for this long after a recovery
+#[derive(Debug, Clone)]
+pub struct I2cBusCfg {
+    pub path: String,
+    pub retries: u32,
+    pub retry_delay_us: u64,
+    pub stuck_after: u32,
+    pub recovery_backoff_ms: u64,
+}
+
+impl Default for I2cBusCfg {
+    fn default() -> Self {
+        Self {
+            path: String::from(DEFAULT_I2C_BUS),
+            retries: DEFAULT_RETRIES,
+            retry_delay_us: DEFAULT_RETRY_DELAY_US,
+            stuck_after: DEFAULT_STUCK_AFTER,
+            recovery_backoff_ms: DEFAULT_RECOVERY_BACKOFF_MS,
+        }
+    }
+}
+
+impl I2cBusCfg {
+    pub fn from_config(config: Option<&ComponentConfig>) -> CuResult<Self> {
+        let mut cfg = Self::default();
+        let Some(ComponentConfig(kv)) = config else {
+            return Ok(cfg);
+        };
+        let get_f64 = |key: &str| kv.get(key).map(|v| -> f64 { v.clone().into() });
+
+        if let Some(v) = kv.get("path") { cfg.path = v.clone().into(); }
+        if let Some(f) = get_f64("retries") { cfg.retries = f as u32; }
+        if let Some(f) = get_f64("retry_delay_us") { cfg.retry_delay_us = f as u64; }
+        if let Some(f) = get_f64("stuck_after") { cfg.stuck_after = f as u32; }
+        if let Some(f) = get_f64("recovery_backoff_ms") { cfg.recovery_backoff_ms = f as u64; }
+
+        if cfg.stuck_after == 0 {
+            return Err(CuError::from("I2C bus: stuck_after must be at least 1"));
+        }
+        Ok(cfg)
+    }
+}
+
+/// What an error says about the bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fault {
+    /// NACK, lost arbitration, a one-off I/O error. Worth another attempt.
+    Transient,
+    /// SCL held low or clock stretched past the adapter timeout, recover now
+    Stuck,
+    /// Retrying won't help (bad fd, no such adapter)
+    Fatal,
+}
+
+impl Fault {
+    fn of(e: &LinuxI2CError) -> Self {
+        let errno = match e {
+            LinuxI2CError::Errno(n) => Some(*n),
+            LinuxI2CError::Io(e) => e.raw_os_error(),
+        };
+        match errno {
+            Some(libc::ETIMEDOUT) => Fault::Stuck,
+            Some(libc::EIO | libc::ENXIO | libc::EREMOTEIO | libc::EAGAIN) => Fault::Transient,
+            _ => Fault::Fatal,
+        }
+    }
+}
+
+/// Retry and stuck-bus bookkeeping, kept apart from the device handles so it runs without hardware
+#[derive(Debug, Default)]
+struct BusHealth {
+    stats: HashMap<u8, I2cDeviceStats>,
+    consecutive_failures: u32,
+    recoveries: u32,
+    recovering_since: Option<CuTime>,
+}
+
+impl BusHealth {
+    /// False while the backoff after a recovery is running
+    fn ready(&mut self, cfg: &I2cBusCfg, now: CuTime) -> bool {
+        if let Some(since) = self.recovering_since {
+            if now.as_nanos().saturating_sub(since.as_nanos()) < CuDuration::from_millis(cfg.recovery_backoff_ms).as_nanos() {
+                return false;
+            }
+            self.recovering_since = None;
+        }
+        true
+    }
+
+    /// Runs `op` for the device at `addr`, retrying transient faults. On Err: the last error, the
+    /// number of attempts and whether the bus now needs recovering.
+    fn run<T, E>(&mut self, cfg: &I2cBusCfg, addr: u8, mut op: impl FnMut() -> Result<T, E>, fault_of: impl Fn(&E) -> Fault)
+    -> Result<T, (E, u32, bool)>
+    {
+        self.stats.entry(addr).or_default().transactions += 1;
+
+        let mut attempt = 0;
+        let (e, fault) = loop {
+            let e = match op() {
+                Ok(v) => {
+                    self.consecutive_failures = 0;
+                    return Ok(v);
+                },
+                Err(e) => e,
+            };
+
+            let fault = fault_of(&e);
+            if fault != Fault::Transient || attempt >= cfg.retries {
+                break (e, fault);
+            }
+            attempt += 1;
+            self.stats.entry(addr).or_default().retries += 1;
+            std::thread::sleep(Duration::from_micros(cfg.retry_delay_us));
+        };
+
+        self.stats.entry(addr).or_default().failures += 1;
+        self.consecutive_failures += 1;
+        let stuck = fault == Fault::Stuck || self.consecutive_failures >= cfg.stuck_after;
+        Err((e, attempt + 1, stuck))
+    }
+
+    fn recovered(&mut self, now: CuTime) {
+        self.recoveries += 1;
+        self.consecutive_failures = 0;
+        self.recovering_since = Some(now);
+    }
+}
+
+struct BusState {
+    /// Opened on first use and dropped (fd closed) on recovery
+    devices: HashMap<u8, LinuxI2CDevice>,
+    health: BusHealth,
+}
+
+pub struct I2cBus {
+    cfg: I2cBusCfg,
+    /// Only times the recovery backoff, a relative duration, so it needn't be the runtime's clock
+    clock: RobotClock,
+    state: Mutex<BusState>,
+}
+
+impl I2cBus {
+    pub fn new(cfg: I2cBusCfg) -> Self {
+        Self::with_clock(cfg, RobotClock::new())
+    }
+
+    pub fn with_clock(cfg: I2cBusCfg, clock: RobotClock) -> Self {
+        Self {
+            cfg,
+            clock,
+            state: Mutex::new(BusState {
+                devices: HashMap::new(),
+                health: BusHealth::default(),
+            }),
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.cfg.path
+    }
+
+    fn lock(&self) -> CuResult<std::sync::MutexGuard<'_, BusState>> {
+        self.state.lock()
+            .map_err(|_| CuError::from(format!("I2C bus {}: lock poisoned", self.cfg.path)))
+    }
+
+    /// Bumped by every recovery. A device that holds configuration (calibration etc.) compares it
+    /// against what it saw at init and re-initializes when it changed, it may have browned out.
+    pub fn generation(&self) -> u32 {
+        self.lock().map_or(0, |state| state.health.recoveries)
+    }
+
+    pub fn stats(&self, addr: u8) -> I2cDeviceStats {
+        self.lock()
+            .map(|state| I2cDeviceStats {
+                recoveries: state.health.recoveries,
+                ..state.health.stats.get(&addr).copied().unwrap_or_default()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Runs `op` against the device at `addr` holding the bus, so a register write + read pair
+    /// can't interleave with another task's. Transient errors are retried, the last error is returned.
+    pub fn transaction<T>(&self, addr: u8, mut op: impl FnMut(&mut LinuxI2CDevice) -> Result<T, LinuxI2CError>)
+    -> CuResult<T>
+    {
+        let now = self.clock.now();
+        let mut guard = self.lock()?;
+        let BusState { devices, health } = &mut *guard;
+
+        if !health.ready(&self.cfg, now) {
+            return Err(CuError::from(format!("I2C 0x{addr:02x}: bus {} recovering", self.cfg.path)));
+        }
+
+        let res = health.run(&self.cfg, addr, || match devices.get_mut(&addr) {
+            Some(dev) => op(dev),
+            None => match LinuxI2CDevice::new(&self.cfg.path, addr as u16) {
+                Ok(dev) => op(devices.entry(addr).or_insert(dev)),
+                Err(e) => Err(e),
+            },
+        }, Fault::of);
+
+        match res {
+            Ok(v) => Ok(v),
+            Err((e, attempts, stuck)) => {
+                if stuck {
+                    self.recover(devices, health, now);
+                }
+                Err(CuError::from(format!("I2C 0x{addr:02x}: {e} after {attempts} attempt(s)")))
+            },
+        }
+    }
+
+    /// Closes every handle so the next transaction starts from a fresh open, and holds off for a bit
+    /// so nobody hammers the bus meanwhile. That alone doesn't free a slave holding SDA low: it only
+    /// helps when the adapter driver does kernel bus recovery (clocking SCL out of it on a timeout).
+    /// Without it the bus stays stuck until the slave is power cycled, and this only turns a steady
+    /// stream of timeouts into a recovery every stuck_after failures.
+    fn recover(&self, devices: &mut HashMap<u8, LinuxI2CDevice>, health: &mut BusHealth, now: CuTime) {
+        eprintln!("I2C bus {}: stuck after {} failed transactions, recovering (#{})",
+            self.cfg.path, health.consecutive_failures, health.recoveries + 1);
+        devices.clear();
+        health.recovered(now);
+    }
+}
+
+/// RON:
+///   resources: [ (id: "i2c1", provider: "cu_i2c_bus::I2cBusBundle", config: { "path": "/dev/i2c-1" }) ]
+/// and on each device task: resources: { "bus": "i2c1.bus" }
+pub struct I2cBusBundle;
+bundle_resources!(I2cBusBundle: Bus);
+
+impl ResourceBundle for I2cBusBundle {
+    fn build(bundle: BundleContext<Self>, config: Option<&ComponentConfig>, manager: &mut ResourceManager)
+    -> CuResult<()>
+    {
+        let cfg = I2cBusCfg::from_config(config)?;
+        manager.add_shared(bundle.key(I2cBusBundleId::Bus), Arc::new(I2cBus::new(cfg)))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use i2cdev::core::I2CDevice;
+
+    fn cfg() -> I2cBusCfg {
+        I2cBusCfg { retry_delay_us: 0, ..Default::default() }
+    }
+
+    /// Replays `faults` one attempt at a time, Ok once they run out
+    fn replay(h: &mut BusHealth, cfg: &I2cBusCfg, addr: u8, faults: &mut Vec<Fault>) -> Result<(), (Fault, u32, bool)> {
+        faults.reverse();
+        let res = h.run(cfg, addr, || faults.pop().map_or(Ok(()), Err), |f| *f);
+        faults.reverse();
+        res
+    }
+
+    #[test]
+    fn transient_faults_are_retried() {
+        let cfg = cfg();
+        let mut h = BusHealth::default();
+
+        assert_eq!(replay(&mut h, &cfg, 0x40, &mut vec![Fault::Transient, Fault::Transient]), Ok(()));
+        assert_eq!(h.stats[&0x40], I2cDeviceStats { transactions: 1, retries: 2, failures: 0, recoveries: 0 });
+
+        // out of retries, the last error comes back after retries + 1 attempts
+        let res = replay(&mut h, &cfg, 0x40, &mut vec![Fault::Transient; 5]);
+        assert_eq!(res, Err((Fault::Transient, cfg.retries + 1, false)));
+        assert_eq!(h.stats[&0x40], I2cDeviceStats { transactions: 2, retries: 4, failures: 1, recoveries: 0 });
+        assert_eq!(h.consecutive_failures, 1);
+
+        // a fatal one isn't retried
+        assert_eq!(replay(&mut h, &cfg, 0x41, &mut vec![Fault::Fatal]), Err((Fault::Fatal, 1, false)));
+        assert_eq!(h.stats[&0x41].retries, 0);
+
+        // counters are per device, the success streak is the bus's
+        assert_eq!(replay(&mut h, &cfg, 0x41, &mut vec![]), Ok(()));
+        assert_eq!(h.consecutive_failures, 0);
+        assert_eq!(h.stats[&0x40].transactions, 2);
+    }
+
+    #[test]
+    fn stuck_bus_detection() {
+        let cfg = cfg();
+        let mut h = BusHealth::default();
+
+        // a timeout is a held line, no retry and recover right away
+        assert_eq!(replay(&mut h, &cfg, 0x40, &mut vec![Fault::Stuck]), Err((Fault::Stuck, 1, true)));
+
+        // or stuck_after failed transactions in a row, across devices
+        let mut h = BusHealth::default();
+        for i in 1..=cfg.stuck_after {
+            let addr = 0x40 + (i % 2) as u8;
+            let (_, _, stuck) = replay(&mut h, &cfg, addr, &mut vec![Fault::Fatal]).unwrap_err();
+            assert_eq!(stuck, i == cfg.stuck_after, "failure {i}");
+        }
+
+        // one success in between starts the count over
+        let mut h = BusHealth::default();
+        for _ in 1..cfg.stuck_after {
+            assert!(!replay(&mut h, &cfg, 0x40, &mut vec![Fault::Fatal]).unwrap_err().2);
+        }
+        assert_eq!(replay(&mut h, &cfg, 0x40, &mut vec![]), Ok(()));
+        assert!(!replay(&mut h, &cfg, 0x40, &mut vec![Fault::Fatal]).unwrap_err().2);
+    }
+
+    #[test]
+    fn backoff_after_a_recovery() {
+        let cfg = cfg();
+        let (clock, mock) = RobotClock::mock();
+        let mut h = BusHealth::default();
+        h.consecutive_failures = cfg.stuck_after;
+
+        assert!(h.ready(&cfg, clock.now()));
+        h.recovered(clock.now());
+        assert_eq!((h.recoveries, h.consecutive_failures), (1, 0));
+
+        mock.increment(Duration::from_millis(cfg.recovery_backoff_ms - 1));
+        assert!(!h.ready(&cfg, clock.now()));
+        mock.increment(Duration::from_millis(1));
+        assert!(h.ready(&cfg, clock.now()));
+        assert_eq!(h.recovering_since, None);
+    }
+
+    #[test]
+    fn bus_recovers_and_backs_off_on_its_clock() {
+        let cfg = I2cBusCfg { path: String::from("/nonexistent/i2c-dev"), stuck_after: 2, ..cfg() };
+        let (clock, mock) = RobotClock::mock();
+        let bus = I2cBus::with_clock(cfg.clone(), clock);
+
+        // no adapter, every transaction fails without a retry
+        for _ in 0..cfg.stuck_after {
+            assert!(bus.transaction(0x40, |dev| dev.smbus_read_byte()).is_err());
+        }
+        assert_eq!(bus.generation(), 1);
+        assert_eq!(bus.stats(0x40), I2cDeviceStats { transactions: 2, retries: 0, failures: 2, recoveries: 1 });
+
+        // failing fast, not even counted as a transaction
+        let err = bus.transaction(0x40, |dev| dev.smbus_read_byte()).unwrap_err();
+        assert!(err.to_string().contains("recovering"), "{err}");
+        assert_eq!(bus.stats(0x40).transactions, 2);
+
+        mock.increment(Duration::from_millis(cfg.recovery_backoff_ms));
+        assert!(bus.transaction(0x40, |dev| dev.smbus_read_byte()).is_err());
+        assert_eq!(bus.stats(0x40).transactions, 3);
+        assert_eq!(bus.generation(), 1);
+    }
+}
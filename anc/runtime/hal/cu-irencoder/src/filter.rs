@@ -0,0 +1,85 @@
+use std::collections::VecDeque;
+use cu29::prelude::*;
+
+/// 1 disables the median stage
+pub const DEFAULT_MEDIAN_WINDOW: usize = 1;
+/// 1.0 disables the EMA stage
+pub const DEFAULT_EMA_ALPHA: f32 = 1.0;
+/// Consecutive implausible samples after which the reading is taken as real (the wheel really did
+/// stop or get kicked), otherwise the filter could hold a stale value forever
+pub const DEFAULT_MAX_REJECTS: u32 = 3;
+
+#[derive(Debug, Clone, Copy)]
+pub struct FilterCfg {
+    pub median_window: usize,
+    pub ema_alpha: f32,
+    /// normalized rpm per second, None disables the check
+    pub max_accel: Option<f32>,
+    pub max_rejects: u32,
+}
+
+impl Default for FilterCfg {
+    fn default() -> Self {
+        Self {
+            median_window: DEFAULT_MEDIAN_WINDOW,
+            ema_alpha: DEFAULT_EMA_ALPHA,
+            max_accel: None,
+            max_rejects: DEFAULT_MAX_REJECTS,
+        }
+    }
+}
+
+/// One per wheel. In slot mode it works on the unsigned reading from the driver and the sign is
+/// applied afterwards so a direction flip doesn't look like a jump. Quadrature readings go in signed.
+/// Stages: plausibility (max acceleration) -> median of the last N -> EMA.
+/// The driver only gives rpm, but rpm is monotonic in the slot period so the median is the same
+/// sample either way.
+#[derive(Debug, Default)]
+pub struct RpmFilter {
+    window: VecDeque<f32>,
+    last_accepted: Option<(f32, CuTime)>,
+    rejects: u32,
+    ema: Option<f32>,
+}
+
+impl RpmFilter {
+    /// `now` is the robot clock, the acceleration check runs on it
+    pub fn update(&mut self, cfg: &FilterCfg, now: CuTime, raw: f32) -> f32 {
+        let accepted = match (cfg.max_accel, self.last_accepted) {
+            (Some(max_accel), Some((last, since))) => {
+                let dt_s = now.as_nanos()
+                    .checked_sub(since.as_nanos())
+                    .unwrap_or(0) as f32 / 1_000_000_000.0;
+                let plausible = (raw - last).abs() <= max_accel * dt_s;
+                if plausible || self.rejects >= cfg.max_rejects {
+                    Some(raw)
+                } else {
+                    None
+                }
+            },
+            _ => Some(raw),
+        };
+
+        let Some(sample) = accepted else {
+            self.rejects += 1;
+            return self.ema.unwrap_or(0.0);
+        };
+        self.rejects = 0;
+        self.last_accepted = Some((sample, now));
+
+        self.window.push_back(sample);
+        while self.window.len() > cfg.median_window.max(1) {
+            self.window.pop_front();
+        }
+        let mut sorted: Vec<f32> = self.window.iter().copied().collect();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let median = sorted[sorted.len() / 2];
+
+        let filtered = match self.ema {
+            Some(prev) => prev + cfg.ema_alpha * (median - prev),
+            None => median,
+        };
+        self.ema = Some(filtered);
+        filtered
+    }
+}
@@ -1,95 +1,314 @@
 extern crate cu_bincode as bincode;
 
+mod filter;
+mod quadrature;
+pub use filter::*;
+pub use quadrature::*;
+
 use cu29::prelude::*;
 use bincode::{Decode, Encode};
 use serde::{Deserialize, Serialize};
+use cu_health::required_key;
 use ir_encoder_gpio_cdev::*;
+use gpio_cdev::Chip;
+use cu_propulsion::{AppliedDirections, WheelDirection};
+
+/// How long a wheel keeps its old sign after the commanded direction flips. The wheel has to spin
+/// down through zero before it can turn the other way, and a single slot sensor can't see that.
+pub const DEFAULT_DIRECTION_HOLDOFF_MS: u64 = 150;
+
+/// "slot": one IR interrupter per wheel, unsigned, sign comes from `direction_source`.
+/// "quadrature": hall-effect A/B channels per wheel, signed by the encoder itself.
+enum WheelDrivers {
+    Slot { lmtr: IrEncoder, rmtr: IrEncoder },
+    Quadrature { lmtr: QuadratureEncoder, rmtr: QuadratureEncoder },
+}
 
 #[derive(Reflect)]
 #[reflect(no_field_bounds, from_reflect = false)]
 pub struct CuIrEncoder {
     #[reflect(ignore)]
-    lmtr_driver: IrEncoder,
-    #[reflect(ignore)]
-    rmtr_driver: IrEncoder,
+    drivers: WheelDrivers,
     #[reflect(ignore)]
     last_value: Option<IrEncoderPayload>,
+    num_of_slots: u32,
+    max_rpm: u32,
+    signed: bool,
+    direction_holdoff_ms: u64,
+    #[reflect(ignore)]
+    lmtr_sign: WheelSign,
+    #[reflect(ignore)]
+    rmtr_sign: WheelSign,
+    #[reflect(ignore)]
+    last_tick: Option<CuTime>,
+    /// fractional ticks, the slot driver only exposes rpm so these are integrated
+    lmtr_ticks_acc: f64,
+    rmtr_ticks_acc: f64,
+    #[reflect(ignore)]
+    filter_cfg: FilterCfg,
+    #[reflect(ignore)]
+    lmtr_filter: RpmFilter,
+    #[reflect(ignore)]
+    rmtr_filter: RpmFilter,
 }
 
+/// Normalized rpm is signed: negative when the wheel turns in reverse.
+/// Unsigned (as before) if `direction_source` is "none".
+/// Tick counts are cumulative since start and signed the same way.
+/// `*_normalized_rpm` is filtered, `*_raw_normalized_rpm` is the driver reading (signed) before filtering.
 #[derive(Debug, Clone, Copy, Encode, Decode, Default, PartialEq, Serialize, Deserialize)]
 #[derive(Reflect)]
 #[reflect(no_field_bounds, from_reflect = false)]
 pub struct IrEncoderPayload {
     pub lmtr_normalized_rpm: Option<f32>,
     pub rmtr_normalized_rpm: Option<f32>,
+    pub lmtr_raw_normalized_rpm: Option<f32>,
+    pub rmtr_raw_normalized_rpm: Option<f32>,
+    pub lmtr_ticks: i64,
+    pub rmtr_ticks: i64,
+}
+
+/// Sign of one wheel's velocity, from the commanded direction with a hold-off on direction flips.
+/// A Stop command keeps the last sign since the wheel coasts the way it was going.
+#[derive(Debug)]
+struct WheelSign {
+    sign: f32,
+    pending: Option<(f32, CuTime)>,
+}
+
+impl Default for WheelSign {
+    fn default() -> Self {
+        Self { sign: 1.0, pending: None }
+    }
+}
+
+impl WheelSign {
+    fn update(&mut self, now: CuTime, commanded: WheelDirection, holdoff_ms: u64) -> f32 {
+        let wanted = match commanded {
+            WheelDirection::Forward => Some(1.0),
+            WheelDirection::Reverse => Some(-1.0),
+            WheelDirection::Stop => None,
+        };
+
+        match wanted {
+            Some(w) if w != self.sign => {
+                // keep the original flip instant if it's still pending
+                let since = match self.pending {
+                    Some((p, since)) if p == w => since,
+                    _ => now,
+                };
+                self.pending = Some((w, since));
+            },
+            Some(_) => self.pending = None,
+            None => (),
+        }
+
+        if let Some((w, since)) = self.pending {
+            let elapsed_ns = now.as_nanos()
+                .checked_sub(since.as_nanos())
+                .unwrap_or(0);
+            if CuDuration::from_nanos(elapsed_ns) >= CuDuration::from_millis(holdoff_ms) {
+                self.sign = w;
+                self.pending = None;
+            }
+        }
+
+        self.sign
+    }
 }
 
 impl Freezable for CuIrEncoder {}
 
-impl CuSrcTask for CuIrEncoder {
+impl CuTask for CuIrEncoder {
+    // AppliedDirections from cu_propulsion::AppliedDirectionsSrc, last cycle's
+    type Input<'m> = input_msg!('m, AppliedDirections);
     type Output<'m> = output_msg!(IrEncoderPayload);
     type Resources<'r> = ();
 
     fn new(config: Option<&ComponentConfig>,  _resources: Self::Resources<'_>) -> CuResult<Self>
     where Self:Sized
     {
-        let ComponentConfig(kv) =
-            config.ok_or("No ComponentConfig specified for GPIO in RON")?;
-
-        let lmtr_output_pin: u32 = kv
-            .get("lmtr_output_pin")
-            .expect("lmtr_output_pin for IrEncoder not set in RON config")
-            .clone()
-            .into();
-
-        let rmtr_output_pin: u32 = kv
-            .get("rmtr_output_pin")
-            .expect("rmtr_output_pin for IrEncoder not set in RON config")
-            .clone()
-            .into();
-
-        let num_of_slots: u32 = kv
-            .get("num_of_slots")
-            .expect("num_of_slots for IrEncoder not set in RON config")
-            .clone()
-            .into();
-
-        let max_rpm: u32 = kv
-            .get("max_rpm")
-            .expect("max_rpm for IrEncoder not set in RON config")
-            .clone()
-            .into();
-
-        let lmtr_driver = IrEncoder::new(lmtr_output_pin, Some(num_of_slots), Some(max_rpm)).expect("ir-encoder driver");
-        let rmtr_driver = IrEncoder::new(rmtr_output_pin, Some(num_of_slots), Some(max_rpm)).expect("ir-encoder driver");
+        let config = config.ok_or("No ComponentConfig specified for GPIO in RON")?;
+        let ComponentConfig(kv) = config;
+
+        let max_rpm: u32 = required_key(config, "IrEncoder", "max_rpm")?;
+
+        // "propulsion" signs the readings with the AppliedDirections input, "none" leaves them unsigned
+        let signed = match kv.get("direction_source") {
+            Some(v) => {
+                let s: String = v.clone().into();
+                match s.as_str() {
+                    "propulsion" => true,
+                    "none" => false,
+                    _ => return Err(CuError::from(format!("Invalid direction_source value: \"{s}\". Valid values: \"propulsion\", \"none\""))),
+                }
+            },
+            None => true,
+        };
+
+        let direction_holdoff_ms: u64 = kv.get("direction_holdoff_ms")
+            .map(|v| { let f: f64 = v.clone().into(); f as u64 })
+            .unwrap_or(DEFAULT_DIRECTION_HOLDOFF_MS);
+
+        let mut filter_cfg = FilterCfg::default();
+        if let Some(v) = kv.get("median_window") {
+            let f: f64 = v.clone().into();
+            filter_cfg.median_window = (f as usize).max(1);
+        }
+        if let Some(v) = kv.get("ema_alpha") {
+            let f: f64 = v.clone().into();
+            if !(f > 0.0 && f <= 1.0) {
+                return Err(CuError::from(format!("IrEncoder: ema_alpha must be in (0, 1], got {f}")));
+            }
+            filter_cfg.ema_alpha = f as f32;
+        }
+        // normalized rpm per second
+        if let Some(v) = kv.get("max_accel") {
+            let f: f64 = v.clone().into();
+            filter_cfg.max_accel = Some(f as f32);
+        }
+        if let Some(v) = kv.get("max_rejects") {
+            let f: f64 = v.clone().into();
+            filter_cfg.max_rejects = f as u32;
+        }
+
+        let encoder_type: String = kv.get("encoder_type")
+            .map(|v| v.clone().into())
+            .unwrap_or(String::from("slot"));
+
+        let (drivers, num_of_slots) = match encoder_type.as_str() {
+            "slot" => {
+                let lmtr_output_pin: u32 = required_key(config, "IrEncoder", "lmtr_output_pin")?;
+                let rmtr_output_pin: u32 = required_key(config, "IrEncoder", "rmtr_output_pin")?;
+                let num_of_slots: u32 = required_key(config, "IrEncoder", "num_of_slots")?;
+
+                let driver = |pin: u32| IrEncoder::new(pin, Some(num_of_slots), Some(max_rpm))
+                    .map_err(|e| CuError::from(format!("IrEncoder: ir-encoder driver error on line {pin}: {e:?}")));
+                let lmtr = driver(lmtr_output_pin)?;
+                let rmtr = driver(rmtr_output_pin)?;
+                (WheelDrivers::Slot { lmtr, rmtr }, num_of_slots)
+            },
+            "quadrature" => {
+                let pin = |key: &str| -> CuResult<u32> { required_key(config, "quadrature IrEncoder", key) };
+
+                let counts_per_rev: u32 = required_key(config, "quadrature IrEncoder", "counts_per_rev")?;
+
+                let gpio_chip: String = kv.get("gpio_chip")
+                    .map(|v| v.clone().into())
+                    .unwrap_or(String::from(DEFAULT_GPIO_CHIP));
+
+                // mirrored motors count the opposite way, flip one side so forward is positive on both
+                let lmtr_invert: bool = kv.get("lmtr_invert").map(|v| v.clone().into()).unwrap_or(false);
+                let rmtr_invert: bool = kv.get("rmtr_invert").map(|v| v.clone().into()).unwrap_or(false);
+
+                let mut chip = Chip::new(&gpio_chip)
+                    .map_err(|e| CuError::from(format!("IrEncoder: failed to open {gpio_chip}: {e}")))?;
+
+                let lmtr = QuadratureEncoder::new(&mut chip, "lmtr-quadrature", pin("lmtr_a_pin")?, pin("lmtr_b_pin")?,
+                    lmtr_invert, counts_per_rev, max_rpm)?;
+                let rmtr = QuadratureEncoder::new(&mut chip, "rmtr-quadrature", pin("rmtr_a_pin")?, pin("rmtr_b_pin")?,
+                    rmtr_invert, counts_per_rev, max_rpm)?;
+                (WheelDrivers::Quadrature { lmtr, rmtr }, 0)
+            },
+            _ => return Err(CuError::from(format!("Invalid encoder_type value: \"{encoder_type}\". Valid values: \"slot\", \"quadrature\""))),
+        };
 
         Ok(Self {
-            lmtr_driver,
-            rmtr_driver,
-            last_value: None
+            drivers,
+            last_value: None,
+            num_of_slots,
+            max_rpm,
+            signed,
+            direction_holdoff_ms,
+            lmtr_sign: WheelSign::default(),
+            rmtr_sign: WheelSign::default(),
+            last_tick: None,
+            lmtr_ticks_acc: 0.0,
+            rmtr_ticks_acc: 0.0,
+            filter_cfg,
+            lmtr_filter: RpmFilter::default(),
+            rmtr_filter: RpmFilter::default(),
         })
     }
 
-    fn process(&mut self, _clock: &RobotClock, output: &mut Self::Output<'_>) -> CuResult<()> {
-        let lmtr_normalized_rpm = self.lmtr_driver.get_normalized_rpm();
-        let rmtr_normalized_rpm = self.rmtr_driver.get_normalized_rpm();
+    fn start(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        if let WheelDrivers::Quadrature { lmtr, rmtr } = &mut self.drivers {
+            lmtr.start()?;
+            rmtr.start()?;
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self, _clock: &RobotClock) -> CuResult<()> {
+        if let WheelDrivers::Quadrature { lmtr, rmtr } = &mut self.drivers {
+            lmtr.stop()?;
+            rmtr.stop()?;
+        }
+        Ok(())
+    }
+
+    fn process(&mut self, clock: &RobotClock, input: &Self::Input<'_>, output: &mut Self::Output<'_>) -> CuResult<()> {
+        if let WheelDrivers::Quadrature { .. } = self.drivers {
+            return self.process_quadrature(clock, output);
+        }
+        let WheelDrivers::Slot { lmtr: lmtr_driver, rmtr: rmtr_driver } = &mut self.drivers else {
+            unreachable!()
+        };
+
+        let now = clock.now();
+        let lmtr_normalized_rpm = lmtr_driver.get_normalized_rpm();
+        let rmtr_normalized_rpm = rmtr_driver.get_normalized_rpm();
 
-        let lmtr = match lmtr_normalized_rpm {
+        let (lmtr_sign, rmtr_sign) = if self.signed {
+            // no message, e.g. the source not running yet: treat it as Stop, which keeps the signs
+            let applied = input.payload().copied().unwrap_or_default();
+            (
+                self.lmtr_sign.update(now, applied.left, self.direction_holdoff_ms),
+                self.rmtr_sign.update(now, applied.right, self.direction_holdoff_ms),
+            )
+        } else {
+            (1.0, 1.0)
+        };
+
+        // unsigned driver readings, None on a read error (previous value is held)
+        let lmtr_raw = match lmtr_normalized_rpm {
             Ok(rpm) => Some(rpm),
             Err(IrEncoderErrors::FdTimeout) => Some(0.0),
-            Err(_) => self.last_value.and_then(|v| v.lmtr_normalized_rpm),
+            Err(_) => None,
         };
 
-        let rmtr = match rmtr_normalized_rpm {
+        let rmtr_raw = match rmtr_normalized_rpm {
             Ok(rpm) => Some(rpm),
             Err(IrEncoderErrors::FdTimeout) => Some(0.0),
-            Err(_) => self.last_value.and_then(|v| v.rmtr_normalized_rpm),
+            Err(_) => None,
         };
 
+        let lmtr = lmtr_raw
+            .map(|raw| self.lmtr_filter.update(&self.filter_cfg, now, raw) * lmtr_sign)
+            .or(self.last_value.and_then(|v| v.lmtr_normalized_rpm));
+
+        let rmtr = rmtr_raw
+            .map(|raw| self.rmtr_filter.update(&self.filter_cfg, now, raw) * rmtr_sign)
+            .or(self.last_value.and_then(|v| v.rmtr_normalized_rpm));
+
+        // ticks = revolutions * slots, integrated over the time since the last cycle
+        if let Some(last_tick) = self.last_tick {
+            let dt_ns = now.as_nanos().checked_sub(last_tick.as_nanos()).unwrap_or(0);
+            let dt_min = dt_ns as f64 / 60_000_000_000.0;
+            let ticks_per_norm = self.max_rpm as f64 * self.num_of_slots as f64 * dt_min;
+            self.lmtr_ticks_acc += lmtr.unwrap_or(0.0) as f64 * ticks_per_norm;
+            self.rmtr_ticks_acc += rmtr.unwrap_or(0.0) as f64 * ticks_per_norm;
+        }
+        self.last_tick = Some(now);
+
         if lmtr.is_some() || rmtr.is_some() {
             self.last_value = Some(IrEncoderPayload {
                 lmtr_normalized_rpm: lmtr.or(self.last_value.and_then(|v| v.lmtr_normalized_rpm)),
                 rmtr_normalized_rpm: rmtr.or(self.last_value.and_then(|v| v.rmtr_normalized_rpm)),
+                lmtr_raw_normalized_rpm: lmtr_raw.map(|rpm| rpm * lmtr_sign),
+                rmtr_raw_normalized_rpm: rmtr_raw.map(|rpm| rpm * rmtr_sign),
+                lmtr_ticks: self.lmtr_ticks_acc.round() as i64,
+                rmtr_ticks: self.rmtr_ticks_acc.round() as i64,
             });
         }
 
@@ -103,3 +322,31 @@ impl CuSrcTask for CuIrEncoder {
         Ok(())
     }
 }
+
+impl CuIrEncoder {
+    /// Quadrature readings are already signed and the counts are real, so no direction hint and no
+    /// tick integration. The filter runs on the signed value.
+    fn process_quadrature(&mut self, clock: &RobotClock, output: &mut Self::Output<'_>) -> CuResult<()> {
+        let WheelDrivers::Quadrature { lmtr, rmtr } = &mut self.drivers else {
+            unreachable!()
+        };
+
+        // a dead edge thread is an error rather than a wheel that stopped turning
+        let now = clock.now();
+        let (lmtr_ticks, lmtr_raw) = lmtr.sample(now)?;
+        let (rmtr_ticks, rmtr_raw) = rmtr.sample(now)?;
+
+        let payload = IrEncoderPayload {
+            lmtr_normalized_rpm: Some(self.lmtr_filter.update(&self.filter_cfg, now, lmtr_raw)),
+            rmtr_normalized_rpm: Some(self.rmtr_filter.update(&self.filter_cfg, now, rmtr_raw)),
+            lmtr_raw_normalized_rpm: Some(lmtr_raw),
+            rmtr_raw_normalized_rpm: Some(rmtr_raw),
+            lmtr_ticks,
+            rmtr_ticks,
+        };
+
+        self.last_value = Some(payload);
+        output.set_payload(payload);
+        Ok(())
+    }
+}
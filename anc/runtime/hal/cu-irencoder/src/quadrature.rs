@@ -0,0 +1,170 @@
+use std::os::fd::AsRawFd;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::thread::{Builder, JoinHandle};
+use gpio_cdev::*;
+use cu29::prelude::*;
+
+pub const DEFAULT_GPIO_CHIP: &str = "/dev/gpiochip4";
+/// How often the edge thread wakes up to check whether the task is stopping
+const EVENT_POLL_TIMEOUT_MS: i32 = 100;
+
+/// Hall-effect quadrature encoder on one wheel, x2 decoding: both edges of channel A are counted
+/// and channel B's level at that edge gives the direction. `counts_per_rev` is therefore
+/// 2 * (pulses per motor revolution) * (gear ratio).
+/// Counting happens on a dedicated thread from gpio-cdev edge events, the task only samples the
+/// count once per cycle.
+pub struct QuadratureEncoder {
+    name: String,
+    a_events: Option<LineEventHandle>,
+    b_line: Option<LineHandle>,
+    invert: bool,
+    count: Arc<AtomicI64>,
+    running: Arc<AtomicBool>,
+    thread_hdl: Option<JoinHandle<CuResult<()>>>,
+    counts_per_rev: f32,
+    max_rpm: f32,
+    last_sample: Option<(i64, CuTime)>,
+    /// Why the edge thread stopped on its own, the count is frozen from then on
+    failure: Option<String>,
+}
+
+impl QuadratureEncoder {
+    pub fn new(chip: &mut Chip, name: &str, a_pin: u32, b_pin: u32, invert: bool, counts_per_rev: u32, max_rpm: u32)
+    -> CuResult<Self>
+    {
+        let a_line = chip.get_line(a_pin)
+            .map_err(|e| CuError::from(format!("{name}: failed to get A line {a_pin}: {e}")))?;
+        let a_events = a_line
+            .events(LineRequestFlags::INPUT, EventRequestFlags::BOTH_EDGES, &format!("{name}-a"))
+            .map_err(|e| CuError::from(format!("{name}: failed to request edge events on A line {a_pin}: {e}")))?;
+
+        let b_line = chip.get_line(b_pin)
+            .map_err(|e| CuError::from(format!("{name}: failed to get B line {b_pin}: {e}")))?;
+        let b_line = b_line.request(LineRequestFlags::INPUT, 0, &format!("{name}-b"))
+            .map_err(|e| CuError::from(format!("{name}: failed to request B line {b_pin}: {e}")))?;
+
+        Ok(Self {
+            name: String::from(name),
+            a_events: Some(a_events),
+            b_line: Some(b_line),
+            invert,
+            count: Arc::new(AtomicI64::new(0)),
+            running: Arc::new(AtomicBool::new(false)),
+            thread_hdl: None,
+            counts_per_rev: counts_per_rev as f32,
+            max_rpm: max_rpm as f32,
+            last_sample: None,
+            failure: None,
+        })
+    }
+
+    pub fn start(&mut self) -> CuResult<()> {
+        let (Some(mut a_events), Some(b_line)) = (self.a_events.take(), self.b_line.take()) else {
+            return Err(CuError::from(format!("{}: already started", self.name)));
+        };
+
+        self.running.store(true, Ordering::Relaxed);
+        let running = Arc::clone(&self.running);
+        let count = Arc::clone(&self.count);
+        let step: i64 = if self.invert { -1 } else { 1 };
+
+        let thread_hdl = Builder::new()
+            .name(format!("{}-edges", self.name))
+            .spawn(move || -> CuResult<()> {
+                let mut pollfd = libc::pollfd { fd: a_events.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+
+                while running.load(Ordering::Relaxed) {
+                    let ready = unsafe { libc::poll(&mut pollfd, 1, EVENT_POLL_TIMEOUT_MS) };
+                    if ready < 0 {
+                        let e = std::io::Error::last_os_error();
+                        if e.kind() == std::io::ErrorKind::Interrupted {
+                            continue;
+                        }
+                        return Err(CuError::from(format!("quadrature: failed to poll edge events: {e}")));
+                    }
+                    if ready == 0 {
+                        continue;
+                    }
+
+                    let event = a_events.get_event()
+                        .map_err(|e| CuError::from(format!("quadrature: failed to read edge event: {e}")))?;
+                    let b = b_line.get_value()
+                        .map_err(|e| CuError::from(format!("quadrature: failed to read B line: {e}")))?;
+
+                    // A leads B going forward: on a rising A edge B is still low
+                    let forward = match event.event_type() {
+                        EventType::RisingEdge => b == 0,
+                        EventType::FallingEdge => b == 1,
+                    };
+                    count.fetch_add(if forward { step } else { -step }, Ordering::Relaxed);
+                }
+                Ok(())
+            })
+            .map_err(|e| CuError::from(format!("{}: failed to spawn edge thread: {e}", self.name)))?;
+
+        self.thread_hdl = Some(thread_hdl);
+        Ok(())
+    }
+
+    pub fn stop(&mut self) -> CuResult<()> {
+        self.running.store(false, Ordering::Relaxed);
+        match self.thread_hdl.take() {
+            Some(hdl) => {
+                let ret = hdl.join()
+                    .map_err(|_| CuError::from("quadrature edge thread panicked upon stop command issued"))?;
+                match ret {
+                    Ok(_) => (),
+                    Err(_) => return Err(CuError::from(format!("Failed to stop {}", self.name)))
+                }
+            },
+            None => ()
+        }
+        Ok(())
+    }
+
+    /// Err from the first sample after the edge thread died (read error or panic) and on every one
+    /// after, a frozen count would otherwise read as a standing wheel
+    fn check_edge_thread(&mut self) -> CuResult<()> {
+        if let Some(failure) = &self.failure {
+            return Err(CuError::from(failure.as_str()));
+        }
+        if !self.thread_hdl.as_ref().is_some_and(|hdl| hdl.is_finished()) {
+            return Ok(());
+        }
+
+        let failure = match self.thread_hdl.take().map(|hdl| hdl.join()) {
+            Some(Ok(Err(e))) => format!("{}: edge thread stopped: {e}", self.name),
+            Some(Err(_)) => format!("{}: edge thread panicked", self.name),
+            _ => format!("{}: edge thread exited", self.name),
+        };
+        eprintln!("{failure}");
+        self.failure = Some(failure.clone());
+        Err(CuError::from(failure))
+    }
+
+    /// (cumulative signed count, signed normalized rpm since the previous sample).
+    /// The first sample only establishes the baseline and reports 0 rpm.
+    pub fn sample(&mut self, now: CuTime) -> CuResult<(i64, f32)> {
+        self.check_edge_thread()?;
+
+        let count = self.count.load(Ordering::Relaxed);
+
+        let normalized_rpm = match self.last_sample {
+            Some((last_count, last)) => {
+                let dt_ns = now.as_nanos().checked_sub(last.as_nanos()).unwrap_or(0);
+                if dt_ns == 0 {
+                    0.0
+                } else {
+                    let dt_min = dt_ns as f32 / 60_000_000_000.0;
+                    let rpm = (count - last_count) as f32 / self.counts_per_rev / dt_min;
+                    rpm / self.max_rpm
+                }
+            },
+            None => 0.0,
+        };
+
+        self.last_sample = Some((count, now));
+        Ok((count, normalized_rpm))
+    }
+}
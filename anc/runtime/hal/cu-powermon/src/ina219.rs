@@ -0,0 +1,212 @@
+use i2cdev::core::I2CDevice;
+use cu29::prelude::*;
+use cu_i2c_bus::I2cBus;
+use std::sync::Arc;
+
+pub const DEFAULT_SHUNT_MOHMS: f64 = 100.0;
+pub const DEFAULT_MAX_CURRENT_MA: f64 = 1000.0;
+
+const REG_CONFIG: u8 = 0x00;
+const REG_SHUNT_VOLTAGE: u8 = 0x01;
+const REG_BUS_VOLTAGE: u8 = 0x02;
+const REG_POWER: u8 = 0x03;
+const REG_CURRENT: u8 = 0x04;
+const REG_CALIBRATION: u8 = 0x05;
+
+const CONFIG_RESET: u16 = 0x8000;
+/// Shunt and bus, continuous
+const MODE_CONTINUOUS: u16 = 0b111;
+const SHUNT_LSB_MV: f64 = 0.01;
+const BUS_LSB_MV: f64 = 4.0;
+/// Fixed by the datasheet, scales the calibration register
+const CAL_SCALE: f64 = 0.04096;
+/// PGA full scale shunt ranges, mV, in register order (/1, /2, /4, /8)
+const PGA_RANGES_MV: [f64; 4] = [40.0, 80.0, 160.0, 320.0];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BusRange {
+    V16,
+    /// Power-on default
+    #[default]
+    V32,
+}
+
+/// Per-instance calibration, from the task's RON config:
+///   "shunt_mohms", "max_current_ma" - calibration and PGA gain (smallest range that fits max_current_ma across the shunt)
+///   "bus_range_v" - 16 or 32
+///   "adc_bits" - 9..12, "adc_samples" - 1, 2, 4 .. 128 (averaged at 12 bit, forces adc_bits 12). Both ADCs.
+#[derive(Debug, Clone, Copy)]
+pub struct Ina219Cfg {
+    pub shunt_mohms: f64,
+    pub max_current_ma: f64,
+    pub bus_range: BusRange,
+    pub adc_bits: u8,
+    pub adc_samples: u8,
+}
+
+impl Default for Ina219Cfg {
+    fn default() -> Self {
+        Self {
+            shunt_mohms: DEFAULT_SHUNT_MOHMS,
+            max_current_ma: DEFAULT_MAX_CURRENT_MA,
+            bus_range: BusRange::default(),
+            adc_bits: 12,
+            adc_samples: 1,
+        }
+    }
+}
+
+impl Ina219Cfg {
+    pub fn from_config(config: &ComponentConfig) -> CuResult<Self> {
+        let ComponentConfig(kv) = config;
+        let get_f64 = |key: &str| kv.get(key).map(|v| -> f64 { v.clone().into() });
+
+        let mut cfg = Self::default();
+        if let Some(f) = get_f64("shunt_mohms") { cfg.shunt_mohms = f; }
+        if let Some(f) = get_f64("max_current_ma") { cfg.max_current_ma = f; }
+        if let Some(f) = get_f64("bus_range_v") {
+            cfg.bus_range = match f as u32 {
+                16 => BusRange::V16,
+                32 => BusRange::V32,
+                _ => return Err(CuError::from(format!("INA219: invalid bus_range_v {f}. Valid values: 16, 32"))),
+            };
+        }
+        if let Some(f) = get_f64("adc_bits") { cfg.adc_bits = f as u8; }
+        if let Some(f) = get_f64("adc_samples") { cfg.adc_samples = f as u8; }
+
+        if cfg.shunt_mohms <= 0.0 || cfg.max_current_ma <= 0.0 {
+            return Err(CuError::from("INA219: shunt_mohms and max_current_ma must be positive"));
+        }
+        // validated here rather than at init, so an optional monitor with a bad config still fails loudly
+        cfg.config_reg()?;
+        cfg.calibration()?;
+        Ok(cfg)
+    }
+
+    /// mA per bit of the current register, the full signed range covers max_current_ma
+    pub fn current_lsb_ma(&self) -> f64 {
+        self.max_current_ma / 32768.0
+    }
+
+    fn calibration(&self) -> CuResult<u16> {
+        // current LSB in A times shunt in ohms
+        let cal = CAL_SCALE / ((self.current_lsb_ma() / 1000.0) * (self.shunt_mohms / 1000.0));
+        if !(1.0..=65534.0).contains(&cal) {
+            return Err(CuError::from(format!(
+                "INA219: shunt_mohms {} with max_current_ma {} is out of the calibration range",
+                self.shunt_mohms, self.max_current_ma)));
+        }
+        // bit 0 is read-only
+        Ok((cal as u16) & 0xFFFE)
+    }
+
+    fn adc_field(&self) -> CuResult<u16> {
+        match (self.adc_bits, self.adc_samples) {
+            (9, 1) => Ok(0b0000),
+            (10, 1) => Ok(0b0001),
+            (11, 1) => Ok(0b0010),
+            (12, 1) => Ok(0b0011),
+            (12, n) if n.is_power_of_two() && n <= 128 => Ok(0b1000 | n.trailing_zeros() as u16),
+            (bits, n) => Err(CuError::from(format!(
+                "INA219: invalid adc_bits {bits} / adc_samples {n}. adc_bits 9..12 with 1 sample, or 12 with 2, 4 .. 128 samples"))),
+        }
+    }
+
+    fn config_reg(&self) -> CuResult<u16> {
+        let shunt_full_scale_mv = self.max_current_ma * self.shunt_mohms / 1000.0;
+        let pga = PGA_RANGES_MV.iter().position(|range| shunt_full_scale_mv <= *range)
+            .ok_or(CuError::from(format!(
+                "INA219: max_current_ma {} across {} mOhm is {shunt_full_scale_mv:.1} mV, above the 320 mV shunt range",
+                self.max_current_ma, self.shunt_mohms)))? as u16;
+        let brng = match self.bus_range {
+            BusRange::V16 => 0,
+            BusRange::V32 => 1,
+        };
+        let adc = self.adc_field()?;
+
+        Ok((brng << 13) | (pga << 11) | (adc << 7) | (adc << 3) | MODE_CONTINUOUS)
+    }
+}
+
+/// One conversion, all in milli-units
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ina219Reading {
+    pub power_mw: f64,
+    /// Signed, positive from VIN+ to VIN-
+    pub load_current_ma: f64,
+    pub shunt_voltage_mv: f64,
+    /// VIN- to ground
+    pub bus_voltage_mv: f64,
+}
+
+/// Register level INA219 driver, calibrated from Ina219Cfg, on a bus shared with the other monitors
+pub struct Ina219 {
+    bus: Arc<I2cBus>,
+    target_addr: u8,
+    cfg: Ina219Cfg,
+    /// Bus generation the calibration was written in
+    generation: u32,
+}
+
+impl Ina219 {
+    pub fn new(bus: Arc<I2cBus>, target_addr: u8, cfg: &Ina219Cfg) -> CuResult<Self> {
+        let mut ina = Self { generation: bus.generation(), bus, target_addr, cfg: *cfg };
+        ina.calibrate()?;
+        Ok(ina)
+    }
+
+    fn calibrate(&mut self) -> CuResult<()> {
+        // taken before the writes, a recovery halfway through means doing it again
+        self.generation = self.bus.generation();
+
+        let calibration = self.cfg.calibration()?;
+        self.write_reg(REG_CONFIG, CONFIG_RESET)?;
+        self.write_reg(REG_CONFIG, self.cfg.config_reg()?)?;
+        self.write_reg(REG_CALIBRATION, calibration)?;
+
+        // a missing pull-up or wrong address tends to read back garbage rather than fail the write
+        let readback = self.read_reg(REG_CALIBRATION)?;
+        if readback != calibration {
+            return Err(CuError::from(format!(
+                "INA219 0x{:02x}: calibration readback 0x{readback:04x}, expected 0x{calibration:04x}. Check I2C wiring.",
+                self.target_addr)));
+        }
+        Ok(())
+    }
+
+    fn write_reg(&mut self, reg: u8, value: u16) -> CuResult<()> {
+        let [hi, lo] = value.to_be_bytes();
+        self.bus.transaction(self.target_addr, |dev| dev.write(&[reg, hi, lo]))
+            .map_err(|e| CuError::from(format!("INA219: failed to write register 0x{reg:02x}: {e}")))
+    }
+
+    fn read_reg(&mut self, reg: u8) -> CuResult<u16> {
+        let mut buf = [0u8; 2];
+        // pointer write and read in one transaction, nobody else gets to move the pointer in between
+        self.bus.transaction(self.target_addr, |dev| dev.write(&[reg]).and_then(|_| dev.read(&mut buf)))
+            .map_err(|e| CuError::from(format!("INA219: failed to read register 0x{reg:02x}: {e}")))?;
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    pub fn read(&mut self) -> CuResult<Ina219Reading> {
+        // the bus was recovered since the calibration went in, the chip may have reset with it
+        if self.generation != self.bus.generation() {
+            eprintln!("INA219 0x{:02x}: recalibrating after an I2C bus recovery", self.target_addr);
+            self.calibrate()?;
+        }
+
+        let current_lsb_ma = self.cfg.current_lsb_ma();
+        let shunt = self.read_reg(REG_SHUNT_VOLTAGE)? as i16;
+        let bus = self.read_reg(REG_BUS_VOLTAGE)?;
+        let current = self.read_reg(REG_CURRENT)? as i16;
+        let power = self.read_reg(REG_POWER)?;
+
+        Ok(Ina219Reading {
+            power_mw: power as f64 * 20.0 * current_lsb_ma,
+            load_current_ma: current as f64 * current_lsb_ma,
+            shunt_voltage_mv: shunt as f64 * SHUNT_LSB_MV,
+            // bits 15..3, the low bits are conversion ready and overflow flags
+            bus_voltage_mv: (bus >> 3) as f64 * BUS_LSB_MV,
+        })
+    }
+}
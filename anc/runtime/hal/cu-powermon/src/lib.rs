@@ -1,100 +1,131 @@
-use std::panic;
-
 extern crate cu_bincode as bincode;
-use dumb_ina219::{units::{CurrentUnit, Gettable, ResistanceUnit}, *};
+
+mod ina219;
+pub use ina219::*;
+
 use cu29::prelude::*;
 use bincode::{Decode, Encode};
 use serde::{Deserialize, Serialize};
+use cu_health::{Availability, Criticality, required_key};
+use cu_i2c_bus::I2cBus;
+pub use cu_i2c_bus::I2cDeviceStats;
+use cu29::resources;
+use std::sync::Arc;
+
+// every monitor on the same bus shares one cu_i2c_bus::I2cBus, see the resources in taskdag.ron
+resources!({
+    bus => Shared<I2cBus>,
+});
 
 #[derive(Reflect)]
 #[reflect(no_field_bounds, from_reflect = false)]
 pub struct CuIna219 {
+    /// None when an optional monitor failed to come up
     #[reflect(ignore)]
     driver_instance: Option<Ina219>,
+    #[reflect(ignore)]
+    bus: Arc<I2cBus>,
+    /// Optional monitor whose last read failed, only logs the transitions
+    failing: bool,
     target_addr: u8,
+    #[reflect(ignore)]
+    criticality: Criticality,
 }
 
 #[derive(Debug, Clone, Copy, Encode, Decode, Default, PartialEq, Serialize, Deserialize)]
 #[derive(Reflect)]
 #[reflect(no_field_bounds, from_reflect = false)]
 pub struct Ina219Payload {
-    pub power: f64,
-    pub load_current: f64,
-    pub shunt_voltage: f64,
-    pub bus_voltage: f64,
+    pub power_mw: f64,
+    /// Signed, positive from VIN+ to VIN-
+    pub load_current_ma: f64,
+    pub shunt_voltage_mv: f64,
+    pub bus_voltage_mv: f64,
     pub target_addr: u8,
+    /// Unavailable: the readings above are zero and must not be used
+    pub availability: Availability,
+    /// Error counters of this address on the shared bus
+    pub i2c: I2cDeviceStats,
+}
+
+impl Ina219Payload {
+    pub fn available(&self) -> bool {
+        self.availability == Availability::Available
+    }
 }
 
 // Sensor is stateless
 impl Freezable for CuIna219 {}
 
+impl CuIna219 {
+    fn read(dev: &mut Ina219, target_addr: u8) -> CuResult<Ina219Payload> {
+        let reading = dev.read()?;
+        Ok(Ina219Payload {
+            power_mw            : reading.power_mw,
+            load_current_ma     : reading.load_current_ma,
+            shunt_voltage_mv    : reading.shunt_voltage_mv,
+            bus_voltage_mv      : reading.bus_voltage_mv,
+            target_addr,
+            availability        : Availability::Available,
+            i2c                 : I2cDeviceStats::default(),
+        })
+    }
+
+    fn unavailable(&self) -> Ina219Payload {
+        Ina219Payload {
+            target_addr: self.target_addr,
+            availability: Availability::Unavailable,
+            i2c: self.bus.stats(self.target_addr),
+            ..Default::default()
+        }
+    }
+}
+
 impl CuSrcTask for CuIna219 {
     type Output<'m> = output_msg!(Ina219Payload);
-    type Resources<'r> = ();
+    type Resources<'r> = Resources;
 
-    fn new(config: Option<&ComponentConfig>, _resources: Self::Resources<'_>) -> CuResult<Self>
+    fn new(config: Option<&ComponentConfig>, resources: Self::Resources<'_>) -> CuResult<Self>
     where Self: Sized
     {
-        let ComponentConfig(kv) =
-            config.ok_or("No ComponentConfig specified for GPIO in RON")?;
-
-        let target_addr: u8 = kv
-            .get("target_addr")
-            .expect("I2C target address for an INA219 not set in RON config")
-            .clone()
-            .into();
-
-        let driver_instance = Ina219::new(
-            ResistanceUnit::milliohms(100.0),
-            CurrentUnit::milliamps(1000.0),
-            target_addr).ok();
-
-        // Do not forget to call init() !
-        match driver_instance {
-            None => panic!("INA219 driver instantiation error"),
-            Some(mut driver_instance) => {
-                match driver_instance.init().ok() {
-                    Some(_) => return Ok(Self { driver_instance: Some(driver_instance), target_addr }),
-                    None => panic!("INA219 init error (instantiation successful). Check I2C wiring.")
-                }
-            }
-        }
+        let config = config.ok_or("No ComponentConfig specified for INA219 in RON")?;
+
+        let target_addr: u8 = required_key(config, "INA219", "target_addr")?;
+        let criticality = Criticality::from_config(config)?;
+        let cfg = Ina219Cfg::from_config(config)?;
+        let bus = Arc::clone(&resources.bus.0);
+
+        let driver_instance = criticality.degrade(&format!("INA219 0x{target_addr:02x} on {}", bus.path()),
+            Ina219::new(Arc::clone(&bus), target_addr, &cfg))?;
+
+        Ok(Self { driver_instance, bus, failing: false, target_addr, criticality })
     }
 
     fn process(&mut self, _clock: &RobotClock, msg: &mut Self::Output<'_>) -> CuResult<()> {
-        let dev = &mut self.driver_instance;
-
-        let power_reading = dev.as_mut().unwrap().power().map_err(|_| {
-            CuError::from(format!("failed to get power reading"))
-        })?;
-
-        let current_reading = dev.as_mut().unwrap().load_current().map_err(|_| {
-            CuError::from(format!("failed to get current reading"))
-        })?;
-
-        let shunt_voltage_reading = dev.as_mut().unwrap().shunt_voltage().map_err(|_| {
-            CuError::from(format!("failed to get shunt voltage reading"))
-        })?;
-
-        let bus_voltage_reading = dev.as_mut().unwrap().bus_voltage().map_err(|_| {
-            CuError::from(format!("failed to get bus voltage reading"))
-        })?;
-
-        let power = power_reading.get_val()*1000.0;
-        let current = current_reading.get_val()*1000.0;
-        let shunt_voltage = shunt_voltage_reading.get_val()*1000.0;
-        let bus_voltage = bus_voltage_reading.get_val();
-
-        msg.set_payload(
-            Ina219Payload {
-                power           : power,
-                load_current    : current,
-                shunt_voltage   : shunt_voltage,
-                bus_voltage     : bus_voltage,
-                target_addr     : self.target_addr
-            }
-        );
-        // msg.metadata.set_status(format!("{power:.2}mW {current:.2}mA {bus_voltage:.2}V"));
+        let Some(dev) = self.driver_instance.as_mut() else {
+            msg.set_payload(self.unavailable());
+            return Ok(());
+        };
+
+        // the bus has already retried, whatever gets here failed every attempt
+        match (Self::read(dev, self.target_addr), self.criticality) {
+            (Ok(payload), _) => {
+                if self.failing {
+                    eprintln!("INA219 0x{:02x}: answering again", self.target_addr);
+                    self.failing = false;
+                }
+                msg.set_payload(Ina219Payload { i2c: self.bus.stats(self.target_addr), ..payload });
+            },
+            (Err(e), Criticality::Required) => return Err(e),
+            // unavailable until it answers again, the bus may recover and the driver recalibrates then
+            (Err(e), Criticality::Optional) => {
+                if !self.failing {
+                    eprintln!("INA219 0x{:02x}: unavailable, running degraded: {e}", self.target_addr);
+                    self.failing = true;
+                }
+                msg.set_payload(self.unavailable());
+            },
+        }
         Ok(())
     }
 }
@@ -1,9 +1,13 @@
 extern crate cu_bincode as bincode;
+use std::sync::{Arc, Mutex};
 use dumb_sysfs_pwm::{Pwm, PwmBuilder};
 use gpio_cdev::*;
 use cu29::prelude::*;
+use cu29::resource::{BundleContext, ResourceBundle, ResourceManager};
+use cu29::bundle_resources;
 use bincode::{Decode, Encode};
 use serde::{Deserialize, Serialize};
+use cu_health::required_key;
 
 pub struct DirectionPair(u8, u8);
 // Just reassign these if the actual hardware connections happen to be flipped
@@ -42,6 +46,52 @@ pub enum WheelDirection {
     Stop
 }
 
+/// Directions the L298N was last driven with. Cu-irencoder signs the slot readings with them, it
+/// feeds the arbitrator and the speed loops upstream of Propulsion so it can't take the final
+/// PropulsionPayload as an input without a cycle in the task graph.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Encode, Decode, Serialize, Deserialize)]
+#[derive(Reflect)]
+pub struct AppliedDirections {
+    pub left: WheelDirection,
+    pub right: WheelDirection,
+}
+
+/// Hands AppliedDirections from Propulsion, at the end of a cycle, to AppliedDirectionsSrc at the
+/// start of the next one
+#[derive(Debug, Default)]
+pub struct DirectionFeedback(Mutex<AppliedDirections>);
+
+impl DirectionFeedback {
+    fn set(&self, directions: AppliedDirections) {
+        if let Ok(mut applied) = self.0.lock() {
+            *applied = directions;
+        }
+    }
+
+    fn get(&self) -> AppliedDirections {
+        self.0.lock().map_or(AppliedDirections::default(), |applied| *applied)
+    }
+}
+
+/// RON:
+///   resources: [ (id: "propulsion-feedback", provider: "cu_propulsion::DirectionFeedbackBundle") ]
+/// and on propulsion and applied-directions: resources: { "feedback": "propulsion-feedback.directions" }
+pub struct DirectionFeedbackBundle;
+bundle_resources!(DirectionFeedbackBundle: Directions);
+
+impl ResourceBundle for DirectionFeedbackBundle {
+    fn build(bundle: BundleContext<Self>, _config: Option<&ComponentConfig>, manager: &mut ResourceManager)
+    -> CuResult<()>
+    {
+        manager.add_shared(bundle.key(DirectionFeedbackBundleId::Directions), Arc::new(DirectionFeedback::default()))?;
+        Ok(())
+    }
+}
+
+resources!({
+    feedback => Shared<DirectionFeedback>,
+});
+
 #[derive(Debug, Clone, Copy, Default, PartialEq, Encode, Decode, Serialize, Deserialize)]
 #[derive(Reflect)]
 pub struct WheelState {
@@ -98,7 +148,9 @@ pub struct Propulsion {
     pin_assignments: PropulsionPinAssignments,
     last_lmtr_duty_cycle: Option<f32>,
     last_rmtr_duty_cycle: Option<f32>,
-    period_ns: u32
+    period_ns: u32,
+    #[reflect(ignore)]
+    feedback: Arc<DirectionFeedback>,
 }
 
 impl Freezable for Propulsion {
@@ -117,58 +169,36 @@ impl Freezable for Propulsion {
 
 impl CuSinkTask for Propulsion {
     type Input<'m> = input_msg!(PropulsionPayload);
-    type Resources<'r> = ();
+    type Resources<'r> = Resources;
 
-    fn new(config: Option<&ComponentConfig>, _resources: Self::Resources<'_>) -> Result<Self, CuError>
+    fn new(config: Option<&ComponentConfig>, resources: Self::Resources<'_>) -> Result<Self, CuError>
     where Self: Sized
     {
-        let ComponentConfig(kv) =
-            config.ok_or("No ComponentConfig specified for GPIO in RON")?;
+        let config = config.ok_or("No ComponentConfig specified for GPIO in RON")?;
+        let ComponentConfig(kv) = config;
 
         let period_ns: u32 = kv
             .get("period_ns")
             .map_or(20_000_000, |p: &config::Value| -> u32 {p.clone().into()})
             .into();
 
-        let l298n_en_a_pin_offset: u32 = kv
-            .get("l298n_en_a")
-            .expect("l298n_en_a for Propulsion not set in RON config. Make sure you're specifying the PWM channel offset instead of its GPIO number.")
-            .clone()
-            .into();
-
-        let l298n_en_b_pin_offset: u32 = kv
-            .get("l298n_en_b")
-            .expect("l298n_en_b for Propulsion not set in RON config. Make sure you're specifying the PWM channel offset instead of its GPIO number.")
-            .clone()
-            .into();
-
-        let l298n_in_1_pin_offset: u32 = kv
-            .get("l298n_in_1")
-            .expect("l298n_in_1 for Propulsion not set in RON config")
-            .clone()
-            .into();
-
-        let l298n_in_2_pin_offset: u32 = kv
-            .get("l298n_in_2")
-            .expect("l298n_in_2 for Propulsion not set in RON config")
-            .clone()
-            .into();
-
-        let l298n_in_3_pin_offset: u32 = kv
-            .get("l298n_in_3")
-            .expect("l298n_in_3 for Propulsion not set in RON config")
-            .clone()
-            .into();
-
-        let l298n_in_4_pin_offset: u32 = kv
-            .get("l298n_in_4")
-            .expect("l298n_in_4 for Propulsion not set in RON config")
-            .clone()
-            .into();
-
-        let lmtr_en_a_instance = PwmBuilder::new(0, l298n_en_a_pin_offset, 20_000_000).build().unwrap();
-        let rmtr_en_b_instance = PwmBuilder::new(0, l298n_en_b_pin_offset, 20_000_000).build().unwrap();
-        let mut gpio = Chip::new("/dev/gpiochip4").unwrap();
+        let l298n_en_a_pin_offset: u32 = required_key(config, "Propulsion", "l298n_en_a")
+            .map_err(|e| CuError::from(format!("{e}. Make sure you're specifying the PWM channel offset instead of its GPIO number.")))?;
+        let l298n_en_b_pin_offset: u32 = required_key(config, "Propulsion", "l298n_en_b")
+            .map_err(|e| CuError::from(format!("{e}. Make sure you're specifying the PWM channel offset instead of its GPIO number.")))?;
+        let l298n_in_1_pin_offset: u32 = required_key(config, "Propulsion", "l298n_in_1")?;
+        let l298n_in_2_pin_offset: u32 = required_key(config, "Propulsion", "l298n_in_2")?;
+        let l298n_in_3_pin_offset: u32 = required_key(config, "Propulsion", "l298n_in_3")?;
+        let l298n_in_4_pin_offset: u32 = required_key(config, "Propulsion", "l298n_in_4")?;
+
+        let export_pwm = |channel: u32| -> CuResult<Pwm> {
+            PwmBuilder::new(0, channel, 20_000_000).build()
+                .map_err(|e| CuError::from(format!("Propulsion: failed to export PWM channel {channel}: {e}")))
+        };
+        let lmtr_en_a_instance = export_pwm(l298n_en_a_pin_offset)?;
+        let rmtr_en_b_instance = export_pwm(l298n_en_b_pin_offset)?;
+        let mut gpio = Chip::new("/dev/gpiochip4")
+            .map_err(|e| CuError::from(format!("Propulsion: failed to open /dev/gpiochip4: {e}")))?;
 
         let pin_assignments = PropulsionPinAssignments {
             l298n_en_a_pin: l298n_en_a_pin_offset,
@@ -179,18 +209,15 @@ impl CuSinkTask for Propulsion {
             l298n_in_4_pin: l298n_in_4_pin_offset
         };
 
-        let in_1_line = gpio.get_line(l298n_in_1_pin_offset).unwrap();
-        let in_1_line = in_1_line.request(LineRequestFlags::OUTPUT, 0, "in-1-left-motor").unwrap();
-
-        let in_2_line = gpio.get_line(l298n_in_2_pin_offset).unwrap();
-        let in_2_line = in_2_line.request(LineRequestFlags::OUTPUT, 0, "in-2-left-motor").unwrap();
-
-        let in_3_line = gpio.get_line(l298n_in_3_pin_offset).unwrap();
-        let in_3_line = in_3_line.request(LineRequestFlags::OUTPUT, 0, "in-3-right-motor").unwrap();
-
-        let in_4_line = gpio.get_line(l298n_in_4_pin_offset).unwrap();
-        let in_4_line = in_4_line.request(LineRequestFlags::OUTPUT, 0, "in-4-right-motor").unwrap();
-
+        let mut request_output = |offset: u32, consumer: &str| -> CuResult<LineHandle> {
+            gpio.get_line(offset)
+                .and_then(|l| l.request(LineRequestFlags::OUTPUT, 0, consumer))
+                .map_err(|e| CuError::from(format!("Propulsion: failed to request {consumer} line {offset}: {e}")))
+        };
+        let in_1_line = request_output(l298n_in_1_pin_offset, "in-1-left-motor")?;
+        let in_2_line = request_output(l298n_in_2_pin_offset, "in-2-left-motor")?;
+        let in_3_line = request_output(l298n_in_3_pin_offset, "in-3-right-motor")?;
+        let in_4_line = request_output(l298n_in_4_pin_offset, "in-4-right-motor")?;
 
         let pin_controller_instances = PropulsionControllerInstances {
             gpio_inst: gpio,
@@ -211,7 +238,8 @@ impl CuSinkTask for Propulsion {
             pin_assignments: pin_assignments,
             last_lmtr_duty_cycle: None,
             last_rmtr_duty_cycle: None,
-            period_ns
+            period_ns,
+            feedback: Arc::clone(&resources.feedback.0),
         })
     }
 
@@ -219,8 +247,10 @@ impl CuSinkTask for Propulsion {
         let en_a_hdl = &mut self.pin_controller_instances.lmtr_en_a;
         let en_b_hdl = &mut self.pin_controller_instances.rmtr_en_b;
 
-        en_a_hdl.set_period_ns(self.period_ns).unwrap();
-        en_b_hdl.set_period_ns(self.period_ns).unwrap();
+        en_a_hdl.set_period_ns(self.period_ns)
+            .map_err(|e| CuError::from(format!("Failed to set propulsion Pwm period: {e}")))?;
+        en_b_hdl.set_period_ns(self.period_ns)
+            .map_err(|e| CuError::from(format!("Failed to set propulsion Pwm period: {e}")))?;
 
         match en_a_hdl.set_duty_cycle(0.0) {
             Ok(_) => (),
@@ -305,6 +335,10 @@ impl CuSinkTask for Propulsion {
             let in_3_line = &dir_hdl.in_3_pin;
             let in_4_line = &dir_hdl.in_4_pin;
 
+            self.left_wheel.direction = payload.left_direction;
+            self.right_wheel.direction = payload.right_direction;
+            self.feedback.set(AppliedDirections { left: payload.left_direction, right: payload.right_direction });
+
             match payload.left_direction {
                 WheelDirection::Forward => {
                     let DirectionPair(in_1_val, in_2_val) = FORWARD;
@@ -390,6 +424,7 @@ impl CuSinkTask for Propulsion {
         let line_3_ret = in_3_line.set_value(0).ok();
         let line_4_ret = in_4_line.set_value(0).ok();
         let mut stop_success: bool = false;
+        self.feedback.set(AppliedDirections::default());
 
         if
             let Some(_) = line_1_ret &&
@@ -413,6 +448,34 @@ impl CuSinkTask for Propulsion {
     }
 }
 
+/// Puts the directions Propulsion applied last cycle into the graph, logged like any other source
+/// so a replay sees the same ones. Always exactly one cycle late: it has no inputs and Propulsion is
+/// downstream of everything that reads it.
+#[derive(Reflect)]
+#[reflect(no_field_bounds, from_reflect = false)]
+pub struct AppliedDirectionsSrc {
+    #[reflect(ignore)]
+    feedback: Arc<DirectionFeedback>,
+}
+
+impl Freezable for AppliedDirectionsSrc {}
+
+impl CuSrcTask for AppliedDirectionsSrc {
+    type Output<'m> = output_msg!(AppliedDirections);
+    type Resources<'r> = Resources;
+
+    fn new(_config: Option<&ComponentConfig>, resources: Self::Resources<'_>) -> CuResult<Self>
+    where Self: Sized
+    {
+        Ok(Self { feedback: Arc::clone(&resources.feedback.0) })
+    }
+
+    fn process(&mut self, _clock: &RobotClock, output: &mut Self::Output<'_>) -> CuResult<()> {
+        output.set_payload(self.feedback.get());
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     // use super::*;
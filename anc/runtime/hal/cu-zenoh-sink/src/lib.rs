@@ -22,6 +22,9 @@ where
 pub struct ZCfg {
     config: Config,
     topic: String,
+    /// Don't put anything on the cycles the input has no payload. Off by default, the message
+    /// (with a null payload) goes out every cycle.
+    skip_empty: bool,
 }
 
 pub struct ZCtx {
@@ -54,13 +57,18 @@ where
 
         let topic = config.get::<String>("topic")
             .map_err(|e| CuError::from(format!("{e}")))?
-            .unwrap();
+            .ok_or(CuError::from("ZSink: topic not set in RON config"))?;
+
+        let skip_empty = config.get::<bool>("skip_empty")
+            .map_err(|e| CuError::from(format!("{e}")))?
+            .unwrap_or(false);
 
         Ok(Self {
             _marker: Default::default(),
             config: ZCfg {
                 config: session_config,
                 topic,
+                skip_empty,
             },
             ctx: None,
         })
@@ -91,6 +99,10 @@ where
             .as_mut()
             .ok_or_else(|| CuError::from("ZSink: Context not found"))?;
 
+        if self.config.skip_empty && input.payload().is_none() {
+            return Ok(());
+        }
+
         let encoded = match to_vec_named(&input) {
             Ok(ret) => ret,
             Err(_) => return Err(CuError::from(format!("failed to encode")))
@@ -62,7 +62,7 @@ where
 
         let topic = config.get::<String>("topic")
             .map_err(|e| CuError::from(format!("{e}")))?
-            .unwrap();
+            .ok_or(CuError::from("ZSrc: topic not set in RON config"))?;
 
         let staleness_timeout_ms = config.get::<f64>("staleness_timeout_ms")
             .map_err(|e| CuError::from(format!("{e}")))?
@@ -1,11 +1,15 @@
 extern crate cu_bincode as bincode;
 use cu_pid::GenericPIDTask;
+use relay_autotuner::RelayAutotuneTask;
 use bincode::{Encode, Decode};
 use serde::{Serialize, Deserialize};
 use cu29::prelude::*;
 
 pub type DualMtrCtrlr = GenericPIDTask<DualMtrCtrlrPayload>;
 
+// Drop-in replacement for the above while tuning, see docs/autotune.md
+pub type DualMtrAutotuner = RelayAutotuneTask<DualMtrCtrlrPayload>;
+
 #[derive(Debug, Clone, Copy, Default, Encode, Decode, PartialEq, Serialize, Deserialize)]
 #[derive(Reflect)]
 #[reflect(no_field_bounds, from_reflect = false)]
@@ -1,5 +1,6 @@
 extern crate cu_bincode as bincode;
 use cu_pid::GenericPIDTask;
+use relay_autotuner::RelayAutotuneTask;
 use bincode::{Encode, Decode};
 use serde::{Serialize, Deserialize};
 use cu29::prelude::*;
@@ -7,6 +8,10 @@ use cu29::prelude::*;
 pub type LmtrSpeedCtrlr = GenericPIDTask<LmtrSpeedErrPayload>;
 pub type RmtrSpeedCtrlr = GenericPIDTask<RmtrSpeedErrPayload>;
 
+// Drop-in replacements for the above while tuning, see docs/autotune.md
+pub type LmtrSpeedAutotuner = RelayAutotuneTask<LmtrSpeedErrPayload>;
+pub type RmtrSpeedAutotuner = RelayAutotuneTask<RmtrSpeedErrPayload>;
+
 #[derive(Debug, Clone, Copy, Default, Encode, Decode, PartialEq, Serialize, Deserialize)]
 #[derive(Reflect)]
 #[reflect(no_field_bounds, from_reflect = false)]
@@ -32,3 +37,33 @@ impl From<&RmtrSpeedErrPayload> for f32 {
         payload.error
     }
 }
+
+/// How the speed loops get their setpoints, set with "setpoint_mode" in the RON of
+/// speed-err-adapter and speed-correction-summer. They should agree.
+/// Velocity: each wheel tracks its own rpm setpoint from WheelVelocitySetpointPayload.
+/// Ratio: legacy mode, wheels track the left/right command ratio against the summed actual speed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SetpointMode {
+    #[default]
+    Velocity,
+    Ratio,
+}
+
+impl SetpointMode {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "velocity" => Ok(SetpointMode::Velocity),
+            "ratio" => Ok(SetpointMode::Ratio),
+            _ => Err(format!("Invalid setpoint_mode value: \"{s}\". Valid values: \"velocity\", \"ratio\"")),
+        }
+    }
+}
+
+/// Per-wheel velocity setpoints in rpm, emitted by the arbitrator. Negative means reverse.
+#[derive(Debug, Clone, Copy, Default, Encode, Decode, PartialEq, Serialize, Deserialize)]
+#[derive(Reflect)]
+#[reflect(no_field_bounds, from_reflect = false)]
+pub struct WheelVelocitySetpointPayload {
+    pub lmtr_rpm: f32,
+    pub rmtr_rpm: f32,
+}
@@ -0,0 +1,47 @@
+use cu29::prelude::*;
+use cu29_helpers::basic_copper_setup;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use cu_propulsion::PropulsionPayload;
+use cu_powermon::Ina219Payload;
+use cu_i2c_bus::*;
+use cu_irencoder::*;
+use motor_characterizer::*;
+
+#[copper_runtime(config = "characterize.ron", sim_mode = false)]
+struct PalanukCharacterize {}
+
+#[allow(clippy::identity_op)]
+const SLAB_SIZE: Option<usize> = Some(1 * 1024 * 1024 * 1024);
+
+fn main() {
+    let logger_path = "logs/palanuk-characterize.copper";
+    if let Some(parent) = Path::new(logger_path).parent() {
+        if !parent.exists() {
+            fs::create_dir_all(parent).expect("Failed to create logs directory");
+        }
+    }
+
+    let copper_ctx = basic_copper_setup(
+        &PathBuf::from(logger_path),
+        SLAB_SIZE,
+        true,
+        None
+    )
+    .expect("Failed to setup logger.");
+    debug!("Logger created at {}.", path = logger_path);
+
+    let clock = copper_ctx.clock;
+
+    let mut application = PalanukCharacterize::new(
+        clock.clone(),
+        copper_ctx.unified_logger.clone(),
+        None
+    ).expect("Failed to create characterization runtime.");
+
+    // motor-characterizer stops the motors once the calibration file is written, Ctrl-C to exit
+    application.run().expect("Failed to run characterization.");
+
+    debug!("End of characterization: final clock: {}.", clock.now());
+}
@@ -8,9 +8,12 @@ use std::path::{Path, PathBuf};
 
 use cu_propulsion::{PropulsionPayload, WheelDirection};
 use cu_cam_pan::{CameraPanningPayload, PositionCommand};
-use cu_hcsr04::{HcSr04Payload};
+use cu_hcsr04::{HcSr04Payload, HcSr04ArrayPayload};
 use cu_powermon::{Ina219Payload};
+use cu_i2c_bus::*;
 use ec_pub::*;
+use ec_energy::*;
+use battery_model::*;
 use zsrc_merger::*;
 use opencv_iox2::*;
 use propulsion_adapter::*;
@@ -27,6 +30,8 @@ use speed_err_adapter::*;
 use speed_correction_summer::*;
 use speed_ctrlrs::*;
 use itp_merger::*;
+use relay_autotuner::*;
+use ultrasonic_fusion::*;
 
 use core_affinity::*;
 use libc::*;
@@ -40,52 +45,90 @@ pub mod bstn_subs {
     pub type BstnOpenLoopDriveStateSrc = ZSrc<zsrc_merger::BstnOpenLoopDriveState>;
     pub type BstnOpenLoopForcepanSrc   = ZSrc<zsrc_merger::BstnOpenLoopForcepan>;
     pub type BstnOpenLoopSteerCmdSrc   = ZSrc<zsrc_merger::BstnOpenLoopSteerCmd>;
+    pub type BstnStallResetSrc         = ZSrc<speed_correction_summer::BstnStallReset>;
+    pub type BstnEStopResetSrc         = ZSrc<arbitrator::BstnEStopReset>;
+    pub type BstnEStopSrc              = ZSrc<arbitrator::BstnEStop>;
+    pub type BstnEnergyResetSrc        = ZSrc<ec_energy::BstnEnergyReset>;
 }
 
 pub mod itp_subs {
     use cu_zenoh_src::ZSrc;
     pub type ItpAccelerateCmdSrc = ZSrc<itp_merger::ItpAccelerateCmd>;
+    pub type ItpEStopSrc         = ZSrc<arbitrator::ItpEStop>;
+    pub type ItpPanScanCmdSrc    = ZSrc<itp_merger::ItpPanScanCmd>;
 }
 
 pub mod anc_pubs {
     use cu_zenoh_sink::ZSink;
-    use anc_pub::{ObstacleDetected, Distance, LmtrSpeed, RmtrSpeed};
+    use anc_pub::{ObstacleDetected, Distance, LmtrSpeed, RmtrSpeed, SpeedCap, EStopStatus, ObstaclePolicyStatus};
 
     pub type ObstacleDetectedSink = ZSink<anc_pub::ObstacleDetected>;
     pub type DistanceSink         = ZSink<anc_pub::Distance>;
     pub type LmtrSpeedSink        = ZSink<anc_pub::LmtrSpeed>;
     pub type RmtrSpeedSink        = ZSink<anc_pub::RmtrSpeed>;
+    pub type SpeedCapSink         = ZSink<anc_pub::SpeedCap>;
+    pub type EStopStatusSink      = ZSink<anc_pub::EStopStatus>;
+    pub type ObstaclePolicySink   = ZSink<anc_pub::ObstaclePolicyStatus>;
+    pub type StallFaultSink       = ZSink<speed_correction_summer::StallFaultPayload>;
+    pub type OvercurrentFaultSink = ZSink<speed_correction_summer::OvercurrentFaultPayload>;
+    pub type PanStateSink         = ZSink<cu_cam_pan::PanStatePayload>;
+}
+
+pub mod autotune_pubs {
+    use cu_zenoh_sink::ZSink;
+    use relay_autotuner::AutotuneResultPayload;
+
+    pub type AutotuneResultSink = ZSink<relay_autotuner::AutotuneResultPayload>;
 }
 
 
 pub mod ec_5vrail_pubs {
     use cu_zenoh_sink::ZSink;
     use ec_pub::{PowerMwatts, LoadCurrentMamps, BusVoltageMvolts, ShuntVoltageMvolts};
+    use ec_energy::{EnergyBootPayload, EnergyRunPayload};
 
     pub type PowerMwattsSink        = ZSink<ec_pub::PowerMwatts>;
     pub type LoadCurrentMampsSink   = ZSink<ec_pub::LoadCurrentMamps>;
     pub type BusVoltageMvoltsSink   = ZSink<ec_pub::BusVoltageMvolts>;
     pub type ShuntVoltageMvoltsSink = ZSink<ec_pub::ShuntVoltageMvolts>;
+    pub type I2cStatsSink           = ZSink<cu_powermon::I2cDeviceStats>;
+    pub type EnergyBootSink         = ZSink<ec_energy::EnergyBootPayload>;
+    pub type EnergyRunSink          = ZSink<ec_energy::EnergyRunPayload>;
 }
 
 pub mod ec_lmtr_pubs {
     use cu_zenoh_sink::ZSink;
     use ec_pub::{PowerMwatts, LoadCurrentMamps, BusVoltageMvolts, ShuntVoltageMvolts};
+    use ec_energy::{EnergyBootPayload, EnergyRunPayload};
 
     pub type PowerMwattsSink        = ZSink<ec_pub::PowerMwatts>;
     pub type LoadCurrentMampsSink   = ZSink<ec_pub::LoadCurrentMamps>;
     pub type BusVoltageMvoltsSink   = ZSink<ec_pub::BusVoltageMvolts>;
     pub type ShuntVoltageMvoltsSink = ZSink<ec_pub::ShuntVoltageMvolts>;
+    pub type I2cStatsSink           = ZSink<cu_powermon::I2cDeviceStats>;
+    pub type EnergyBootSink         = ZSink<ec_energy::EnergyBootPayload>;
+    pub type EnergyRunSink          = ZSink<ec_energy::EnergyRunPayload>;
 }
 
 pub mod ec_rmtr_pubs {
     use cu_zenoh_sink::ZSink;
     use ec_pub::{PowerMwatts, LoadCurrentMamps, BusVoltageMvolts, ShuntVoltageMvolts};
+    use ec_energy::{EnergyBootPayload, EnergyRunPayload};
 
     pub type PowerMwattsSink        = ZSink<ec_pub::PowerMwatts>;
     pub type LoadCurrentMampsSink   = ZSink<ec_pub::LoadCurrentMamps>;
     pub type BusVoltageMvoltsSink   = ZSink<ec_pub::BusVoltageMvolts>;
     pub type ShuntVoltageMvoltsSink = ZSink<ec_pub::ShuntVoltageMvolts>;
+    pub type I2cStatsSink           = ZSink<cu_powermon::I2cDeviceStats>;
+    pub type EnergyBootSink         = ZSink<ec_energy::EnergyBootPayload>;
+    pub type EnergyRunSink          = ZSink<ec_energy::EnergyRunPayload>;
+}
+
+pub mod ec_battery_pubs {
+    use cu_zenoh_sink::ZSink;
+    use battery_model::BatteryPayload;
+
+    pub type BatterySink = ZSink<battery_model::BatteryPayload>;
 }
 
 #[copper_runtime(config = "taskdag.ron", sim_mode = false)]